@@ -0,0 +1,62 @@
+//! Compares capturing the displays one after another with capturing them all at once.
+//!
+//! Run with `cargo bench --bench capture_all`, needs a running display server.
+
+use captis::*;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 60;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let capturer = init_capturer()?;
+
+    println!("Found Displays: {:?}", capturer.displays());
+
+    let (sequential, sequential_skew) = measure(|| {
+        let mut captured_at = vec![];
+
+        for i in 0..capturer.displays().len() {
+            captured_at.push(Instant::now());
+            capturer.capture(i)?;
+        }
+
+        Ok(captured_at
+            .last()
+            .zip(captured_at.first())
+            .map(|(last, first)| *last - *first))
+    })?;
+
+    let (at_once, _) = measure(|| {
+        capturer.capture_all_at_once()?;
+        Ok(None)
+    })?;
+
+    println!(
+        "capture_all: {:?} per iteration, {:?} between the first and the last display",
+        sequential / ITERATIONS,
+        sequential_skew / ITERATIONS
+    );
+    println!(
+        "capture_all_at_once: {:?} per iteration",
+        at_once / ITERATIONS
+    );
+
+    Ok(())
+}
+
+/// Runs `f` a few times, returning the total time it took and the total skew it reported.
+fn measure<F>(mut f: F) -> Result<(Duration, Duration), Error>
+where
+    F: FnMut() -> Result<Option<Duration>, Error>,
+{
+    f()?;
+
+    let mut skew = Duration::ZERO;
+    let now = Instant::now();
+
+    for _ in 0..ITERATIONS {
+        skew += f()?.unwrap_or_default();
+    }
+
+    Ok((now.elapsed(), skew))
+}
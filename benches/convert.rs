@@ -0,0 +1,74 @@
+//! Compares the BGRX to RGB conversion picked at runtime with the scalar one on a 4K frame.
+//!
+//! Run with `cargo bench --bench convert`.
+
+use captis::{
+    convert::{bgrx_row_to_rgb, bgrx_row_to_rgb_scalar, bgrx_to_rgb, bgrx_to_rgb_scaled},
+    Scale, ScaleFilter,
+};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+const WIDTH: usize = 3840;
+const HEIGHT: usize = 2160;
+
+fn frame() -> Vec<u8> {
+    // Any pattern works, it only has to be the same for every conversion.
+    (0..WIDTH * HEIGHT * 4)
+        .map(|i| (i * 7 + i / 5) as u8)
+        .collect()
+}
+
+fn convert(source: &[u8], target: &mut [u8], convert_row: fn(&[u8], &mut [u8])) {
+    for (source, target) in source
+        .chunks_exact(WIDTH * 4)
+        .zip(target.chunks_exact_mut(WIDTH * 3))
+    {
+        convert_row(source, target);
+    }
+}
+
+fn rows(c: &mut Criterion) {
+    let source = frame();
+    let mut target = vec![0; WIDTH * HEIGHT * 3];
+
+    let mut group = c.benchmark_group("rows");
+    group.throughput(Throughput::Bytes(source.len() as u64));
+
+    group.bench_function("bgrx_row_to_rgb", |b| {
+        b.iter(|| convert(&source, &mut target, bgrx_row_to_rgb))
+    });
+    group.bench_function("bgrx_row_to_rgb_scalar", |b| {
+        b.iter(|| convert(&source, &mut target, bgrx_row_to_rgb_scalar))
+    });
+
+    group.finish();
+}
+
+fn images(c: &mut Criterion) {
+    let source = frame();
+    let (width, height) = (WIDTH as u32, HEIGHT as u32);
+
+    let mut group = c.benchmark_group("images");
+    group.throughput(Throughput::Bytes(source.len() as u64));
+
+    group.bench_function("bgrx_to_rgb", |b| {
+        b.iter(|| bgrx_to_rgb(&source, WIDTH * 4, width, height))
+    });
+    group.bench_function("bgrx_to_rgb_scaled/half/bilinear", |b| {
+        b.iter(|| {
+            bgrx_to_rgb_scaled(
+                &source,
+                WIDTH * 4,
+                width,
+                height,
+                Scale::Factor(0.5),
+                ScaleFilter::Bilinear,
+            )
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, rows, images);
+criterion_main!(benches);
@@ -0,0 +1,15 @@
+use std::{env, path::PathBuf};
+
+fn main() {
+    let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    // Only generated into `OUT_DIR`, building never touches the source tree. The copy in
+    // `include` is checked in and compared against this one by the tests.
+    cbindgen::generate(&crate_dir)
+        .expect("Couldn't Generate Header")
+        .write_to_file(out_dir.join("captis.h"));
+}
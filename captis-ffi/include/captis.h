@@ -0,0 +1,140 @@
+/*
+ * C bindings for captis, generated by cbindgen from captis-ffi so don't edit it by hand.
+ *
+ * Ownership:
+ *
+ * - A CaptisCapturer is created by captis_init or captis_init_mock and owned by the caller
+ *   until it's given back to captis_free, exactly once.
+ * - Every function taking a CaptisCapturer locks it for the duration of the call, so one
+ *   handle can be shared between threads. Calls on the same handle run one at a time.
+ * - Buffers are always owned by the caller, the library only writes into them.
+ * - The strings returned by captis_status_string are static. The one returned by
+ *   captis_last_error belongs to the calling thread and stays valid until the next call
+ *   that fails on that thread.
+ */
+
+#ifndef CAPTIS_H
+#define CAPTIS_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// What every fallible function returns, anything but `CAPTIS_STATUS_OK` leaves a message
+// behind for `captis_last_error`.
+typedef enum CaptisStatus {
+  CAPTIS_STATUS_OK = 0,
+  CAPTIS_STATUS_NULL_POINTER,
+  CAPTIS_STATUS_INVALID_DISPLAY,
+  CAPTIS_STATUS_BUFFER_TOO_SMALL,
+  CAPTIS_STATUS_INIT_FAILED,
+  CAPTIS_STATUS_CAPTURE_FAILED,
+  CAPTIS_STATUS_PANICKED,
+} CaptisStatus;
+
+// A capturer shared with C, the header explains how to handle it.
+typedef struct CaptisCapturer CaptisCapturer;
+
+// A display, in the coordinates of the platform.
+typedef struct CaptisDisplay {
+  int32_t x;
+  int32_t y;
+  uint32_t width;
+  uint32_t height;
+  // Physical pixels per logical pixel.
+  double scale_factor;
+} CaptisDisplay;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Connects to the screen and writes the new capturer into `out`.
+//
+// # Safety
+//
+// `out` has to be null or valid for writes.
+enum CaptisStatus captis_init(struct CaptisCapturer **out);
+
+// Creates a capturer without a screen that has the `count` displays in `displays` and
+// captures a gradient on each of them, for testing. A `scale_factor` that isn't positive is
+// taken as 1, and displays that don't fit the coordinates of the platform, 16 bits on X11,
+// are `CAPTIS_STATUS_INVALID_DISPLAY`.
+//
+// # Safety
+//
+// `displays` has to point to `count` displays unless `count` is 0, and `out` has to be null
+// or valid for writes.
+enum CaptisStatus captis_init_mock(const struct CaptisDisplay *displays,
+                                   size_t count,
+                                   size_t primary,
+                                   struct CaptisCapturer **out);
+
+// Releases a capturer, null is ignored.
+//
+// # Safety
+//
+// `capturer` has to come from `captis_init` or `captis_init_mock`, and can't be used
+// anymore afterwards.
+void captis_free(struct CaptisCapturer *capturer);
+
+// Returns how many displays there are, 0 if `capturer` is null.
+//
+// # Safety
+//
+// `capturer` has to be null or a live capturer.
+size_t captis_display_count(const struct CaptisCapturer *capturer);
+
+// Writes the display at `index` into `out`.
+//
+// # Safety
+//
+// `capturer` has to be null or a live capturer, and `out` null or valid for writes.
+enum CaptisStatus captis_display(const struct CaptisCapturer *capturer,
+                                 size_t index,
+                                 struct CaptisDisplay *out);
+
+// Returns the index of the primary display, 0 if `capturer` is null.
+//
+// # Safety
+//
+// `capturer` has to be null or a live capturer.
+size_t captis_primary_display(const struct CaptisCapturer *capturer);
+
+// Looks for displays that were added, removed or moved.
+//
+// # Safety
+//
+// `capturer` has to be null or a live capturer.
+enum CaptisStatus captis_refresh_displays(struct CaptisCapturer *capturer);
+
+// Captures the display at `index` into `buffer` as tightly packed RGB rows, 3 bytes per
+// pixel, and writes its size into `width` and `height`.
+//
+// If `buffer_len` is too small nothing is written into `buffer`, but `width` and `height`
+// are still set so the caller can make room and try again. `buffer` may be null then.
+//
+// # Safety
+//
+// `capturer` has to be null or a live capturer, `buffer` valid for `buffer_len` bytes of
+// writes, and `width` and `height` null or valid for writes.
+enum CaptisStatus captis_capture(const struct CaptisCapturer *capturer,
+                                 size_t index,
+                                 uint8_t *buffer,
+                                 size_t buffer_len,
+                                 uint32_t *width,
+                                 uint32_t *height);
+
+// Describes `status` in a few words.
+const char *captis_status_string(enum CaptisStatus status);
+
+// Returns the message of the last call that failed on this thread, or an empty string.
+const char *captis_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CAPTIS_H */
@@ -0,0 +1,376 @@
+//! C bindings for captis. The header is generated when the crate is built and checked in as
+//! `include/captis.h`, it explains who owns what.
+
+use captis::{
+    init_capturer, Capturer, CoordinateType, Display, Layout, MockCapturer, ProportionType, Rect,
+};
+use std::{
+    cell::RefCell,
+    ffi::CString,
+    os::raw::c_char,
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+    sync::Mutex,
+};
+
+/// A capturer shared with C, the header explains how to handle it.
+pub struct CaptisCapturer {
+    inner: Mutex<Box<dyn Capturer>>,
+}
+
+/// A display, in the coordinates of the platform.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct CaptisDisplay {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// Physical pixels per logical pixel.
+    pub scale_factor: f64,
+}
+
+/// What every fallible function returns, anything but `CAPTIS_STATUS_OK` leaves a message
+/// behind for `captis_last_error`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CaptisStatus {
+    Ok = 0,
+    NullPointer,
+    InvalidDisplay,
+    BufferTooSmall,
+    InitFailed,
+    CaptureFailed,
+    Panicked,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn fail(status: CaptisStatus, message: impl ToString) -> CaptisStatus {
+    // Interior NUL bytes would cut the message short, they're dropped instead.
+    let message = message.to_string().replace('\0', "");
+
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).unwrap_or_default());
+
+    status
+}
+
+/// Runs `f` with the capturer behind `capturer`, turning panics into
+/// `CAPTIS_STATUS_PANICKED` since they can't unwind into C.
+fn with_capturer(
+    capturer: *const CaptisCapturer,
+    f: impl FnOnce(&mut dyn Capturer) -> CaptisStatus,
+) -> CaptisStatus {
+    // Checked for null, the caller guarantees it came from `captis_init*` otherwise.
+    let capturer = match unsafe { capturer.as_ref() } {
+        Some(capturer) => capturer,
+        None => return fail(CaptisStatus::NullPointer, "The capturer is null"),
+    };
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut inner = match capturer.inner.lock() {
+            Ok(inner) => inner,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        f(inner.as_mut())
+    }));
+
+    result.unwrap_or_else(|_| fail(CaptisStatus::Panicked, "The capturer panicked"))
+}
+
+fn display_from(display: &Display) -> CaptisDisplay {
+    let bounds = display.bounds();
+
+    CaptisDisplay {
+        x: bounds.x,
+        y: bounds.y,
+        width: bounds.width,
+        height: bounds.height,
+        scale_factor: display.scale_factor(),
+    }
+}
+
+/// Connects to the screen and writes the new capturer into `out`.
+///
+/// # Safety
+///
+/// `out` has to be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn captis_init(out: *mut *mut CaptisCapturer) -> CaptisStatus {
+    if out.is_null() {
+        return fail(CaptisStatus::NullPointer, "`out` is null");
+    }
+
+    match panic::catch_unwind(init_capturer) {
+        Ok(Ok(capturer)) => {
+            *out = Box::into_raw(Box::new(CaptisCapturer {
+                inner: Mutex::new(Box::new(capturer)),
+            }));
+            CaptisStatus::Ok
+        }
+        Ok(Err(error)) => fail(CaptisStatus::InitFailed, error),
+        Err(_) => fail(CaptisStatus::Panicked, "Initializing the capturer panicked"),
+    }
+}
+
+/// Creates a capturer without a screen that has the `count` displays in `displays` and
+/// captures a gradient on each of them, for testing. A `scale_factor` that isn't positive is
+/// taken as 1, and displays that don't fit the coordinates of the platform, 16 bits on X11,
+/// are `CAPTIS_STATUS_INVALID_DISPLAY`.
+///
+/// # Safety
+///
+/// `displays` has to point to `count` displays unless `count` is 0, and `out` has to be null
+/// or valid for writes.
+#[no_mangle]
+// Some of the conversions are from a type to itself, depending on the platform.
+#[allow(clippy::useless_conversion)]
+pub unsafe extern "C" fn captis_init_mock(
+    displays: *const CaptisDisplay,
+    count: usize,
+    primary: usize,
+    out: *mut *mut CaptisCapturer,
+) -> CaptisStatus {
+    if out.is_null() || (displays.is_null() && count > 0) {
+        return fail(CaptisStatus::NullPointer, "`displays` or `out` is null");
+    }
+
+    let displays = match count {
+        0 => &[],
+        _ => slice::from_raw_parts(displays, count),
+    };
+
+    if primary >= count.max(1) {
+        return fail(
+            CaptisStatus::InvalidDisplay,
+            "`primary` isn't one of the displays",
+        );
+    }
+
+    let displays = displays
+        .iter()
+        .map(|display| {
+            let scale_factor = match display.scale_factor {
+                scale_factor if scale_factor > 0.0 => scale_factor,
+                _ => 1.0,
+            };
+
+            match (
+                CoordinateType::try_from(display.x),
+                CoordinateType::try_from(display.y),
+                ProportionType::try_from(display.width),
+                ProportionType::try_from(display.height),
+            ) {
+                (Ok(x), Ok(y), Ok(width), Ok(height)) => {
+                    Some(Display::new(x, y, width, height).with_scale_factor(scale_factor))
+                }
+                _ => None,
+            }
+        })
+        .collect::<Option<Vec<_>>>();
+
+    let displays = match displays {
+        Some(displays) => displays,
+        None => {
+            return fail(
+                CaptisStatus::InvalidDisplay,
+                "A display doesn't fit the coordinates of the platform",
+            )
+        }
+    };
+
+    let layout = Layout { displays, primary };
+
+    *out = Box::into_raw(Box::new(CaptisCapturer {
+        inner: Mutex::new(Box::new(MockCapturer::new(layout))),
+    }));
+
+    CaptisStatus::Ok
+}
+
+/// Releases a capturer, null is ignored.
+///
+/// # Safety
+///
+/// `capturer` has to come from `captis_init` or `captis_init_mock`, and can't be used
+/// anymore afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn captis_free(capturer: *mut CaptisCapturer) {
+    if !capturer.is_null() {
+        drop(Box::from_raw(capturer));
+    }
+}
+
+/// Returns how many displays there are, 0 if `capturer` is null.
+///
+/// # Safety
+///
+/// `capturer` has to be null or a live capturer.
+#[no_mangle]
+pub unsafe extern "C" fn captis_display_count(capturer: *const CaptisCapturer) -> usize {
+    let mut count = 0;
+
+    with_capturer(capturer, |capturer| {
+        count = capturer.displays().len();
+        CaptisStatus::Ok
+    });
+
+    count
+}
+
+/// Writes the display at `index` into `out`.
+///
+/// # Safety
+///
+/// `capturer` has to be null or a live capturer, and `out` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn captis_display(
+    capturer: *const CaptisCapturer,
+    index: usize,
+    out: *mut CaptisDisplay,
+) -> CaptisStatus {
+    if out.is_null() {
+        return fail(CaptisStatus::NullPointer, "`out` is null");
+    }
+
+    with_capturer(capturer, |capturer| match capturer.displays().get(index) {
+        Some(display) => {
+            *out = display_from(display);
+            CaptisStatus::Ok
+        }
+        None => fail(
+            CaptisStatus::InvalidDisplay,
+            format!("There's no display {}", index),
+        ),
+    })
+}
+
+/// Returns the index of the primary display, 0 if `capturer` is null.
+///
+/// # Safety
+///
+/// `capturer` has to be null or a live capturer.
+#[no_mangle]
+pub unsafe extern "C" fn captis_primary_display(capturer: *const CaptisCapturer) -> usize {
+    let mut primary = 0;
+
+    with_capturer(capturer, |capturer| {
+        primary = capturer.primary_display();
+        CaptisStatus::Ok
+    });
+
+    primary
+}
+
+/// Looks for displays that were added, removed or moved.
+///
+/// # Safety
+///
+/// `capturer` has to be null or a live capturer.
+#[no_mangle]
+pub unsafe extern "C" fn captis_refresh_displays(capturer: *mut CaptisCapturer) -> CaptisStatus {
+    with_capturer(capturer, |capturer| match capturer.refresh_displays() {
+        Ok(()) => CaptisStatus::Ok,
+        Err(error) => fail(CaptisStatus::CaptureFailed, error),
+    })
+}
+
+/// Captures the display at `index` into `buffer` as tightly packed RGB rows, 3 bytes per
+/// pixel, and writes its size into `width` and `height`.
+///
+/// If `buffer_len` is too small nothing is written into `buffer`, but `width` and `height`
+/// are still set so the caller can make room and try again. `buffer` may be null then.
+///
+/// # Safety
+///
+/// `capturer` has to be null or a live capturer, `buffer` valid for `buffer_len` bytes of
+/// writes, and `width` and `height` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn captis_capture(
+    capturer: *const CaptisCapturer,
+    index: usize,
+    buffer: *mut u8,
+    buffer_len: usize,
+    width: *mut u32,
+    height: *mut u32,
+) -> CaptisStatus {
+    if width.is_null() || height.is_null() {
+        return fail(CaptisStatus::NullPointer, "`width` or `height` is null");
+    }
+
+    with_capturer(capturer, |capturer| {
+        if index >= capturer.displays().len() {
+            return fail(
+                CaptisStatus::InvalidDisplay,
+                format!("There's no display {}", index),
+            );
+        }
+
+        // A capture is as big as the display in physical pixels, so too small a buffer is
+        // turned away without capturing anything.
+        let display = capturer.displays()[index];
+        let bounds = display.bounds();
+        let size = display.physical_region(
+            Rect::new(0, 0, bounds.width, bounds.height),
+            capturer.desktop_space(),
+        );
+        let needed = size.width as usize * size.height as usize * 3;
+
+        *width = size.width;
+        *height = size.height;
+
+        if buffer.is_null() || buffer_len < needed {
+            return fail(
+                CaptisStatus::BufferTooSmall,
+                format!("The buffer needs {} bytes", needed),
+            );
+        }
+
+        let image = match capturer.capture(index) {
+            Ok(image) => image,
+            Err(error) => return fail(CaptisStatus::CaptureFailed, error),
+        };
+
+        *width = image.width();
+        *height = image.height();
+
+        let pixels = image.as_raw();
+
+        // Only if the display changed since it was last looked at.
+        if buffer_len < pixels.len() {
+            return fail(
+                CaptisStatus::BufferTooSmall,
+                format!("The buffer needs {} bytes", pixels.len()),
+            );
+        }
+
+        ptr::copy_nonoverlapping(pixels.as_ptr(), buffer, pixels.len());
+
+        CaptisStatus::Ok
+    })
+}
+
+/// Describes `status` in a few words.
+#[no_mangle]
+pub extern "C" fn captis_status_string(status: CaptisStatus) -> *const c_char {
+    let message: &'static [u8] = match status {
+        CaptisStatus::Ok => b"Ok\0",
+        CaptisStatus::NullPointer => b"A pointer was null\0",
+        CaptisStatus::InvalidDisplay => b"There's no such display\0",
+        CaptisStatus::BufferTooSmall => b"The buffer is too small\0",
+        CaptisStatus::InitFailed => b"Couldn't initialize the capturer\0",
+        CaptisStatus::CaptureFailed => b"Couldn't capture\0",
+        CaptisStatus::Panicked => b"The library panicked\0",
+    };
+
+    message.as_ptr() as *const c_char
+}
+
+/// Returns the message of the last call that failed on this thread, or an empty string.
+#[no_mangle]
+pub extern "C" fn captis_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
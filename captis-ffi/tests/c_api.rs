@@ -0,0 +1,98 @@
+//! Compiles `smoke.c` against the checked-in header and the shared library, then runs it.
+
+use captis_ffi::{captis_free, captis_init_mock, CaptisDisplay, CaptisStatus};
+use std::{env, fs, path::PathBuf, process::Command, ptr};
+
+/// The build script generates the header into `OUT_DIR`, the one in `include` is checked in.
+/// Running the tests with `CAPTIS_UPDATE_HEADER=1` copies the generated one over it.
+#[test]
+fn checked_in_header_is_up_to_date() {
+    let generated = fs::read_to_string(concat!(env!("OUT_DIR"), "/captis.h")).unwrap();
+    let checked_in = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("include/captis.h");
+
+    if env::var_os("CAPTIS_UPDATE_HEADER").is_some() {
+        fs::write(&checked_in, &generated).unwrap();
+    }
+
+    assert!(
+        fs::read_to_string(&checked_in).unwrap() == generated,
+        "include/captis.h is out of date, run the tests with CAPTIS_UPDATE_HEADER=1"
+    );
+}
+
+/// Displays that don't fit the coordinates of the platform, X11's are 16 bits.
+#[cfg(target_os = "linux")]
+#[test]
+fn mock_displays_have_to_fit_the_platform() {
+    let mut capturer = ptr::null_mut();
+
+    for display in [
+        CaptisDisplay {
+            x: 40_000,
+            width: 10,
+            height: 10,
+            ..CaptisDisplay::default()
+        },
+        CaptisDisplay {
+            width: 70_000,
+            height: 10,
+            ..CaptisDisplay::default()
+        },
+    ] {
+        let status = unsafe { captis_init_mock(&display, 1, 0, &mut capturer) };
+
+        assert_eq!(status, CaptisStatus::InvalidDisplay);
+        assert!(capturer.is_null());
+    }
+
+    let display = CaptisDisplay {
+        x: -100,
+        width: 10,
+        height: 10,
+        ..CaptisDisplay::default()
+    };
+
+    assert_eq!(
+        unsafe { captis_init_mock(&display, 1, 0, &mut capturer) },
+        CaptisStatus::Ok
+    );
+    unsafe { captis_free(capturer) };
+}
+
+#[test]
+fn c_smoke_test() {
+    let crate_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+
+    // The shared library is built next to the test executable, in `target/<profile>/deps`.
+    let deps = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let program = deps.join("captis_smoke");
+
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+
+    let status = Command::new(&compiler)
+        .arg("-std=c99")
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-I")
+        .arg(crate_dir.join("include"))
+        .arg(crate_dir.join("tests/smoke.c"))
+        .arg("-L")
+        .arg(&deps)
+        .arg(format!("-Wl,-rpath,{}", deps.display()))
+        .arg("-lcaptis_ffi")
+        .arg("-o")
+        .arg(&program)
+        .status()
+        .expect("Couldn't Run The C Compiler");
+
+    assert!(status.success(), "Couldn't Compile smoke.c");
+
+    let output = Command::new(&program)
+        .output()
+        .expect("Couldn't Run The C Program");
+
+    print!("{}", String::from_utf8_lossy(&output.stdout));
+    eprint!("{}", String::from_utf8_lossy(&output.stderr));
+
+    assert!(output.status.success(), "smoke.c failed");
+}
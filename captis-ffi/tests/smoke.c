@@ -0,0 +1,86 @@
+/* Exercises the C API against a mock capturer, and a real one if there's a screen. */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "captis.h"
+
+#define CHECK(condition)                                                   \
+    do {                                                                   \
+        if (!(condition)) {                                                \
+            fprintf(stderr, "%s:%d: %s failed: %s\n", __FILE__, __LINE__, \
+                    #condition, captis_last_error());                      \
+            return 1;                                                      \
+        }                                                                  \
+    } while (0)
+
+static int capture_all(CaptisCapturer *capturer) {
+    size_t count = captis_display_count(capturer);
+
+    for (size_t i = 0; i < count; i++) {
+        CaptisDisplay display;
+        uint32_t width = 0, height = 0;
+
+        CHECK(captis_display(capturer, i, &display) == CAPTIS_STATUS_OK);
+
+        /* Asking with no buffer tells how big it has to be. */
+        CHECK(captis_capture(capturer, i, NULL, 0, &width, &height) ==
+              CAPTIS_STATUS_BUFFER_TOO_SMALL);
+        CHECK(width > 0 && height > 0);
+
+        size_t len = (size_t)width * height * 3;
+        uint8_t *buffer = malloc(len);
+        CHECK(buffer != NULL);
+        CHECK(captis_capture(capturer, i, buffer, len, &width, &height) == CAPTIS_STATUS_OK);
+        free(buffer);
+    }
+
+    return 0;
+}
+
+int main(void) {
+    CaptisDisplay displays[2] = {
+        {.x = 0, .y = 0, .width = 64, .height = 48, .scale_factor = 1.0},
+        {.x = 64, .y = 0, .width = 32, .height = 24, .scale_factor = 1.0},
+    };
+
+    CaptisCapturer *mock = NULL;
+    CHECK(captis_init_mock(displays, 2, 1, &mock) == CAPTIS_STATUS_OK);
+    CHECK(captis_display_count(mock) == 2);
+    CHECK(captis_primary_display(mock) == 1);
+
+    CaptisDisplay display;
+    CHECK(captis_display(mock, 1, &display) == CAPTIS_STATUS_OK);
+    CHECK(display.x == 64 && display.width == 32 && display.height == 24);
+
+    uint32_t width = 0, height = 0;
+    uint8_t small[16];
+    CHECK(captis_capture(mock, 1, small, sizeof small, &width, &height) ==
+          CAPTIS_STATUS_BUFFER_TOO_SMALL);
+    CHECK(width == 32 && height == 24);
+    CHECK(strlen(captis_last_error()) > 0);
+
+    CHECK(captis_capture(mock, 2, small, sizeof small, &width, &height) ==
+          CAPTIS_STATUS_INVALID_DISPLAY);
+    CHECK(captis_display_count(NULL) == 0);
+    CHECK(strcmp(captis_status_string(CAPTIS_STATUS_OK), "Ok") == 0);
+
+    CHECK(capture_all(mock) == 0);
+    captis_free(mock);
+
+    /* Without a screen initializing fails, but it has to fail cleanly. */
+    CaptisCapturer *capturer = NULL;
+
+    if (captis_init(&capturer) == CAPTIS_STATUS_OK) {
+        CHECK(capture_all(capturer) == 0);
+        captis_free(capturer);
+    } else {
+        CHECK(capturer == NULL);
+        CHECK(strlen(captis_last_error()) > 0);
+        printf("No screen: %s\n", captis_last_error());
+    }
+
+    printf("ok\n");
+    return 0;
+}
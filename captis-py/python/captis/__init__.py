@@ -0,0 +1,13 @@
+"""Screen capturing for Linux, Windows and MacOS, with frames as NumPy arrays.
+
+>>> import captis
+>>> capturer = captis.Capturer()
+>>> capturer.displays[0]["width"]
+1920
+>>> capturer.capture(region=(0, 0, 100, 50)).shape
+(50, 100, 3)
+"""
+
+from ._captis import CaptureError, Capturer
+
+__all__ = ["CaptureError", "Capturer"]
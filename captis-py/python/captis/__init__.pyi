@@ -0,0 +1,32 @@
+from typing import List, Literal, Mapping, Optional, Sequence, Tuple, TypedDict
+
+import numpy as np
+import numpy.typing as npt
+
+class DisplayInfo(TypedDict):
+    index: int
+    left: int
+    top: int
+    width: int
+    height: int
+    scale_factor: float
+    is_primary: bool
+
+class CaptureError(Exception): ...
+
+class Capturer:
+    def __init__(self) -> None: ...
+    @staticmethod
+    def mock(displays: Sequence[Mapping[str, float]], primary: int = 0) -> "Capturer": ...
+    @property
+    def displays(self) -> List[DisplayInfo]: ...
+    @property
+    def primary_display(self) -> int: ...
+    def capture(
+        self,
+        display: Optional[int] = None,
+        region: Optional[Tuple[int, int, int, int]] = None,
+        space: Literal["physical", "logical"] = "physical",
+    ) -> npt.NDArray[np.uint8]: ...
+    def capture_all(self) -> List[npt.NDArray[np.uint8]]: ...
+    def refresh_displays(self) -> None: ...
@@ -0,0 +1,262 @@
+//! Python bindings for captis, built as the `captis._captis` extension module and re-exported
+//! by the `captis` package in `python/`.
+
+use captis::{
+    init_capturer, CoordinateSpace, CoordinateType, Display, Layout, MockCapturer, ProportionType,
+    Rect,
+};
+use numpy::{PyArray1, PyArrayMethods};
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyIndexError, PyValueError},
+    prelude::*,
+    types::PyDict,
+};
+use std::collections::HashMap;
+
+create_exception!(
+    captis,
+    CaptureError,
+    PyException,
+    "Raised when the screen couldn't be reached or captured."
+);
+
+/// Captures the displays of the screen into `(height, width, 3)` arrays of RGB bytes.
+///
+/// The pixels are handed to NumPy as they are, without copying them. Other Python threads keep
+/// running while a capture is taken.
+#[pyclass(module = "captis")]
+struct Capturer {
+    inner: Box<dyn captis::Capturer>,
+}
+
+#[pymethods]
+impl Capturer {
+    #[new]
+    fn new() -> PyResult<Self> {
+        let capturer = init_capturer().map_err(|error| CaptureError::new_err(error.to_string()))?;
+
+        Ok(Self {
+            inner: Box::new(capturer),
+        })
+    }
+
+    /// A capturer without a screen that has the given displays, dicts with `left`, `top`,
+    /// `width`, `height` and optionally `scale_factor`, and captures a gradient on each of
+    /// them. The position and size have to be whole numbers the platform's coordinates can
+    /// hold, 16 bits on X11.
+    #[staticmethod]
+    #[pyo3(signature = (displays, primary = 0))]
+    // Some of the conversions are from a type to itself, depending on the platform.
+    #[allow(clippy::useless_conversion)]
+    fn mock(displays: Vec<HashMap<String, f64>>, primary: usize) -> PyResult<Self> {
+        if !displays.is_empty() && primary >= displays.len() {
+            return Err(PyIndexError::new_err(format!(
+                "There's no display {}",
+                primary
+            )));
+        }
+
+        let field = |display: &HashMap<String, f64>, name: &str| {
+            display
+                .get(name)
+                .copied()
+                .ok_or_else(|| PyValueError::new_err(format!("A display is missing `{}`", name)))
+        };
+
+        // `as` would quietly turn fractions, negative sizes and anything too big into
+        // something else.
+        let invalid = |name: &str, value: f64| {
+            PyValueError::new_err(format!("`{}` can't be {} on this platform", name, value))
+        };
+        let coordinate = |display: &HashMap<String, f64>, name: &str| {
+            let value = field(display, name)?;
+
+            match whole(value, i32::MIN as f64, i32::MAX as f64) {
+                Some(value) => CoordinateType::try_from(value as i32).ok(),
+                None => None,
+            }
+            .ok_or_else(|| invalid(name, value))
+        };
+        let proportion = |display: &HashMap<String, f64>, name: &str| {
+            let value = field(display, name)?;
+
+            match whole(value, 0.0, u32::MAX as f64) {
+                Some(value) => ProportionType::try_from(value as u32).ok(),
+                None => None,
+            }
+            .ok_or_else(|| invalid(name, value))
+        };
+
+        let displays = displays
+            .iter()
+            .map(|display| {
+                let scale_factor = display.get("scale_factor").copied().unwrap_or(1.0);
+
+                if !(scale_factor.is_finite() && scale_factor > 0.0) {
+                    return Err(invalid("scale_factor", scale_factor));
+                }
+
+                Ok(Display::new(
+                    coordinate(display, "left")?,
+                    coordinate(display, "top")?,
+                    proportion(display, "width")?,
+                    proportion(display, "height")?,
+                )
+                .with_scale_factor(scale_factor))
+            })
+            .collect::<PyResult<_>>()?;
+
+        Ok(Self {
+            inner: Box::new(MockCapturer::new(Layout { displays, primary })),
+        })
+    }
+
+    /// The displays as dicts with `index`, `left`, `top`, `width`, `height`,
+    /// `scale_factor` and `is_primary`.
+    #[getter]
+    fn displays<'py>(&self, py: Python<'py>) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let primary = self.inner.primary_display();
+
+        self.inner
+            .displays()
+            .iter()
+            .enumerate()
+            .map(|(index, display)| {
+                let bounds = display.bounds();
+                let dict = PyDict::new(py);
+
+                dict.set_item("index", index)?;
+                dict.set_item("left", bounds.x)?;
+                dict.set_item("top", bounds.y)?;
+                dict.set_item("width", bounds.width)?;
+                dict.set_item("height", bounds.height)?;
+                dict.set_item("scale_factor", display.scale_factor())?;
+                dict.set_item("is_primary", index == primary)?;
+
+                Ok(dict)
+            })
+            .collect()
+    }
+
+    /// The index of the primary display.
+    #[getter]
+    fn primary_display(&self) -> usize {
+        self.inner.primary_display()
+    }
+
+    /// Captures the display at `display`, the primary one by default.
+    ///
+    /// `region` is a `(left, top, width, height)` tuple relative to the display, and has to
+    /// lie within it. It's measured in the pixels of the capture unless `space` is
+    /// `"logical"`, then it's multiplied by the display's `scale_factor` first.
+    #[pyo3(signature = (display = None, region = None, space = "physical"))]
+    fn capture<'py>(
+        &self,
+        py: Python<'py>,
+        display: Option<usize>,
+        region: Option<(u32, u32, u32, u32)>,
+        space: &str,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let space = match space {
+            "physical" => CoordinateSpace::Physical,
+            "logical" => CoordinateSpace::Logical,
+            space => {
+                return Err(PyValueError::new_err(format!(
+                    "`space` has to be \"physical\" or \"logical\", got {:?}",
+                    space
+                )))
+            }
+        };
+
+        let index = display.unwrap_or_else(|| self.inner.primary_display());
+
+        if index >= self.inner.displays().len() {
+            return Err(PyIndexError::new_err(format!(
+                "There's no display {}",
+                index
+            )));
+        }
+
+        let image = match region {
+            None => py.detach(|| self.inner.capture(index)),
+            Some((left, top, region_width, region_height)) => {
+                let display = self.inner.displays()[index];
+                let bounds = display.bounds();
+                let size = display.physical_region(
+                    Rect::new(0, 0, bounds.width, bounds.height),
+                    self.inner.desktop_space(),
+                );
+
+                let region = Rect::new(left as i32, top as i32, region_width, region_height);
+                let physical = display.physical_region(region, space);
+
+                if physical.intersection(&size) != Some(physical) {
+                    return Err(PyValueError::new_err(format!(
+                        "The region {:?} isn't within the {}x{} display",
+                        (left, top, region_width, region_height),
+                        size.width,
+                        size.height
+                    )));
+                }
+
+                py.detach(|| self.inner.capture_region(index, region, space))
+            }
+        }
+        .map_err(|error| CaptureError::new_err(error.to_string()))?;
+
+        let (width, height) = image.dimensions();
+
+        to_array(py, image.into_raw(), width, height)
+    }
+
+    /// Captures every display, in the order of `displays`.
+    fn capture_all<'py>(&self, py: Python<'py>) -> PyResult<Vec<Bound<'py, PyAny>>> {
+        let images = py
+            .detach(|| self.inner.capture_all())
+            .map_err(|error| CaptureError::new_err(error.to_string()))?;
+
+        images
+            .into_iter()
+            .map(|image| {
+                let (width, height) = image.dimensions();
+                to_array(py, image.into_raw(), width, height)
+            })
+            .collect()
+    }
+
+    /// Looks for displays that were added, removed or moved.
+    fn refresh_displays(&mut self) -> PyResult<()> {
+        self.inner
+            .refresh_displays()
+            .map_err(|error| CaptureError::new_err(error.to_string()))
+    }
+}
+
+/// Hands `pixels` over to NumPy, which keeps the allocation alive, and shapes it as an image.
+fn to_array(
+    py: Python<'_>,
+    pixels: Vec<u8>,
+    width: u32,
+    height: u32,
+) -> PyResult<Bound<'_, PyAny>> {
+    let array = PyArray1::from_vec(py, pixels).reshape([height as usize, width as usize, 3])?;
+
+    Ok(array.into_any())
+}
+
+/// Returns `value` if it's a whole number between `min` and `max`.
+fn whole(value: f64, min: f64, max: f64) -> Option<f64> {
+    match value {
+        value if value.fract() == 0.0 && value >= min && value <= max => Some(value),
+        _ => None,
+    }
+}
+
+#[pymodule]
+fn _captis(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Capturer>()?;
+    module.add("CaptureError", module.py().get_type::<CaptureError>())?;
+
+    Ok(())
+}
@@ -0,0 +1,105 @@
+import os
+
+import numpy as np
+import pytest
+
+import captis
+
+DISPLAYS = [
+    {"left": 0, "top": 0, "width": 64, "height": 48},
+    {"left": 64, "top": 0, "width": 32, "height": 24},
+]
+
+
+@pytest.fixture
+def mock():
+    return captis.Capturer.mock(DISPLAYS, primary=1)
+
+
+def test_displays(mock):
+    displays = mock.displays
+
+    assert [(d["left"], d["width"], d["height"]) for d in displays] == [
+        (0, 64, 48),
+        (64, 32, 24),
+    ]
+    assert [d["is_primary"] for d in displays] == [False, True]
+    assert mock.primary_display == 1
+
+
+def test_capture(mock):
+    frame = mock.capture(0)
+
+    assert frame.shape == (48, 64, 3)
+    assert frame.dtype == np.uint8
+    # The mock captures a ramp, red grows to the right and green downwards.
+    assert frame[0, -1, 0] > frame[0, 0, 0]
+    assert frame[-1, 0, 1] > frame[0, 0, 1]
+
+
+def test_capture_primary_by_default(mock):
+    assert mock.capture().shape == (24, 32, 3)
+
+
+def test_capture_region(mock):
+    full = mock.capture(0)
+    region = mock.capture(0, region=(10, 5, 20, 8))
+
+    assert region.shape == (8, 20, 3)
+    assert np.array_equal(region, full[5:13, 10:30])
+
+
+def test_capture_logical_region():
+    hidpi = captis.Capturer.mock(
+        [{"left": 0, "top": 0, "width": 64, "height": 48, "scale_factor": 2}]
+    )
+
+    assert hidpi.displays[0]["scale_factor"] == 2.0
+
+    full = hidpi.capture(0)
+    region = hidpi.capture(0, region=(5, 2, 10, 4), space="logical")
+
+    assert region.shape == (8, 20, 3)
+    assert np.array_equal(region, full[4:12, 10:30])
+
+
+def test_capture_region_outside(mock):
+    with pytest.raises(ValueError):
+        mock.capture(0, region=(60, 0, 10, 10))
+
+
+def test_capture_missing_display(mock):
+    with pytest.raises(IndexError):
+        mock.capture(2)
+
+
+@pytest.mark.parametrize(
+    "field, value",
+    [
+        ("left", 1.5),
+        ("width", -32),
+        ("height", float("nan")),
+        ("width", 2**40),
+        ("scale_factor", 0),
+    ],
+)
+def test_mock_rejects_invalid_displays(field, value):
+    with pytest.raises(ValueError):
+        captis.Capturer.mock([{**DISPLAYS[0], field: value}])
+
+
+def test_capture_all(mock):
+    assert [frame.shape for frame in mock.capture_all()] == [(48, 64, 3), (24, 32, 3)]
+
+
+@pytest.mark.skipif(
+    not os.environ.get("DISPLAY"), reason="needs an X server, like Xvfb"
+)
+def test_screen():
+    capturer = captis.Capturer()
+    display = capturer.displays[capturer.primary_display]
+
+    frame = capturer.capture()
+
+    assert frame.shape == (display["height"], display["width"], 3)
+    assert capturer.capture(region=(0, 0, 16, 16)).shape == (16, 16, 3)
@@ -0,0 +1,551 @@
+use captis::*;
+use std::{
+    env,
+    error::Error,
+    fmt,
+    io::{self, BufRead, Cursor, Write},
+    process, thread,
+    time::{Duration, Instant},
+};
+
+const USAGE: &str = "\
+Usage: captis <command> [options]
+
+Commands:
+    list      Lists the displays
+                  --json                 Prints the layout as JSON, which MockCapturer can load
+    shot      Takes a screenshot
+                  --display <index>      Display to capture [default: 0]
+                  --region <x,y,w,h>     Part of the display to keep
+                  --logical              Measures `--region` in logical pixels
+                  -o, --output <file>    Where to save it, `-` for stdout [default: screenshot.png]
+                  --format <format>      Image format when writing to stdout [default: png]
+    record    Records a video, the format is picked from the extension
+                  --display <index>      Display to record [default: 0]
+                  --fps <rate>           Frames per second [default: 30]
+                  --duration <time>      How long to record, like 10s or 2m [default: until Enter]
+                  -o, --output <file>    Where to save it, `-` streams Y4M to stdout [default: recording.avi]
+    bench     Measures how fast displays are captured
+                  --display <index>      Display to capture [default: all of them]
+                  --frames <count>       Frames to capture per measurement [default: 60]
+    watch     Prints the parts of a display that change
+                  --display <index>      Display to watch [default: 0]
+                  --fps <rate>           Checks per second [default: 2]
+                  --json                 Prints every change as a line of JSON
+    help      Prints this message";
+
+/// A mistake in the arguments, which is followed by the usage.
+#[derive(Debug)]
+struct UsageError(String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for UsageError {}
+
+fn usage_error<T>(message: impl Into<String>) -> Result<T, Box<dyn Error>> {
+    Err(Box::new(UsageError(message.into())))
+}
+
+fn main() {
+    if let Err(error) = run(env::args().skip(1).collect()) {
+        eprintln!("captis: {}", error);
+
+        if error.is::<UsageError>() {
+            eprintln!("\n{}", USAGE);
+            process::exit(2);
+        }
+
+        process::exit(1);
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), Box<dyn Error>> {
+    let (command, args) = match args.split_first() {
+        Some((command, args)) => (command.as_str(), args),
+        None => return usage_error("Missing command"),
+    };
+
+    match command {
+        "list" => list(Options::parse(args, &["--json"])?),
+        "shot" => shot(Options::parse(
+            args,
+            &["--display", "--region", "--logical", "--output", "--format"],
+        )?),
+        "record" => record(Options::parse(
+            args,
+            &["--display", "--fps", "--duration", "--output"],
+        )?),
+        "bench" => bench(Options::parse(args, &["--display", "--frames"])?),
+        "watch" => watch(Options::parse(args, &["--display", "--fps", "--json"])?),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        command => usage_error(format!("Unknown command `{}`", command)),
+    }
+}
+
+#[derive(Default)]
+struct Options {
+    display: Option<usize>,
+    region: Option<Rect>,
+    output: Option<String>,
+    format: Option<String>,
+    fps: Option<u32>,
+    duration: Option<Duration>,
+    frames: Option<u32>,
+    json: bool,
+    logical: bool,
+}
+
+impl Options {
+    /// Parses `args`, only accepting the options in `allowed`.
+    fn parse(args: &[String], allowed: &[&str]) -> Result<Options, Box<dyn Error>> {
+        let mut options = Options::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let name = match arg.as_str() {
+                "-o" => "--output",
+                name => name,
+            };
+
+            if !allowed.contains(&name) {
+                return usage_error(format!("Unexpected argument `{}`", arg));
+            }
+
+            match name {
+                "--json" => {
+                    options.json = true;
+                    continue;
+                }
+                "--logical" => {
+                    options.logical = true;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let value = match args.next() {
+                Some(value) => value.as_str(),
+                None => return usage_error(format!("`{}` needs a value", arg)),
+            };
+
+            match name {
+                "--display" => options.display = Some(parse_number(name, value)?),
+                "--region" => options.region = Some(parse_region(value)?),
+                "--output" => options.output = Some(value.to_owned()),
+                "--format" => options.format = Some(value.to_owned()),
+                "--fps" => options.fps = Some(parse_number::<u32>(name, value)?.max(1)),
+                "--duration" => options.duration = Some(parse_duration(value)?),
+                "--frames" => options.frames = Some(parse_number::<u32>(name, value)?.max(1)),
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, Box<dyn Error>> {
+    match value.parse() {
+        Ok(number) => Ok(number),
+        Err(_) => usage_error(format!("`{}` needs a number, got `{}`", name, value)),
+    }
+}
+
+fn parse_region(value: &str) -> Result<Rect, Box<dyn Error>> {
+    let parts: Vec<&str> = value.split(',').map(str::trim).collect();
+
+    match parts[..] {
+        [x, y, width, height] => Ok(Rect::new(
+            parse_number("--region", x)?,
+            parse_number("--region", y)?,
+            parse_number("--region", width)?,
+            parse_number("--region", height)?,
+        )),
+        _ => usage_error(format!("`--region` needs x,y,w,h, got `{}`", value)),
+    }
+}
+
+/// Parses durations like `1.5s`, `500ms` or `2m`, plain numbers are seconds.
+fn parse_duration(value: &str) -> Result<Duration, Box<dyn Error>> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let seconds = match (number.parse::<f64>(), unit) {
+        (Ok(number), "" | "s") => number,
+        (Ok(number), "ms") => number / 1000.0,
+        (Ok(number), "m") => number * 60.0,
+        (Ok(number), "h") => number * 3600.0,
+        _ => {
+            return usage_error(format!(
+                "`--duration` needs a time like 10s, got `{}`",
+                value
+            ))
+        }
+    };
+
+    // Anything longer doesn't fit a `Duration`, which would panic.
+    if seconds >= u64::MAX as f64 {
+        return usage_error(format!("`--duration` is too long, got `{}`", value));
+    }
+
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+fn list(options: Options) -> Result<(), Box<dyn Error>> {
+    let capturer = init_capturer()?;
+    let displays = capturer.displays();
+
+    if options.json {
+        println!("{}", serde_json::to_string(&capturer.layout())?);
+        return Ok(());
+    }
+
+    println!(
+        "{:<6} {:>6} {:>6} {:>6} {:>6} {:>6}",
+        "INDEX", "X", "Y", "WIDTH", "HEIGHT", "SCALE"
+    );
+
+    for (index, display) in displays.iter().enumerate() {
+        println!(
+            "{:<6} {:>6} {:>6} {:>6} {:>6} {:>6}",
+            index,
+            display.left(),
+            display.top(),
+            display.width(),
+            display.height(),
+            display.scale_factor()
+        );
+    }
+
+    Ok(())
+}
+
+fn shot(options: Options) -> Result<(), Box<dyn Error>> {
+    let capturer = init_capturer()?;
+
+    let index = options.display.unwrap_or(0);
+
+    let display = match capturer.displays().get(index) {
+        Some(display) => *display,
+        None => return usage_error(format!("There's no display {}", index)),
+    };
+
+    let image = match options.region {
+        Some(region) => {
+            let space = if options.logical {
+                CoordinateSpace::Logical
+            } else {
+                CoordinateSpace::Physical
+            };
+
+            let bounds = display.bounds();
+            let size = display.physical_region(
+                Rect::new(0, 0, bounds.width, bounds.height),
+                capturer.desktop_space(),
+            );
+            let physical = display.physical_region(region, space);
+
+            if region.is_empty() || physical.intersection(&size) != Some(physical) {
+                return usage_error(format!(
+                    "`--region` has to be inside the {}x{} display",
+                    size.width, size.height
+                ));
+            }
+
+            capturer.capture_region(index, region, space)?
+        }
+        None => capturer.capture(index)?,
+    };
+
+    let output = options.output.as_deref().unwrap_or("screenshot.png");
+
+    if output != "-" {
+        image.save(output)?;
+        return Ok(());
+    }
+
+    let format = match options.format.as_deref().unwrap_or("png") {
+        "png" => image::ImageOutputFormat::Png,
+        "jpg" | "jpeg" => image::ImageOutputFormat::Jpeg(90),
+        format => return usage_error(format!("Unknown image format `{}`", format)),
+    };
+
+    // The encoders need to seek, which stdout can't.
+    let mut encoded = Cursor::new(vec![]);
+    image.write_to(&mut encoded, format)?;
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    stdout.write_all(encoded.get_ref())?;
+    stdout.flush()?;
+
+    Ok(())
+}
+
+fn record(options: Options) -> Result<(), Box<dyn Error>> {
+    let capturer = init_capturer()?;
+
+    let fps = options.fps.unwrap_or(30);
+    let output = options.output.as_deref().unwrap_or("recording.avi");
+
+    let mut recorder = Recorder::new(if output == "-" {
+        VideoFormat::Y4m
+    } else {
+        match VideoFormat::from_path(output) {
+            Some(format) => format,
+            None => return usage_error(format!("Can't tell the video format of `{}`", output)),
+        }
+    })
+    .frame_rate(fps);
+
+    if let Some(duration) = options.duration {
+        recorder = recorder.max_duration(duration);
+    }
+
+    let recording = if output == "-" {
+        recorder.create_stream(io::stdout())?
+    } else {
+        recorder.create(output)?
+    };
+
+    if options.duration.is_none() {
+        let controls = recording.controls();
+
+        eprintln!("Recording, press Enter to stop.");
+
+        thread::spawn(move || {
+            let stdin = io::stdin();
+            let mut lines = stdin.lock().lines();
+
+            // Stdin that's closed or not a terminal doesn't stop the recording.
+            if let Some(Ok(_)) = lines.next() {
+                controls.stop();
+            }
+        });
+    }
+
+    recording.record(FrameStream::new(
+        &capturer,
+        options.display.unwrap_or(0),
+        fps,
+    ))?;
+
+    Ok(())
+}
+
+fn bench(options: Options) -> Result<(), Box<dyn Error>> {
+    let capturer = init_capturer()?;
+    let frames = options.frames.unwrap_or(60);
+
+    let displays: Vec<usize> = match options.display {
+        Some(display) => vec![display],
+        None => (0..capturer.displays().len()).collect(),
+    };
+
+    let measure = |name: &str, capture: &dyn Fn() -> Result<(), captis::Error>| {
+        let started = Instant::now();
+
+        for _ in 0..frames {
+            capture()?;
+        }
+
+        let per_frame = started.elapsed() / frames;
+
+        println!(
+            "{:<32} {:>8.2}ms {:>8.1} fps",
+            name,
+            per_frame.as_secs_f64() * 1000.0,
+            1.0 / per_frame.as_secs_f64()
+        );
+
+        Ok::<_, captis::Error>(())
+    };
+
+    for display in displays {
+        measure(&format!("capture({})", display), &|| {
+            capturer.capture(display).map(drop)
+        })?;
+        measure(&format!("capture_scaled({}, 0.25, Box)", display), &|| {
+            capturer
+                .capture_scaled(display, Scale::Factor(0.25), ScaleFilter::Box)
+                .map(drop)
+        })?;
+    }
+
+    if options.display.is_none() && capturer.displays().len() > 1 {
+        measure("capture_all_at_once()", &|| {
+            capturer.capture_all_at_once().map(drop)
+        })?;
+    }
+
+    Ok(())
+}
+
+/// A line of `watch --json`.
+#[derive(serde::Serialize)]
+struct Change<'a> {
+    /// Seconds since watching started.
+    time: f64,
+    regions: &'a [Rect],
+}
+
+fn watch(options: Options) -> Result<(), Box<dyn Error>> {
+    let capturer = init_capturer()?;
+
+    let mut previous: Option<RgbImage> = None;
+
+    let stdout = io::stdout();
+
+    for frame in FrameStream::new(
+        &capturer,
+        options.display.unwrap_or(0),
+        options.fps.unwrap_or(2),
+    ) {
+        let (timestamp, image) = frame?;
+
+        let regions = match &previous {
+            Some(previous) => changed_regions(previous, &image, TILE_SIZE),
+            None => vec![],
+        };
+
+        previous = Some(image);
+
+        if regions.is_empty() {
+            continue;
+        }
+
+        let mut stdout = stdout.lock();
+
+        if options.json {
+            let change = Change {
+                time: (timestamp.as_secs_f64() * 1000.0).round() / 1000.0,
+                regions: &regions,
+            };
+
+            writeln!(stdout, "{}", serde_json::to_string(&change)?)?;
+        } else {
+            let regions: Vec<String> = regions
+                .iter()
+                .map(|region| {
+                    format!(
+                        "{}x{}+{}+{}",
+                        region.width, region.height, region.x, region.y
+                    )
+                })
+                .collect();
+
+            writeln!(
+                stdout,
+                "{:>10.3}s changed {}",
+                timestamp.as_secs_f64(),
+                regions.join(" ")
+            )?;
+        }
+
+        // Lets whatever reads the pipe react right away.
+        stdout.flush()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn is_usage_error<T>(result: Result<T, Box<dyn Error>>) -> bool {
+        matches!(result, Err(error) if error.is::<UsageError>())
+    }
+
+    #[test]
+    fn parses_the_allowed_options() {
+        let options = Options::parse(
+            &args(&[
+                "--display",
+                "1",
+                "-o",
+                "-",
+                "--logical",
+                "--region",
+                "1, 2,3,4",
+            ]),
+            &["--display", "--output", "--logical", "--region"],
+        )
+        .unwrap();
+
+        assert_eq!(options.display, Some(1));
+        assert_eq!(options.output.as_deref(), Some("-"));
+        assert!(options.logical);
+        assert_eq!(options.region, Some(Rect::new(1, 2, 3, 4)));
+        assert!(!options.json);
+    }
+
+    #[test]
+    fn rejects_other_options_and_missing_values() {
+        assert!(is_usage_error(Options::parse(
+            &args(&["--json"]),
+            &["--display"]
+        )));
+        assert!(is_usage_error(Options::parse(
+            &args(&["--display"]),
+            &["--display"]
+        )));
+        assert!(is_usage_error(Options::parse(
+            &args(&["--display", "first"]),
+            &["--display"]
+        )));
+    }
+
+    #[test]
+    fn frame_rates_are_at_least_one() {
+        let options = Options::parse(&args(&["--fps", "0"]), &["--fps"]).unwrap();
+
+        assert_eq!(options.fps, Some(1));
+    }
+
+    #[test]
+    fn parses_regions() {
+        assert_eq!(
+            parse_region("-10,20,30,40").unwrap(),
+            Rect::new(-10, 20, 30, 40)
+        );
+        assert!(is_usage_error(parse_region("1,2,3")));
+        assert!(is_usage_error(parse_region("1,2,3,-4")));
+        assert!(is_usage_error(parse_region("a,b,c,d")));
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("10").unwrap(), Duration::from_secs(10));
+        assert_eq!(parse_duration("1.5s").unwrap(), Duration::from_millis(1500));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("2m").unwrap(), Duration::from_secs(120));
+        assert_eq!(parse_duration("1h").unwrap(), Duration::from_secs(3600));
+
+        assert!(is_usage_error(parse_duration("10d")));
+        assert!(is_usage_error(parse_duration("-1s")));
+        assert!(is_usage_error(parse_duration("")));
+    }
+
+    #[test]
+    fn huge_durations_are_usage_errors() {
+        assert!(is_usage_error(parse_duration(&"9".repeat(30))));
+        assert!(is_usage_error(parse_duration(&format!(
+            "{}h",
+            "9".repeat(17)
+        ))));
+    }
+}
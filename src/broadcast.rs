@@ -0,0 +1,326 @@
+use super::{Capturer, Error as CaptureError, RgbImage};
+use std::{
+    error::Error,
+    fmt,
+    io::{self, ErrorKind},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How many clients a server takes at once unless it's told otherwise.
+const DEFAULT_MAX_CLIENTS: usize = 32;
+
+/// How long the listeners sleep while nobody is connecting, which is also how long they take
+/// to notice that the server stopped.
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// What [`VncServer`](crate::VncServer) and [`PreviewServer`](crate::PreviewServer) fail with.
+#[derive(Debug)]
+pub enum ServerError {
+    Io(io::Error),
+    Capture(CaptureError),
+    CouldntFindDisplay,
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for ServerError {}
+
+impl From<io::Error> for ServerError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<CaptureError> for ServerError {
+    fn from(error: CaptureError) -> Self {
+        Self::Capture(error)
+    }
+}
+
+/// The displays a server shares, each on the listener its clients connect to.
+pub(crate) struct Listeners {
+    listeners: Vec<(usize, TcpListener)>,
+    max_clients: usize,
+}
+
+impl Listeners {
+    pub(crate) fn new() -> Self {
+        Self {
+            listeners: vec![],
+            max_clients: DEFAULT_MAX_CLIENTS,
+        }
+    }
+
+    pub(crate) fn bind<A: ToSocketAddrs>(&mut self, index: usize, addr: A) -> io::Result<()> {
+        self.listeners.push((index, TcpListener::bind(addr)?));
+        Ok(())
+    }
+
+    /// Limits how many clients are served at once over all displays, anyone connecting
+    /// beyond that is hung up on right away.
+    pub(crate) fn max_clients(&mut self, max_clients: usize) {
+        self.max_clients = max_clients;
+    }
+
+    /// Returns the addresses listened on, in the order they were bound.
+    pub(crate) fn local_addrs(&self) -> io::Result<Vec<SocketAddr>> {
+        self.listeners
+            .iter()
+            .map(|(_, listener)| listener.local_addr())
+            .collect()
+    }
+
+    /// Hands every client to `serve` on a thread of its own, together with the mailbox of
+    /// the display it connected to. Each display is captured every `interval` on the current
+    /// thread while it has subscribers, and published after `prepare` made it ready for
+    /// them.
+    ///
+    /// Only returns if capturing fails. The mailboxes are closed then, so the clients fail
+    /// the next time they wait for a capture, and the listeners stop accepting.
+    pub(crate) fn run<C, T, P, S>(
+        self,
+        capturer: &C,
+        interval: Duration,
+        prepare: P,
+        serve: S,
+    ) -> Result<(), ServerError>
+    where
+        C: Capturer,
+        T: Send + Sync + 'static,
+        P: Fn(RgbImage) -> T,
+        S: Fn(TcpStream, Arc<Mailbox<T>>) -> io::Result<()> + Clone + Send + 'static,
+    {
+        if self
+            .listeners
+            .iter()
+            .any(|(index, _)| *index >= capturer.displays().len())
+        {
+            return Err(ServerError::CouldntFindDisplay);
+        }
+
+        let mut mailboxes = Vec::with_capacity(self.listeners.len());
+        let clients = Arc::new(AtomicUsize::new(0));
+
+        for (index, listener) in self.listeners {
+            let mailbox = Arc::new(Mailbox::new());
+            let serve = serve.clone();
+            let clients = ClientSlots {
+                taken: clients.clone(),
+                max: self.max_clients,
+            };
+
+            // Polled, so the listener can see that the mailbox was closed.
+            listener.set_nonblocking(true)?;
+
+            mailboxes.push((index, mailbox.clone()));
+
+            thread::spawn(move || {
+                while !mailbox.is_closed() {
+                    let stream = match listener.accept() {
+                        Ok((stream, _)) => stream,
+                        Err(_) => {
+                            thread::sleep(ACCEPT_INTERVAL);
+                            continue;
+                        }
+                    };
+
+                    // Some platforms hand out the stream as non-blocking as the listener.
+                    let slot = match (stream.set_nonblocking(false), clients.take()) {
+                        (Ok(()), Some(slot)) => slot,
+                        _ => continue,
+                    };
+
+                    let mailbox = mailbox.clone();
+                    let serve = serve.clone();
+
+                    thread::spawn(move || {
+                        let _slot = slot;
+                        serve(stream, mailbox)
+                    });
+                }
+            });
+        }
+
+        loop {
+            let started = Instant::now();
+
+            for (index, mailbox) in &mailboxes {
+                if !mailbox.has_subscribers() {
+                    continue;
+                }
+
+                match capturer.capture(*index) {
+                    Ok(image) => mailbox.publish(prepare(image)),
+                    Err(error) => {
+                        for (_, mailbox) in &mailboxes {
+                            mailbox.close();
+                        }
+
+                        return Err(error.into());
+                    }
+                }
+            }
+
+            thread::sleep(interval.saturating_sub(started.elapsed()));
+        }
+    }
+}
+
+/// The clients of a server, which hand out a slot to every client while there are any left.
+struct ClientSlots {
+    taken: Arc<AtomicUsize>,
+    max: usize,
+}
+
+impl ClientSlots {
+    fn take(&self) -> Option<ClientSlot> {
+        if self.taken.fetch_add(1, Ordering::SeqCst) >= self.max {
+            self.taken.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+
+        Some(ClientSlot(self.taken.clone()))
+    }
+}
+
+/// Gives its slot back once the client is done.
+struct ClientSlot(Arc<AtomicUsize>);
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Holds the most recent value published by a producer so any number of consumer threads can
+/// pick it up, each at its own pace.
+pub(crate) struct Mailbox<T> {
+    state: Mutex<State<T>>,
+    published: Condvar,
+}
+
+struct State<T> {
+    value: Option<Arc<T>>,
+    sequence: u64,
+    subscribers: usize,
+    /// Set once the producer stopped for good.
+    closed: bool,
+}
+
+impl<T> Mailbox<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                value: None,
+                sequence: 0,
+                subscribers: 0,
+                closed: false,
+            }),
+            published: Condvar::new(),
+        }
+    }
+
+    /// Replaces the current value and wakes up everyone waiting for it.
+    pub(crate) fn publish(&self, value: T) {
+        let mut state = self.state.lock().unwrap();
+        state.value = Some(Arc::new(value));
+        state.sequence += 1;
+        self.published.notify_all();
+    }
+
+    /// Waits up to `timeout` for a value newer than the `seen` sequence number. Fails once the
+    /// mailbox is closed, nothing is going to be published anymore then.
+    pub(crate) fn wait_newer(
+        &self,
+        seen: u64,
+        timeout: Duration,
+    ) -> io::Result<Option<(u64, Arc<T>)>> {
+        let state = self.state.lock().unwrap();
+
+        let (state, _) = self
+            .published
+            .wait_timeout_while(state, timeout, |state| {
+                !state.closed && (state.sequence <= seen || state.value.is_none())
+            })
+            .unwrap();
+
+        if state.closed {
+            return Err(stopped_capturing());
+        }
+
+        match &state.value {
+            Some(value) if state.sequence > seen => Ok(Some((state.sequence, value.clone()))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Tells everyone waiting, and everyone who's going to, that the producer stopped.
+    pub(crate) fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.published.notify_all();
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+
+    /// Fails if the mailbox is closed, for clients that are idle instead of waiting.
+    pub(crate) fn check_open(&self) -> io::Result<()> {
+        if self.is_closed() {
+            return Err(stopped_capturing());
+        }
+
+        Ok(())
+    }
+
+    /// Returns the sequence number of the current value, which counts every value published.
+    pub(crate) fn sequence(&self) -> u64 {
+        self.state.lock().unwrap().sequence
+    }
+
+    /// Registers a consumer, the producer only has to do work while there are any.
+    pub(crate) fn subscribe(self: &Arc<Self>) -> Subscription<T> {
+        self.state.lock().unwrap().subscribers += 1;
+        Subscription(self.clone())
+    }
+
+    pub(crate) fn has_subscribers(&self) -> bool {
+        self.state.lock().unwrap().subscribers > 0
+    }
+}
+
+pub(crate) struct Subscription<T>(Arc<Mailbox<T>>);
+
+impl<T> std::ops::Deref for Subscription<T> {
+    type Target = Mailbox<T>;
+
+    fn deref(&self) -> &Mailbox<T> {
+        &self.0
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap();
+        state.subscribers -= 1;
+
+        // Drop the stale value once nobody is watching, so a new viewer never gets an old frame.
+        if state.subscribers == 0 {
+            state.value = None;
+        }
+    }
+}
+
+fn stopped_capturing() -> io::Error {
+    io::Error::new(ErrorKind::ConnectionAborted, "The server stopped capturing")
+}
@@ -0,0 +1,113 @@
+use super::RgbImage;
+use image::Rgb;
+use std::cmp::Reverse;
+
+/// How many of the high bits of each channel pick the bucket a colour is counted in for
+/// [`ColorStats::dominant_colors`], 4 gives 4096 buckets.
+const BUCKET_BITS: u32 = 4;
+
+/// The average colour, per channel histograms and most common colours of an image, for colour
+/// pickers and ambient lighting that only need a summary of a region.
+///
+/// ```
+/// use captis::{ColorStats, Rgb, RgbImage};
+///
+/// let stats = ColorStats::of(&RgbImage::from_pixel(4, 4, Rgb([200, 30, 30])));
+///
+/// assert_eq!(stats.average(), Some(Rgb([200, 30, 30])));
+/// assert_eq!(stats.dominant(), Some(Rgb([200, 30, 30])));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ColorStats {
+    pixels: u64,
+    sums: [u64; 3],
+    histograms: [[u32; 256]; 3],
+    /// The number of pixels in each bucket and the sums of their channels.
+    buckets: Vec<(u32, [u64; 3])>,
+}
+
+impl ColorStats {
+    pub fn of(image: &RgbImage) -> Self {
+        let mut stats = Self {
+            pixels: 0,
+            sums: [0; 3],
+            histograms: [[0; 256]; 3],
+            buckets: vec![(0, [0; 3]); 1 << (BUCKET_BITS * 3)],
+        };
+
+        let shift = 8 - BUCKET_BITS;
+
+        for rgb in image.as_raw().chunks_exact(3) {
+            let bucket = (rgb[0] as usize >> shift) << (BUCKET_BITS * 2)
+                | (rgb[1] as usize >> shift) << BUCKET_BITS
+                | rgb[2] as usize >> shift;
+
+            let (count, sums) = &mut stats.buckets[bucket];
+            *count += 1;
+
+            for c in 0..3 {
+                stats.sums[c] += rgb[c] as u64;
+                stats.histograms[c][rgb[c] as usize] += 1;
+                sums[c] += rgb[c] as u64;
+            }
+        }
+
+        stats.pixels = (image.width() * image.height()) as u64;
+
+        stats
+    }
+
+    /// The number of pixels that were counted.
+    pub fn pixels(&self) -> u64 {
+        self.pixels
+    }
+
+    /// The mean of every channel, or `None` for an empty image.
+    pub fn average(&self) -> Option<Rgb<u8>> {
+        if self.pixels == 0 {
+            return None;
+        }
+
+        Some(Rgb(self.sums.map(|sum| rounded_mean(sum, self.pixels))))
+    }
+
+    /// How many pixels have each value, for the red, green and blue channel.
+    pub fn histograms(&self) -> &[[u32; 256]; 3] {
+        &self.histograms
+    }
+
+    /// The most common colour, see [`ColorStats::dominant_colors`].
+    pub fn dominant(&self) -> Option<Rgb<u8>> {
+        self.dominant_colors(1).first().map(|&(color, _)| color)
+    }
+
+    /// Up to `count` of the most common colours with the share of pixels that have them, the
+    /// most common first.
+    ///
+    /// Colours that only differ a little, within 16 levels per channel, are counted as one,
+    /// whose colour is the average of them.
+    pub fn dominant_colors(&self, count: usize) -> Vec<(Rgb<u8>, f64)> {
+        let mut buckets: Vec<&(u32, [u64; 3])> = self
+            .buckets
+            .iter()
+            .filter(|(pixels, _)| *pixels > 0)
+            .collect();
+
+        buckets.sort_by_key(|(pixels, _)| Reverse(*pixels));
+
+        buckets
+            .into_iter()
+            .take(count)
+            .map(|&(pixels, sums)| {
+                (
+                    Rgb(sums.map(|sum| rounded_mean(sum, pixels as u64))),
+                    pixels as f64 / self.pixels as f64,
+                )
+            })
+            .collect()
+    }
+}
+
+fn rounded_mean(sum: u64, count: u64) -> u8 {
+    ((sum + count / 2) / count) as u8
+}
@@ -0,0 +1,303 @@
+//! Conversion of the BGRX pixels the platforms hand out into [`RgbImage`]s.
+//!
+//! The fastest implementation the CPU supports is picked at runtime, AVX2 or SSE2 on x86 and
+//! NEON on AArch64, all of them produce the exact same output as
+//! [`bgrx_row_to_rgb_scalar`].
+
+use super::{
+    scale::{scale_rows, Scale, ScaleFilter},
+    RgbImage,
+};
+
+/// Converts `height` rows of `width` BGRX pixels, which start `stride` bytes apart, into an
+/// [`RgbImage`].
+///
+/// # Panics
+///
+/// If `data` is too short to hold all the rows.
+pub fn bgrx_to_rgb(data: &[u8], stride: usize, width: u32, height: u32) -> RgbImage {
+    let mut image = RgbImage::new(width, height);
+
+    let convert_row = row_converter();
+    let (source_row, target_row) = (width as usize * 4, width as usize * 3);
+
+    if target_row == 0 {
+        return image;
+    }
+
+    for (y, target) in image.chunks_exact_mut(target_row).enumerate() {
+        let start = y * stride;
+        convert_row(&data[start..start + source_row], target);
+    }
+
+    image
+}
+
+/// Like [`bgrx_to_rgb`] but resizes the image to `scale` in the same pass, only converting the
+/// rows `filter` needs.
+///
+/// # Panics
+///
+/// If `data` is too short to hold all the rows.
+pub fn bgrx_to_rgb_scaled(
+    data: &[u8],
+    stride: usize,
+    width: u32,
+    height: u32,
+    scale: Scale,
+    filter: ScaleFilter,
+) -> RgbImage {
+    let convert_row = row_converter();
+    let source_row = width as usize * 4;
+
+    scale_rows(
+        (width, height),
+        scale.target_size(width, height),
+        filter,
+        |y, target| convert_row(&data[y * stride..y * stride + source_row], target),
+    )
+}
+
+/// Converts a row of BGRX pixels in `source` into RGB pixels in `target`, using the fastest
+/// implementation available.
+///
+/// # Panics
+///
+/// If `source` and `target` don't hold the same amount of pixels.
+pub fn bgrx_row_to_rgb(source: &[u8], target: &mut [u8]) {
+    row_converter()(source, target)
+}
+
+/// The reference implementation of [`bgrx_row_to_rgb`], one pixel at a time.
+///
+/// # Panics
+///
+/// If `source` and `target` don't hold the same amount of pixels.
+pub fn bgrx_row_to_rgb_scalar(source: &[u8], target: &mut [u8]) {
+    assert_eq!(source.len() / 4, target.len() / 3);
+
+    for (bgrx, rgb) in source.chunks_exact(4).zip(target.chunks_exact_mut(3)) {
+        rgb[0] = bgrx[2];
+        rgb[1] = bgrx[1];
+        rgb[2] = bgrx[0];
+    }
+}
+
+type RowConverter = fn(&[u8], &mut [u8]);
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn row_converter() -> RowConverter {
+    if is_x86_feature_detected!("avx2") {
+        x86::convert_row_avx2
+    } else if is_x86_feature_detected!("sse2") {
+        x86::convert_row_sse2
+    } else {
+        bgrx_row_to_rgb_scalar
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn row_converter() -> RowConverter {
+    aarch64::convert_row_neon
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+fn row_converter() -> RowConverter {
+    bgrx_row_to_rgb_scalar
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    pub(super) fn convert_row_avx2(source: &[u8], target: &mut [u8]) {
+        assert_eq!(source.len() / 4, target.len() / 3);
+        // Only picked after checking the CPU supports AVX2.
+        unsafe { avx2(source, target) }
+    }
+
+    pub(super) fn convert_row_sse2(source: &[u8], target: &mut [u8]) {
+        assert_eq!(source.len() / 4, target.len() / 3);
+        // Only picked after checking the CPU supports SSE2.
+        unsafe { sse2(source, target) }
+    }
+
+    /// Eight pixels at a time, every iteration writes 8 bytes past the pixels it converts
+    /// which the next one overwrites, so it stops while there's still room for that.
+    #[target_feature(enable = "avx2")]
+    unsafe fn avx2(source: &[u8], target: &mut [u8]) {
+        let pixels = source.len() / 4;
+        let mut i = 0;
+
+        // Packs the RGB bytes of each half's four pixels into its first 12 bytes.
+        let shuffle = _mm256_setr_epi8(
+            2, 1, 0, 6, 5, 4, 10, 9, 8, 14, 13, 12, -1, -1, -1, -1, 2, 1, 0, 6, 5, 4, 10, 9, 8, 14,
+            13, 12, -1, -1, -1, -1,
+        );
+        // Moves the 12 bytes of the upper half right after the ones of the lower half.
+        let permute = _mm256_setr_epi32(0, 1, 2, 4, 5, 6, 3, 7);
+
+        while (i + 8) * 3 + 8 <= target.len() && i + 8 <= pixels {
+            let bgrx = _mm256_loadu_si256(source.as_ptr().add(i * 4) as *const __m256i);
+            let rgb = _mm256_permutevar8x32_epi32(_mm256_shuffle_epi8(bgrx, shuffle), permute);
+            _mm256_storeu_si256(target.as_mut_ptr().add(i * 3) as *mut __m256i, rgb);
+            i += 8;
+        }
+
+        sse2(&source[i * 4..], &mut target[i * 3..]);
+    }
+
+    /// Four pixels at a time, every iteration writes 2 bytes past the pixels it converts
+    /// which the next one overwrites, so it stops while there's still room for that.
+    #[target_feature(enable = "sse2")]
+    unsafe fn sse2(source: &[u8], target: &mut [u8]) {
+        let pixels = source.len() / 4;
+        let mut i = 0;
+
+        let green = _mm_set1_epi32(0x0000_ff00);
+        let low = _mm_set1_epi32(0x0000_00ff);
+        let first_pixel = _mm_set1_epi64x(0x0000_0000_00ff_ffff);
+        let second_pixel = _mm_set1_epi64x(0x0000_ffff_ff00_0000);
+
+        while i * 3 + 14 <= target.len() && i + 4 <= pixels {
+            let bgrx = _mm_loadu_si128(source.as_ptr().add(i * 4) as *const __m128i);
+
+            // Swap the blue and red bytes of every pixel, dropping the padding byte.
+            let rgb = _mm_or_si128(
+                _mm_or_si128(
+                    _mm_and_si128(bgrx, green),
+                    _mm_and_si128(_mm_srli_epi32(bgrx, 16), low),
+                ),
+                _mm_slli_epi32(_mm_and_si128(bgrx, low), 16),
+            );
+
+            // Close the gap between the two pixels of each 64-bit half.
+            let packed = _mm_or_si128(
+                _mm_and_si128(rgb, first_pixel),
+                _mm_and_si128(_mm_srli_epi64(rgb, 8), second_pixel),
+            );
+
+            let target = target.as_mut_ptr().add(i * 3);
+            _mm_storel_epi64(target as *mut __m128i, packed);
+            _mm_storel_epi64(target.add(6) as *mut __m128i, _mm_srli_si128(packed, 8));
+            i += 4;
+        }
+
+        super::bgrx_row_to_rgb_scalar(&source[i * 4..], &mut target[i * 3..]);
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64 {
+    use std::arch::aarch64::*;
+
+    /// Sixteen pixels at a time, NEON can load and store interleaved channels directly.
+    pub(super) fn convert_row_neon(source: &[u8], target: &mut [u8]) {
+        assert_eq!(source.len() / 4, target.len() / 3);
+
+        let pixels = source.len() / 4;
+        let mut i = 0;
+
+        // NEON is always available on AArch64.
+        unsafe {
+            while i + 16 <= pixels {
+                let bgrx = vld4q_u8(source.as_ptr().add(i * 4));
+                vst3q_u8(
+                    target.as_mut_ptr().add(i * 3),
+                    uint8x16x3_t(bgrx.2, bgrx.1, bgrx.0),
+                );
+                i += 16;
+            }
+        }
+
+        super::bgrx_row_to_rgb_scalar(&source[i * 4..], &mut target[i * 3..]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Every implementation the CPU can run, not just the one that's picked.
+    fn converters() -> Vec<(&'static str, RowConverter)> {
+        #[allow(unused_mut)]
+        let mut converters: Vec<(&'static str, RowConverter)> =
+            vec![("bgrx_row_to_rgb", bgrx_row_to_rgb)];
+
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("sse2") {
+                converters.push(("sse2", x86::convert_row_sse2));
+            }
+            if is_x86_feature_detected!("avx2") {
+                converters.push(("avx2", x86::convert_row_avx2));
+            }
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        converters.push(("neon", aarch64::convert_row_neon));
+
+        converters
+    }
+
+    /// Rows up to 80 pixels wide, which covers every length of what's left after the last
+    /// block of 4, 8 or 16 pixels.
+    fn rows() -> impl Strategy<Value = Vec<u8>> {
+        (0usize..80).prop_flat_map(|width| prop::collection::vec(any::<u8>(), width * 4))
+    }
+
+    /// An image's size, the padding after each row and enough bytes for all of it.
+    fn images() -> impl Strategy<Value = (u32, u32, usize, Vec<u8>)> {
+        (0u32..70, 0u32..6, 0usize..20).prop_flat_map(|(width, height, padding)| {
+            let stride = width as usize * 4 + padding;
+
+            (
+                Just(width),
+                Just(height),
+                Just(stride),
+                prop::collection::vec(any::<u8>(), stride * height as usize),
+            )
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn rows_match_scalar(source in rows()) {
+            let pixels = source.len() / 4;
+
+            let mut expected = vec![0; pixels * 3];
+            bgrx_row_to_rgb_scalar(&source, &mut expected);
+
+            for (name, convert_row) in converters() {
+                // Whatever follows the row has to be left alone.
+                let mut target = vec![0xaa; pixels * 3 + 32];
+                convert_row(&source, &mut target[..pixels * 3]);
+
+                prop_assert_eq!(&target[..pixels * 3], &expected[..], "{}", name);
+                prop_assert!(target[pixels * 3..].iter().all(|&byte| byte == 0xaa), "{}", name);
+            }
+        }
+
+        #[test]
+        fn images_match_scalar((width, height, stride, data) in images()) {
+            let image = bgrx_to_rgb(&data, stride, width, height);
+
+            let (source_row, target_row) = (width as usize * 4, width as usize * 3);
+            let mut expected = vec![0; target_row * height as usize];
+
+            for y in 0..height as usize {
+                bgrx_row_to_rgb_scalar(
+                    &data[y * stride..y * stride + source_row],
+                    &mut expected[y * target_row..(y + 1) * target_row],
+                );
+            }
+
+            prop_assert_eq!(image.dimensions(), (width, height));
+            prop_assert_eq!(image.into_raw(), expected);
+        }
+    }
+}
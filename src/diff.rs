@@ -0,0 +1,56 @@
+use super::{Rect, RgbImage};
+
+/// Edge length of the square tiles frames are compared in.
+pub const TILE_SIZE: u32 = 64;
+
+/// Compares two frames tile by tile and returns the areas of `current` that differ from
+/// `previous`.
+///
+/// Changed tiles that sit next to each other on the same row are merged into a single
+/// rectangle. If the frames don't have the same dimensions the whole of `current` is returned.
+pub fn changed_regions(previous: &RgbImage, current: &RgbImage, tile_size: u32) -> Vec<Rect> {
+    let (width, height) = current.dimensions();
+
+    if previous.dimensions() != (width, height) {
+        return vec![Rect::new(0, 0, width, height)];
+    }
+
+    let tile_size = tile_size.max(1);
+    let stride = width as usize * 3;
+    let (previous, current) = (previous.as_raw(), current.as_raw());
+
+    let mut regions = vec![];
+
+    for tile_y in (0..height).step_by(tile_size as usize) {
+        let tile_height = tile_size.min(height - tile_y);
+        let mut run: Option<Rect> = None;
+
+        for tile_x in (0..width).step_by(tile_size as usize) {
+            let tile_width = tile_size.min(width - tile_x);
+
+            let (start, end) = (tile_x as usize * 3, (tile_x + tile_width) as usize * 3);
+
+            let changed = (tile_y..tile_y + tile_height).any(|y| {
+                let row = y as usize * stride;
+                previous[row + start..row + end] != current[row + start..row + end]
+            });
+
+            match (changed, run.as_mut()) {
+                (true, Some(rect)) => rect.width += tile_width,
+                (true, None) => {
+                    run = Some(Rect::new(
+                        tile_x as i32,
+                        tile_y as i32,
+                        tile_width,
+                        tile_height,
+                    ))
+                }
+                (false, _) => regions.extend(run.take()),
+            }
+        }
+
+        regions.extend(run);
+    }
+
+    regions
+}
@@ -0,0 +1,319 @@
+use super::{Frame, RgbImage};
+#[cfg(feature = "jpeg")]
+use crate::convert::bgrx_to_rgb;
+#[cfg(any(feature = "png", feature = "jpeg", feature = "qoi"))]
+use image::{ColorType, ImageEncoder};
+use std::{error::Error, fmt, fs::File, io, io::BufWriter, io::Write, path::Path};
+
+/// The formats [`Frame::encode`] can write, each behind the feature of the same name.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+    #[cfg(feature = "png")]
+    Png,
+    /// The only format that's lossy, and the only one `quality` matters for.
+    #[cfg(feature = "jpeg")]
+    Jpeg,
+    /// Lossless WebP, the `webp` feature needs Rust 1.67.
+    #[cfg(feature = "webp")]
+    WebP,
+    #[cfg(feature = "qoi")]
+    Qoi,
+}
+
+impl ImageFormat {
+    /// Picks the format from the extension of `path`, like `png` or `jpg`, ignoring case.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<ImageFormat> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+
+        match extension.as_str() {
+            #[cfg(feature = "png")]
+            "png" => Some(ImageFormat::Png),
+            #[cfg(feature = "jpeg")]
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            #[cfg(feature = "webp")]
+            "webp" => Some(ImageFormat::WebP),
+            #[cfg(feature = "qoi")]
+            "qoi" => Some(ImageFormat::Qoi),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum EncodeError {
+    Io(io::Error),
+    Image(image::ImageError),
+    #[cfg(feature = "webp")]
+    WebP(image_webp::EncodingError),
+    /// The extension of the path doesn't belong to an enabled format.
+    UnknownExtension,
+    /// Capturing the image to encode failed.
+    Capture(crate::Error),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for EncodeError {}
+
+impl From<io::Error> for EncodeError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<image::ImageError> for EncodeError {
+    fn from(error: image::ImageError) -> Self {
+        Self::Image(error)
+    }
+}
+
+impl From<crate::Error> for EncodeError {
+    fn from(error: crate::Error) -> Self {
+        Self::Capture(error)
+    }
+}
+
+#[cfg(feature = "webp")]
+impl From<image_webp::EncodingError> for EncodeError {
+    fn from(error: image_webp::EncodingError) -> Self {
+        Self::WebP(error)
+    }
+}
+
+/// The JPEG quality [`Frame::save`] uses.
+const DEFAULT_QUALITY: u8 = 90;
+
+impl Frame {
+    /// Encodes the frame as `format`. `quality` goes from 1 to 100 and only matters for
+    /// JPEG, every other format is lossless.
+    ///
+    /// To encode a capture that hasn't been converted to RGB yet, use
+    /// [`Capturer::capture_encoded`](crate::Capturer::capture_encoded).
+    pub fn encode(&self, format: ImageFormat, quality: u8) -> Result<Vec<u8>, EncodeError> {
+        let mut encoded = vec![];
+        self.encode_to(&mut encoded, format, quality)?;
+        Ok(encoded)
+    }
+
+    /// Encodes the frame into `writer`, see [`Frame::encode`].
+    pub fn encode_to(
+        &self,
+        writer: impl Write,
+        format: ImageFormat,
+        quality: u8,
+    ) -> Result<(), EncodeError> {
+        encode(&self.image, writer, format, quality)
+    }
+
+    /// Saves the frame to `path` in the format its extension names, JPEGs with a quality of
+    /// 90.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), EncodeError> {
+        let path = path.as_ref();
+        let format = ImageFormat::from_path(path).ok_or(EncodeError::UnknownExtension)?;
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.encode_to(&mut writer, format, DEFAULT_QUALITY)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+#[allow(unused_variables)]
+pub(crate) fn encode(
+    image: &RgbImage,
+    writer: impl Write,
+    format: ImageFormat,
+    quality: u8,
+) -> Result<(), EncodeError> {
+    let (width, height) = image.dimensions();
+    let pixels = image.as_raw();
+
+    match format {
+        #[cfg(feature = "png")]
+        ImageFormat::Png => image::codecs::png::PngEncoder::new(writer).write_image(
+            pixels,
+            width,
+            height,
+            ColorType::Rgb8,
+        )?,
+        #[cfg(feature = "jpeg")]
+        ImageFormat::Jpeg => {
+            image::codecs::jpeg::JpegEncoder::new_with_quality(writer, quality.clamp(1, 100))
+                .write_image(pixels, width, height, ColorType::Rgb8)?
+        }
+        #[cfg(feature = "webp")]
+        ImageFormat::WebP => image_webp::WebPEncoder::new(writer).encode(
+            pixels,
+            width,
+            height,
+            image_webp::ColorType::Rgb8,
+        )?,
+        #[cfg(feature = "qoi")]
+        ImageFormat::Qoi => image::codecs::qoi::QoiEncoder::new(writer).write_image(
+            pixels,
+            width,
+            height,
+            ColorType::Rgb8,
+        )?,
+    }
+
+    Ok(())
+}
+
+/// Encodes `height` rows of `width` BGRX pixels which start `stride` bytes apart, the way
+/// most platforms capture them, as `format`. `quality` is the same as for [`Frame::encode`].
+///
+/// QOI is written straight from the BGRX pixels. PNG and WebP get them as RGBA, which is only
+/// a swap of two bytes per pixel, and JPEG as RGB.
+#[allow(unused_variables, unused_mut)]
+pub fn encode_bgrx(
+    data: &[u8],
+    stride: usize,
+    width: u32,
+    height: u32,
+    mut writer: impl Write,
+    format: ImageFormat,
+    quality: u8,
+) -> Result<(), EncodeError> {
+    match format {
+        #[cfg(feature = "png")]
+        ImageFormat::Png => image::codecs::png::PngEncoder::new(writer).write_image(
+            &bgrx_to_rgba(data, stride, width, height),
+            width,
+            height,
+            ColorType::Rgba8,
+        )?,
+        #[cfg(feature = "jpeg")]
+        ImageFormat::Jpeg => encode(
+            &bgrx_to_rgb(data, stride, width, height),
+            writer,
+            format,
+            quality,
+        )?,
+        #[cfg(feature = "webp")]
+        ImageFormat::WebP => image_webp::WebPEncoder::new(writer).encode(
+            &bgrx_to_rgba(data, stride, width, height),
+            width,
+            height,
+            image_webp::ColorType::Rgba8,
+        )?,
+        #[cfg(feature = "qoi")]
+        ImageFormat::Qoi => encode_qoi(data, stride, width, height, &mut writer)?,
+    }
+
+    Ok(())
+}
+
+#[cfg(any(feature = "png", feature = "webp"))]
+fn bgrx_to_rgba(data: &[u8], stride: usize, width: u32, height: u32) -> Vec<u8> {
+    let row = width as usize * 4;
+    let mut rgba = vec![0; row * height as usize];
+
+    if row == 0 {
+        return rgba;
+    }
+
+    for (y, target) in rgba.chunks_exact_mut(row).enumerate() {
+        for (source, target) in data[y * stride..y * stride + row]
+            .chunks_exact(4)
+            .zip(target.chunks_exact_mut(4))
+        {
+            target.copy_from_slice(&[source[2], source[1], source[0], 255]);
+        }
+    }
+
+    rgba
+}
+
+/// Writes the BGRX pixels as a QOI image, see <https://qoiformat.org/qoi-specification.pdf>.
+/// It's simple enough that going through an encoder would only cost a copy of the image.
+#[cfg(feature = "qoi")]
+fn encode_qoi(
+    data: &[u8],
+    stride: usize,
+    width: u32,
+    height: u32,
+    writer: &mut impl Write,
+) -> io::Result<()> {
+    let mut header = *b"qoif\0\0\0\0\0\0\0\0\x03\x00";
+    header[4..8].copy_from_slice(&width.to_be_bytes());
+    header[8..12].copy_from_slice(&height.to_be_bytes());
+    writer.write_all(&header)?;
+
+    // Like in the spec, every slot starts out as transparent black, which no pixel here is.
+    let mut index = [[0u8; 4]; 64];
+    let mut previous = [0u8; 3];
+    let mut run = 0u8;
+    let mut chunks = Vec::with_capacity(width as usize * 4 + 1);
+
+    for y in 0..height as usize {
+        let row = &data[y * stride..y * stride + width as usize * 4];
+
+        for bgrx in row.chunks_exact(4) {
+            let pixel = [bgrx[2], bgrx[1], bgrx[0]];
+
+            if pixel == previous {
+                run += 1;
+
+                if run == 62 {
+                    chunks.push(0xc0 | (run - 1));
+                    run = 0;
+                }
+
+                continue;
+            }
+
+            if run > 0 {
+                chunks.push(0xc0 | (run - 1));
+                run = 0;
+            }
+
+            // The alpha of 255 is part of the hash even without an alpha channel.
+            let hash =
+                (pixel[0] as usize * 3 + pixel[1] as usize * 5 + pixel[2] as usize * 7 + 255 * 11)
+                    % 64;
+
+            let rgba = [pixel[0], pixel[1], pixel[2], 255];
+
+            if index[hash] == rgba {
+                chunks.push(hash as u8);
+            } else {
+                index[hash] = rgba;
+
+                let [dr, dg, db] = [0, 1, 2].map(|c| pixel[c].wrapping_sub(previous[c]) as i8);
+                let (dr_dg, db_dg) = (dr.wrapping_sub(dg), db.wrapping_sub(dg));
+
+                if [dr, dg, db].iter().all(|d| (-2..=1).contains(d)) {
+                    chunks.push(
+                        0x40 | ((dr + 2) as u8) << 4 | ((dg + 2) as u8) << 2 | (db + 2) as u8,
+                    );
+                } else if (-32..=31).contains(&dg)
+                    && (-8..=7).contains(&dr_dg)
+                    && (-8..=7).contains(&db_dg)
+                {
+                    chunks.push(0x80 | (dg + 32) as u8);
+                    chunks.push(((dr_dg + 8) as u8) << 4 | (db_dg + 8) as u8);
+                } else {
+                    chunks.extend_from_slice(&[0xfe, pixel[0], pixel[1], pixel[2]]);
+                }
+            }
+
+            previous = pixel;
+        }
+
+        writer.write_all(&chunks)?;
+        chunks.clear();
+    }
+
+    if run > 0 {
+        chunks.push(0xc0 | (run - 1));
+    }
+
+    chunks.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+    writer.write_all(&chunks)
+}
@@ -0,0 +1,345 @@
+use super::{Rect, RgbImage};
+use std::cmp::Ordering;
+
+/// Where a template was found on the screen, see
+/// [`Capturer::find_on_screen`](crate::Capturer::find_on_screen).
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Match {
+    /// The index of the display the template was found on.
+    pub display: usize,
+    /// The area the template covers, in the same coordinates as the displays' positions, see
+    /// [`Capturer::desktop_space`](crate::Capturer::desktop_space).
+    pub bounds: Rect,
+    /// The normalized cross-correlation between the template and the area, up to 1 for a
+    /// perfect match.
+    pub score: f64,
+}
+
+/// The search starts on a copy scaled down until the template's shorter side is about this
+/// long, and is refined from there.
+const MIN_TEMPLATE_SIZE: u32 = 6;
+
+/// How many times the image and template are halved at most.
+const MAX_LEVELS: usize = 3;
+
+/// How many of the best places on a scaled down copy are followed up on the next larger one.
+/// Scaling blurs away detail, so a match can score lower there than places that aren't one,
+/// which is why this doesn't go by the threshold.
+const MAX_CANDIDATES: usize = 256;
+
+/// How far from twice its position on the next smaller copy a match is looked for, a pixel
+/// there covers two here and the scaling can shift it by one more.
+const REFINE_RADIUS: u32 = 1;
+
+/// Looks for `template` in `image` and returns the areas it appears in with their scores,
+/// the best first. Only the brightness is compared, using the normalized cross-correlation,
+/// which goes from -1 to 1 and isn't thrown off by the image being a bit brighter or
+/// darker. Areas that score below `threshold` are left out, `0.9` is a good start.
+///
+/// Large templates are first searched for on scaled down copies of both, and only the
+/// promising places are compared at full size. Overlapping matches are reduced to the best
+/// one among them.
+pub fn find_template(image: &RgbImage, template: &RgbImage, threshold: f64) -> Vec<(Rect, f64)> {
+    let (width, height) = template.dimensions();
+
+    if width == 0 || height == 0 || width > image.width() || height > image.height() {
+        return vec![];
+    }
+
+    let mut images = vec![Gray::new(image)];
+    let mut templates = vec![Gray::new(template)];
+
+    while images.len() <= MAX_LEVELS
+        && templates
+            .last()
+            .unwrap()
+            .width
+            .min(templates.last().unwrap().height)
+            >= MIN_TEMPLATE_SIZE * 2
+    {
+        images.push(images.last().unwrap().half());
+        templates.push(templates.last().unwrap().half());
+    }
+
+    let coarsest = images.len() - 1;
+    let coarse_threshold = if coarsest == 0 {
+        threshold
+    } else {
+        f64::NEG_INFINITY
+    };
+
+    let mut candidates = search(&images[coarsest], &templates[coarsest], coarse_threshold);
+
+    for level in (0..coarsest).rev() {
+        sort_by_score(&mut candidates);
+        candidates.truncate(MAX_CANDIDATES);
+
+        let (image, template) = (&images[level], &templates[level]);
+
+        candidates = candidates
+            .into_iter()
+            .filter_map(|(x, y, _)| refine(image, template, x * 2, y * 2))
+            .collect();
+
+        // Neighbouring places often end up on the same one.
+        candidates.sort_by_key(|&(x, y, _)| (x, y));
+        candidates.dedup_by_key(|&mut (x, y, _)| (x, y));
+    }
+
+    candidates.retain(|&(_, _, score)| score >= threshold);
+    sort_by_score(&mut candidates);
+
+    let mut matches: Vec<(Rect, f64)> = vec![];
+
+    for (x, y, score) in candidates {
+        let bounds = Rect::new(x as i32, y as i32, width, height);
+
+        // Matches closer than half the template to a better one are the same match.
+        let duplicate = matches.iter().any(|(other, _)| {
+            (other.x - bounds.x).unsigned_abs() < width / 2
+                && (other.y - bounds.y).unsigned_abs() < height / 2
+        });
+
+        if !duplicate {
+            matches.push((bounds, score));
+        }
+    }
+
+    matches
+}
+
+/// Puts the best scores first.
+fn sort_by_score(candidates: &mut [(u32, u32, f64)]) {
+    candidates.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(Ordering::Equal));
+}
+
+/// Scores every position of `template` in `image` and returns the local maxima that reach
+/// `threshold`.
+fn search(image: &Gray, template: &Gray, threshold: f64) -> Vec<(u32, u32, f64)> {
+    let (columns, rows) = (
+        image.width - template.width + 1,
+        image.height - template.height + 1,
+    );
+    let integral = Integral::new(image);
+    let template_stats = template.stats();
+
+    let scores: Vec<f64> = (0..rows)
+        .flat_map(|y| (0..columns).map(move |x| (x, y)))
+        .map(|(x, y)| score(image, &integral, template, template_stats, x, y))
+        .collect();
+
+    let mut peaks = vec![];
+
+    for y in 0..rows {
+        for x in 0..columns {
+            let score = scores[(y * columns + x) as usize];
+
+            if score < threshold {
+                continue;
+            }
+
+            let is_peak = (y.saturating_sub(1)..(y + 2).min(rows)).all(|ny| {
+                (x.saturating_sub(1)..(x + 2).min(columns))
+                    .all(|nx| scores[(ny * columns + nx) as usize] <= score)
+            });
+
+            if is_peak {
+                peaks.push((x, y, score));
+            }
+        }
+    }
+
+    peaks
+}
+
+/// Scores the positions within [`REFINE_RADIUS`] of `(x, y)`, where a match on the next
+/// smaller copy ends up, and returns the best one.
+fn refine(image: &Gray, template: &Gray, x: u32, y: u32) -> Option<(u32, u32, f64)> {
+    let (last_x, last_y) = (image.width - template.width, image.height - template.height);
+    let integral = Integral::around(image, x, y, template);
+    let template_stats = template.stats();
+
+    let mut best: Option<(u32, u32, f64)> = None;
+
+    for y in y.saturating_sub(REFINE_RADIUS)..=(y + REFINE_RADIUS).min(last_y) {
+        for x in x.saturating_sub(REFINE_RADIUS)..=(x + REFINE_RADIUS).min(last_x) {
+            let score = score(image, &integral, template, template_stats, x, y);
+
+            if best.map_or(true, |(_, _, best)| score > best) {
+                best = Some((x, y, score));
+            }
+        }
+    }
+
+    best
+}
+
+/// The normalized cross-correlation of `template` and the area of `image` at `(x, y)`.
+///
+/// A template without any contrast can't be correlated, it matches areas without contrast
+/// by how close their brightness is instead.
+fn score(
+    image: &Gray,
+    integral: &Integral,
+    template: &Gray,
+    (template_mean, template_deviation): (f64, f64),
+    x: u32,
+    y: u32,
+) -> f64 {
+    let count = (template.width * template.height) as f64;
+    let (sum, squares) = integral.sum(x, y, template.width, template.height);
+
+    let mean = sum / count;
+    let deviation = (squares - sum * mean).max(0.0).sqrt();
+
+    // Less than a tenth of a brightness level per pixel, which is rounding noise.
+    let flat = count.sqrt() * 0.1;
+
+    if template_deviation < flat || deviation < flat {
+        return if template_deviation < flat && deviation < flat {
+            1.0 - (mean - template_mean).abs() / 255.0
+        } else {
+            0.0
+        };
+    }
+
+    let mut product = 0.0;
+
+    for row in 0..template.height {
+        let image_row = image.row(y + row);
+        let template_row = template.row(row);
+
+        product += image_row[x as usize..(x + template.width) as usize]
+            .iter()
+            .zip(template_row)
+            .map(|(&a, &b)| a as f64 * b as f64)
+            .sum::<f64>();
+    }
+
+    ((product - count * mean * template_mean) / (deviation * template_deviation)).clamp(-1.0, 1.0)
+}
+
+/// The brightness of an image's pixels, from 0 to 255.
+struct Gray {
+    width: u32,
+    height: u32,
+    pixels: Vec<f32>,
+}
+
+impl Gray {
+    fn new(image: &RgbImage) -> Self {
+        Self {
+            width: image.width(),
+            height: image.height(),
+            pixels: image
+                .as_raw()
+                .chunks_exact(3)
+                .map(|rgb| 0.299 * rgb[0] as f32 + 0.587 * rgb[1] as f32 + 0.114 * rgb[2] as f32)
+                .collect(),
+        }
+    }
+
+    fn row(&self, y: u32) -> &[f32] {
+        let start = (y * self.width) as usize;
+        &self.pixels[start..start + self.width as usize]
+    }
+
+    /// Averages every 2x2 block into a pixel, a last odd row or column is dropped.
+    fn half(&self) -> Self {
+        let (width, height) = (self.width / 2, self.height / 2);
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+
+        for y in 0..height {
+            let (top, bottom) = (self.row(y * 2), self.row(y * 2 + 1));
+
+            for x in 0..width as usize {
+                pixels
+                    .push((top[x * 2] + top[x * 2 + 1] + bottom[x * 2] + bottom[x * 2 + 1]) / 4.0);
+            }
+        }
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// The mean and the square root of the sum of squared differences from it.
+    fn stats(&self) -> (f64, f64) {
+        let count = self.pixels.len() as f64;
+        let mean = self.pixels.iter().map(|&value| value as f64).sum::<f64>() / count;
+        let squares = self
+            .pixels
+            .iter()
+            .map(|&value| (value as f64 - mean) * (value as f64 - mean))
+            .sum::<f64>();
+
+        (mean, squares.sqrt())
+    }
+}
+
+/// Running sums of the brightness and its square over part of an image, so the sums under
+/// any area within it take four lookups.
+struct Integral {
+    left: u32,
+    top: u32,
+    /// One more than the width of the part, the first row and column are zero.
+    stride: usize,
+    sums: Vec<(f64, f64)>,
+}
+
+impl Integral {
+    fn new(image: &Gray) -> Self {
+        Self::of_area(image, 0, 0, image.width, image.height)
+    }
+
+    /// Covers the places [`refine`] looks at around `(x, y)`.
+    fn around(image: &Gray, x: u32, y: u32, template: &Gray) -> Self {
+        let (left, top) = (
+            x.saturating_sub(REFINE_RADIUS),
+            y.saturating_sub(REFINE_RADIUS),
+        );
+        let right = (x + REFINE_RADIUS + template.width).min(image.width);
+        let bottom = (y + REFINE_RADIUS + template.height).min(image.height);
+
+        Self::of_area(image, left, top, right - left, bottom - top)
+    }
+
+    fn of_area(image: &Gray, left: u32, top: u32, width: u32, height: u32) -> Self {
+        let stride = width as usize + 1;
+        let mut sums = vec![(0.0, 0.0); stride * (height as usize + 1)];
+
+        for y in 0..height as usize {
+            let row = &image.row(top + y as u32)[left as usize..(left + width) as usize];
+            let (mut sum, mut squares) = (0.0, 0.0);
+
+            for (x, &value) in row.iter().enumerate() {
+                sum += value as f64;
+                squares += value as f64 * value as f64;
+
+                let above = sums[y * stride + x + 1];
+                sums[(y + 1) * stride + x + 1] = (above.0 + sum, above.1 + squares);
+            }
+        }
+
+        Self {
+            left,
+            top,
+            stride,
+            sums,
+        }
+    }
+
+    /// The sums of the brightness and its square under the area at `(x, y)` in the image.
+    fn sum(&self, x: u32, y: u32, width: u32, height: u32) -> (f64, f64) {
+        let (x, y) = ((x - self.left) as usize, (y - self.top) as usize);
+        let (right, bottom) = (x + width as usize, y + height as usize);
+
+        let at = |x: usize, y: usize| self.sums[y * self.stride + x];
+        let (a, b, c, d) = (at(x, y), at(right, y), at(x, bottom), at(right, bottom));
+
+        (d.0 - b.0 - c.0 + a.0, d.1 - b.1 - c.1 + a.1)
+    }
+}
@@ -0,0 +1,95 @@
+use super::{CoordinateSpace, Display, Rect, RgbImage};
+use std::time::{Duration, SystemTime};
+
+/// A capture of a display together with where, when and how it was taken, see
+/// [`Capturer::capture_frame`](crate::Capturer::capture_frame).
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub image: RgbImage,
+    /// The index of the display the frame is from.
+    pub index: usize,
+    /// The display as it was when the frame was captured.
+    pub display: Display,
+    /// When the capture was started, before anything was requested from the platform.
+    pub started_at: SystemTime,
+    /// When the pixels were converted and the masks hidden.
+    pub finished_at: SystemTime,
+    pub backend: Backend,
+    /// Where the cursor was when the capture was started, in the same coordinates as the
+    /// display's position, or `None` if the platform didn't say or it's on another screen.
+    pub cursor: Option<(i32, i32)>,
+}
+
+impl Frame {
+    /// How long the capture took.
+    pub fn duration(&self) -> Duration {
+        self.finished_at
+            .duration_since(self.started_at)
+            .unwrap_or_default()
+    }
+
+    /// Returns the pixel of `image` the cursor is on, if it's on this display.
+    ///
+    /// ```
+    /// use captis::{Capturer, Display, Layout, MockCapturer};
+    ///
+    /// let display = Display::from_pixels(100, 0, 200, 100).with_scale_factor(2.0);
+    /// let capturer = MockCapturer::new(Layout {
+    ///     displays: vec![display],
+    ///     primary: 0,
+    /// });
+    ///
+    /// // Like on X11 and Windows, the position is already in physical pixels.
+    /// capturer.set_cursor(Some((250, 40)));
+    ///
+    /// let frame = capturer.capture_frame(0).unwrap();
+    /// assert_eq!(frame.cursor_in_image(), Some((150, 40)));
+    /// ```
+    pub fn cursor_in_image(&self) -> Option<(u32, u32)> {
+        let (x, y) = self.cursor?;
+
+        let pixel = self
+            .display
+            .from_desktop(Rect::new(x, y, 1, 1), self.backend.coordinate_space());
+
+        if pixel.x < 0
+            || pixel.y < 0
+            || pixel.x as u32 >= self.image.width()
+            || pixel.y as u32 >= self.image.height()
+        {
+            return None;
+        }
+
+        Some((pixel.x as u32, pixel.y as u32))
+    }
+}
+
+/// How a [`Frame`] was captured.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Backend {
+    /// The X server wrote the pixels into memory shared with it, through MIT-SHM.
+    Shm,
+    /// The X server sent the pixels over the connection.
+    Standard,
+    /// The X server sent the windows one by one to leave out the excluded ones, see
+    /// [`Capturer::exclude_windows`](crate::Capturer::exclude_windows).
+    Composited,
+    /// GDI copied the pixels into a bitmap, on Windows.
+    Gdi,
+    /// CoreGraphics took a screenshot, on macOS.
+    CoreGraphics,
+    /// A [`MockCapturer`](crate::MockCapturer) handed out its frame.
+    Mock,
+}
+
+impl Backend {
+    /// Returns what the backend measures the positions of the displays and the cursor in,
+    /// only CoreGraphics uses points, which are logical pixels.
+    pub fn coordinate_space(&self) -> CoordinateSpace {
+        match self {
+            Backend::CoreGraphics => CoordinateSpace::Logical,
+            _ => CoordinateSpace::Physical,
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use super::{
+    encode::{encode, ImageFormat},
+    ssim, Capturer, CoordinateSpace, EncodeError, Error as CaptureError, Rect, RgbImage,
+};
+use std::{
+    env,
+    error::Error,
+    fmt, fs,
+    fs::File,
+    io,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// Setting this environment variable to anything but `0` or nothing makes every check save
+/// what it was given as the new golden image instead of comparing against the old one.
+pub const UPDATE_GOLDEN: &str = "CAPTIS_UPDATE_GOLDEN";
+
+#[derive(Debug)]
+pub enum GoldenError {
+    Io(io::Error),
+    Capture(CaptureError),
+    Encode(EncodeError),
+    /// The golden image couldn't be decoded.
+    Image(image::ImageError),
+    /// There's no golden image at `path` yet, the capture was saved to `actual`.
+    Missing {
+        path: PathBuf,
+        actual: PathBuf,
+    },
+    /// The capture isn't the size of the golden image at all.
+    SizeMismatch {
+        expected: (u32, u32),
+        found: (u32, u32),
+        actual: PathBuf,
+    },
+    /// More pixels differ than allowed, or the images aren't similar enough. The differing
+    /// pixels are red in the image at `diff`, over a faded copy of the golden one.
+    Mismatch {
+        mismatched: usize,
+        total: usize,
+        ssim: Option<f64>,
+        actual: PathBuf,
+        diff: PathBuf,
+    },
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for GoldenError {}
+
+impl From<io::Error> for GoldenError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<CaptureError> for GoldenError {
+    fn from(error: CaptureError) -> Self {
+        Self::Capture(error)
+    }
+}
+
+impl From<EncodeError> for GoldenError {
+    fn from(error: EncodeError) -> Self {
+        Self::Encode(error)
+    }
+}
+
+impl From<image::ImageError> for GoldenError {
+    fn from(error: image::ImageError) -> Self {
+        Self::Image(error)
+    }
+}
+
+/// Compares captures against golden images, PNG files stored under a directory by name.
+///
+/// On a mismatch the capture and an image marking the differing pixels are saved under
+/// `failures/` in the same directory, so CI can keep them as artifacts. Running with
+/// [`UPDATE_GOLDEN`] set saves the captures as the new golden images instead.
+///
+/// By default every pixel has to match exactly, [`Golden::channel_tolerance`],
+/// [`Golden::max_mismatched`] and [`Golden::min_ssim`] loosen that for antialiasing and
+/// font rendering differences.
+///
+/// ```no_run
+/// use captis::{init_capturer, Golden};
+///
+/// let capturer = init_capturer().expect("Couldn't Initialize Capturer");
+///
+/// Golden::new("tests/golden")
+///     .channel_tolerance(16)
+///     .max_mismatched(0.001)
+///     .check_display(&capturer, 0, "main-window")
+///     .expect("The Screen Changed");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Golden {
+    dir: PathBuf,
+    failures_dir: PathBuf,
+    channel_tolerance: u8,
+    max_mismatched: f64,
+    min_ssim: Option<f64>,
+    update: bool,
+}
+
+impl Golden {
+    /// Golden images are read from and written to `dir`, whether they're updated is decided
+    /// by [`UPDATE_GOLDEN`].
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+
+        Self {
+            failures_dir: dir.join("failures"),
+            dir,
+            channel_tolerance: 0,
+            max_mismatched: 0.0,
+            min_ssim: None,
+            update: env::var_os(UPDATE_GOLDEN)
+                .map_or(false, |value| !value.is_empty() && value != "0"),
+        }
+    }
+
+    /// Saves the captures and diffs of failed checks in `dir` instead of `failures/`.
+    pub fn failures_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.failures_dir = dir.into();
+        self
+    }
+
+    /// Pixels whose channels all differ by at most this much still count as matching.
+    pub fn channel_tolerance(mut self, tolerance: u8) -> Self {
+        self.channel_tolerance = tolerance;
+        self
+    }
+
+    /// The share of pixels, from 0 to 1, that may differ before a check fails.
+    pub fn max_mismatched(mut self, ratio: f64) -> Self {
+        self.max_mismatched = ratio;
+        self
+    }
+
+    /// Also fails a check if [`ssim`] rates the capture below this.
+    pub fn min_ssim(mut self, min_ssim: f64) -> Self {
+        self.min_ssim = Some(min_ssim);
+        self
+    }
+
+    /// Overrides [`UPDATE_GOLDEN`].
+    pub fn update(mut self, update: bool) -> Self {
+        self.update = update;
+        self
+    }
+
+    /// Where the golden image called `name` is stored, `name` may contain slashes to put it
+    /// into a subdirectory.
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.png", name))
+    }
+
+    /// Captures the display at `index` and checks it against the golden image `name`.
+    pub fn check_display<C: Capturer + ?Sized>(
+        &self,
+        capturer: &C,
+        index: usize,
+        name: &str,
+    ) -> Result<(), GoldenError> {
+        self.check(name, &capturer.capture(index)?)
+    }
+
+    /// Captures `region` of the display at `index` and checks it against the golden image
+    /// `name`, see [`Capturer::capture_region`].
+    pub fn check_region<C: Capturer + ?Sized>(
+        &self,
+        capturer: &C,
+        index: usize,
+        region: Rect,
+        space: CoordinateSpace,
+        name: &str,
+    ) -> Result<(), GoldenError> {
+        self.check(name, &capturer.capture_region(index, region, space)?)
+    }
+
+    /// Captures the contents of `window` and checks them against the golden image `name`,
+    /// see [`X11Capturer::capture_drawable`](crate::X11Capturer::capture_drawable).
+    #[cfg(target_os = "linux")]
+    pub fn check_window(
+        &self,
+        capturer: &crate::X11Capturer,
+        window: x11rb::protocol::xproto::Window,
+        name: &str,
+    ) -> Result<(), GoldenError> {
+        self.check(name, &capturer.capture_drawable(window, None)?)
+    }
+
+    /// Checks `image` against the golden image `name`, or saves it as the golden image when
+    /// updating.
+    pub fn check(&self, name: &str, image: &RgbImage) -> Result<(), GoldenError> {
+        let path = self.path(name);
+        let actual_path = self.failures_dir.join(format!("{}.actual.png", name));
+        let diff_path = self.failures_dir.join(format!("{}.diff.png", name));
+
+        if self.update {
+            save(&path, image)?;
+            return remove_failures(&actual_path, &diff_path);
+        }
+
+        if !path.exists() {
+            save(&actual_path, image)?;
+
+            return Err(GoldenError::Missing {
+                path,
+                actual: actual_path,
+            });
+        }
+
+        let expected = image::open(&path)?.to_rgb8();
+
+        if expected.dimensions() != image.dimensions() {
+            save(&actual_path, image)?;
+
+            return Err(GoldenError::SizeMismatch {
+                expected: expected.dimensions(),
+                found: image.dimensions(),
+                actual: actual_path,
+            });
+        }
+
+        let (diff, mismatched) = diff(&expected, image, self.channel_tolerance);
+        let total = (image.width() * image.height()) as usize;
+
+        let ssim = self.min_ssim.map(|_| ssim(&expected, image));
+
+        let too_many = mismatched as f64 > self.max_mismatched * total as f64;
+        let too_different = matches!((ssim, self.min_ssim), (Some(ssim), Some(min)) if ssim < min);
+
+        if too_many || too_different {
+            save(&actual_path, image)?;
+            save(&diff_path, &diff)?;
+
+            return Err(GoldenError::Mismatch {
+                mismatched,
+                total,
+                ssim,
+                actual: actual_path,
+                diff: diff_path,
+            });
+        }
+
+        remove_failures(&actual_path, &diff_path)
+    }
+}
+
+/// Marks the pixels of `actual` that differ from `expected` by more than `tolerance` in red
+/// over a faded, grey copy of `expected`, and counts them.
+fn diff(expected: &RgbImage, actual: &RgbImage, tolerance: u8) -> (RgbImage, usize) {
+    let mut diff = RgbImage::new(expected.width(), expected.height());
+    let mut mismatched = 0;
+
+    for ((target, expected), actual) in diff
+        .chunks_exact_mut(3)
+        .zip(expected.as_raw().chunks_exact(3))
+        .zip(actual.as_raw().chunks_exact(3))
+    {
+        let differs = expected
+            .iter()
+            .zip(actual)
+            .any(|(&expected, &actual)| expected.max(actual) - expected.min(actual) > tolerance);
+
+        if differs {
+            mismatched += 1;
+            target.copy_from_slice(&[255, 0, 0]);
+        } else {
+            let luma =
+                (expected[0] as u32 * 299 + expected[1] as u32 * 587 + expected[2] as u32 * 114)
+                    / 1000;
+            let faded = (255 - (255 - luma) / 3) as u8;
+            target.copy_from_slice(&[faded; 3]);
+        }
+    }
+
+    (diff, mismatched)
+}
+
+fn save(path: &Path, image: &RgbImage) -> Result<(), GoldenError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    encode(image, &mut writer, ImageFormat::Png, 0)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Removes what an earlier failed check left behind.
+fn remove_failures(actual: &Path, diff: &Path) -> Result<(), GoldenError> {
+    for path in [actual, diff] {
+        match fs::remove_file(path) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error.into()),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
@@ -16,6 +16,8 @@ pub type ProportionType = i32;
 #[cfg(target_os = "linux")]
 mod linux;
 
+#[cfg(target_os = "linux")]
+pub use linux::{X11Capturer, X11Options};
 #[cfg(target_os = "linux")]
 pub use x11rb;
 
@@ -43,61 +45,516 @@ pub type CoordinateType = f64;
 #[cfg(target_os = "macos")]
 pub type ProportionType = f64;
 
-pub use image::RgbImage;
+pub use image::{Rgb, RgbImage};
 
-#[derive(Debug, Copy, Clone)]
-#[repr(C)]
-pub(crate) struct Bgr {
-    b: u8,
-    g: u8,
-    r: u8,
-    _padding: u8,
-}
+use std::{cmp::Ordering, time::SystemTime};
+
+pub mod convert;
+
+mod color;
+
+pub use color::ColorStats;
+
+mod diff;
+
+pub use diff::{changed_regions, TILE_SIZE};
+
+mod find;
+
+pub use find::{find_template, Match};
+
+mod frame;
+
+pub use frame::{Backend, Frame};
+
+#[cfg(any(feature = "png", feature = "jpeg", feature = "webp", feature = "qoi"))]
+mod encode;
+
+#[cfg(any(feature = "png", feature = "jpeg", feature = "webp", feature = "qoi"))]
+pub use encode::{encode_bgrx, EncodeError, ImageFormat};
+
+#[cfg(feature = "golden")]
+mod golden;
+
+#[cfg(feature = "golden")]
+pub use golden::{Golden, GoldenError, UPDATE_GOLDEN};
+
+mod mock;
+
+pub use mock::MockCapturer;
+
+mod redact;
+
+pub use redact::{Mask, RedactStyle, RedactionId, Redactions};
+
+mod scale;
+
+pub use scale::{Scale, ScaleFilter};
+
+mod similarity;
+
+pub use similarity::{average_hash, difference_hash, perceptual_hash, ssim, ImageHash, SSIM_SIZE};
+
+mod stream;
+
+pub use stream::FrameStream;
+
+mod wait;
 
-pub trait Capturer {
+pub use wait::{WaitFuture, Waiter};
+
+mod record;
+
+pub use record::{RecordControls, RecordError, Recorder, Recording, VideoFormat};
+
+#[cfg(feature = "replay")]
+mod replay;
+
+#[cfg(feature = "replay")]
+pub use replay::ReplayBuffer;
+
+#[cfg(any(feature = "vnc", feature = "http-preview"))]
+mod broadcast;
+
+#[cfg(any(feature = "vnc", feature = "http-preview"))]
+pub use broadcast::ServerError;
+
+#[cfg(feature = "vnc")]
+mod vnc;
+
+#[cfg(feature = "vnc")]
+pub use vnc::VncServer;
+
+#[cfg(feature = "http-preview")]
+mod preview;
+
+#[cfg(feature = "http-preview")]
+pub use preview::PreviewServer;
+
+/// Captures the displays of the screen.
+///
+/// Capturers are `Send` and `Sync`, so one can be moved into a worker thread or shared behind
+/// an `Arc`. Captures from several threads at once are safe, though on Linux and Windows they
+/// take turns since they go through the same buffer.
+pub trait Capturer: Send + Sync {
     /// Returns a single image from the selected display.
     fn capture(&self, index: usize) -> Result<RgbImage, Error>;
+    /// Captures the display at `index` like [`Capturer::capture`], together with when it
+    /// was captured, how, and where the cursor was.
+    fn capture_frame(&self, index: usize) -> Result<Frame, Error>;
     /// Captures a single image from the primary display.
     fn capture_primary(&self) -> Result<RgbImage, Error>;
     /// Captures a single image from all the displays available and returns them.
     fn capture_all(&self) -> Result<Vec<RgbImage>, Error>;
+    /// Captures all the displays available as close to the same moment as the platform allows
+    /// and returns them together with the time they were captured at.
+    fn capture_all_at_once(&self) -> Result<(SystemTime, Vec<RgbImage>), Error> {
+        let captured_at = SystemTime::now();
+        Ok((captured_at, self.capture_all()?))
+    }
+    /// Captures the display at `index` resized to `scale`, the resizing happens while the
+    /// pixels are converted so the full-size image is never allocated.
+    fn capture_scaled(
+        &self,
+        index: usize,
+        scale: Scale,
+        filter: ScaleFilter,
+    ) -> Result<RgbImage, Error> {
+        Ok(scale::scale_image(&self.capture(index)?, scale, filter))
+    }
+    /// Captures the display at `index` and encodes it as `format`, like [`Frame::encode`].
+    /// Where the platform captures BGRX pixels and there's nothing to hide, they're encoded
+    /// with [`encode_bgrx`] without being converted to RGB first.
+    #[cfg(any(feature = "png", feature = "jpeg", feature = "webp", feature = "qoi"))]
+    fn capture_encoded(
+        &self,
+        index: usize,
+        format: ImageFormat,
+        quality: u8,
+    ) -> Result<Vec<u8>, EncodeError> {
+        let mut encoded = vec![];
+        encode::encode(&self.capture(index)?, &mut encoded, format, quality)?;
+        Ok(encoded)
+    }
+    /// Leaves the windows in `exclusions` out of every capture as if they weren't on the
+    /// screen, instead of hiding and showing them around each capture. An empty
+    /// [`Exclusions`] captures everything again.
+    ///
+    /// Only supported on X11, where it needs the Composite extension.
+    fn exclude_windows(&mut self, exclusions: Exclusions) -> Result<(), Error>;
+    /// Returns the masks that are hidden in every image this capturer hands out.
+    fn redactions(&self) -> &Redactions;
     /// Returns a reference to the currently available displays.
     fn displays(&self) -> &[Display];
+    /// Returns the index of the primary display.
+    fn primary_display(&self) -> usize;
+    /// Returns the displays together with which one is the primary display.
+    fn layout(&self) -> Layout {
+        Layout {
+            displays: self.displays().to_vec(),
+            primary: self.primary_display(),
+        }
+    }
     /// Refreshes the current displays.
     fn refresh_displays(&mut self) -> Result<(), Error>;
+    /// Returns what the displays' positions and sizes are measured in, and with them the
+    /// areas [`Capturer::capture_at`] takes and [`Capturer::find_on_screen`] returns. That's
+    /// physical pixels, except on macOS where it's points, which are logical pixels.
+    fn desktop_space(&self) -> CoordinateSpace {
+        if cfg!(target_os = "macos") {
+            CoordinateSpace::Logical
+        } else {
+            CoordinateSpace::Physical
+        }
+    }
+    /// Captures the part of the display at `index` under `region`, which is relative to the
+    /// display's top left corner and measured in `space`. Whatever lies outside of the
+    /// display is left out, the image is empty if nothing is left.
+    fn capture_region(
+        &self,
+        index: usize,
+        region: Rect,
+        space: CoordinateSpace,
+    ) -> Result<RgbImage, Error> {
+        let image = self.capture(index)?;
+
+        Ok(crop_visible(
+            &image,
+            self.displays()[index].physical_region(region, space),
+        ))
+    }
+    /// Captures every display and looks for `template` on them with [`find_template`],
+    /// returning the matches that score at least `threshold` across all displays, the best
+    /// first.
+    ///
+    /// The template is compared pixel for pixel with the captures, so it has to be taken at
+    /// the same scale factor as the display it's looked for on.
+    fn find_on_screen(&self, template: &RgbImage, threshold: f64) -> Result<Vec<Match>, Error> {
+        let mut matches = vec![];
+
+        for (index, image) in self.capture_all()?.iter().enumerate() {
+            let display = &self.displays()[index];
+
+            for (area, score) in find_template(image, template, threshold) {
+                matches.push(Match {
+                    display: index,
+                    bounds: display.to_desktop(area, self.desktop_space()),
+                    score,
+                });
+            }
+        }
+
+        matches.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+
+        Ok(matches)
+    }
+    /// Captures `area`, in the same coordinates as the displays' positions, from the display
+    /// its top left corner is on, and returns that display's index with the capture. Whatever
+    /// lies outside of that display is left out. Returns `None` if there's no display there.
+    ///
+    /// Only the area is captured where the platform allows it, on X11 the server only sends
+    /// those pixels, otherwise the display is captured and cropped.
+    fn capture_at(&self, area: Rect) -> Result<Option<(usize, RgbImage)>, Error> {
+        let index = match self.displays().iter().position(|display| {
+            let bounds = display.bounds();
+
+            area.x >= bounds.x
+                && area.y >= bounds.y
+                && ((area.x - bounds.x) as u32) < bounds.width
+                && ((area.y - bounds.y) as u32) < bounds.height
+        }) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+
+        let region = self.displays()[index].from_desktop(area, self.desktop_space());
+
+        Ok(Some((
+            index,
+            self.capture_region(index, region, CoordinateSpace::Physical)?,
+        )))
+    }
+    /// Returns the colour of the pixel at `(x, y)`, in the same coordinates as the displays'
+    /// positions, or `None` if there's no display there. See [`Capturer::capture_at`].
+    fn pixel_at(&self, x: i32, y: i32) -> Result<Option<Rgb<u8>>, Error> {
+        Ok(self
+            .capture_at(Rect::new(x, y, 1, 1))?
+            .filter(|(_, image)| !image.is_empty())
+            .map(|(_, image)| *image.get_pixel(0, 0)))
+    }
+    /// Returns the colour statistics of `area`, in the same coordinates as the displays'
+    /// positions, or `None` if there's no display there. See [`Capturer::capture_at`].
+    fn color_stats_at(&self, area: Rect) -> Result<Option<ColorStats>, Error> {
+        Ok(self
+            .capture_at(area)?
+            .map(|(_, image)| ColorStats::of(&image)))
+    }
 }
 
-#[derive(Debug, Copy, Clone)]
+/// Copies the part of `image` under `region`, an empty image if none of it is.
+pub(crate) fn crop_visible(image: &RgbImage, region: Rect) -> RgbImage {
+    match region.intersection(&Rect::new(0, 0, image.width(), image.height())) {
+        Some(visible) => image::imageops::crop_imm(
+            image,
+            visible.x as u32,
+            visible.y as u32,
+            visible.width,
+            visible.height,
+        )
+        .to_image(),
+        None => RgbImage::new(0, 0),
+    }
+}
+
+/// What coordinates are measured in. Captures are always made of physical pixels, there are
+/// [`Display::scale_factor`] of them per logical pixel in each direction.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CoordinateSpace {
+    /// The pixels applications and UI toolkits work with.
+    Logical,
+    /// The pixels of the screen and of captured images.
+    Physical,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Display {
     top: CoordinateType,
     left: CoordinateType,
     width: ProportionType,
     height: ProportionType,
+    scale_factor: f64,
 }
 
 impl Display {
+    /// Describes a display, for capturers that don't talk to a real screen like
+    /// [`MockCapturer`].
+    pub fn new(
+        left: CoordinateType,
+        top: CoordinateType,
+        width: ProportionType,
+        height: ProportionType,
+    ) -> Self {
+        Self {
+            top,
+            left,
+            width,
+            height,
+            scale_factor: 1.0,
+        }
+    }
+    /// Like [`Display::new`] but from plain integers, which fit the coordinate types of every
+    /// platform, so the same code describes a display everywhere.
+    #[allow(clippy::useless_conversion)]
+    pub fn from_pixels(left: i16, top: i16, width: u16, height: u16) -> Self {
+        Self::new(left.into(), top.into(), width.into(), height.into())
+    }
+    pub fn left(&self) -> CoordinateType {
+        self.left
+    }
+    pub fn top(&self) -> CoordinateType {
+        self.top
+    }
     pub fn width(&self) -> ProportionType {
         self.width
     }
     pub fn height(&self) -> ProportionType {
         self.height
     }
+    /// Returns how many physical pixels there are per logical pixel, `1.0` unless the
+    /// platform says otherwise.
+    ///
+    /// On X11 it's `Xft.dpi` over 96 when it's set, otherwise it's guessed from the physical
+    /// size RandR reports for the display and rounded to a quarter. Windows reports the
+    /// effective DPI of the monitor, which is only exact for DPI aware processes, and macOS
+    /// the backing scale of the display mode.
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+    /// Sets the scale factor, for displays made with [`Display::new`].
+    pub fn with_scale_factor(mut self, scale_factor: f64) -> Self {
+        self.scale_factor = scale_factor;
+        self
+    }
+
+    /// Converts a point relative to the display's top left corner from logical pixels to
+    /// the physical pixels of a capture.
+    pub fn to_physical(&self, x: f64, y: f64) -> (f64, f64) {
+        (x * self.scale_factor, y * self.scale_factor)
+    }
+    /// Converts a point relative to the display's top left corner from the physical pixels
+    /// of a capture to logical pixels.
+    pub fn to_logical(&self, x: f64, y: f64) -> (f64, f64) {
+        (x / self.scale_factor, y / self.scale_factor)
+    }
+    /// Converts `region`, relative to the display's top left corner and measured in `space`,
+    /// to physical pixels. It's rounded outwards, so it covers at least as much as before.
+    pub fn physical_region(&self, region: Rect, space: CoordinateSpace) -> Rect {
+        match space {
+            CoordinateSpace::Physical => region,
+            CoordinateSpace::Logical => scale_rect(region, self.scale_factor),
+        }
+    }
+    /// Converts `region`, relative to the display's top left corner and measured in `space`,
+    /// to logical pixels. It's rounded outwards, so it covers at least as much as before.
+    pub fn logical_region(&self, region: Rect, space: CoordinateSpace) -> Rect {
+        match space {
+            CoordinateSpace::Logical => region,
+            CoordinateSpace::Physical => scale_rect(region, 1.0 / self.scale_factor),
+        }
+    }
+
+    /// Converts `area`, in the same coordinates as the displays' positions and measured in
+    /// `space`, see [`Capturer::desktop_space`], to the physical pixels of a capture of this
+    /// display. It's rounded outwards, like [`Display::physical_region`].
+    pub fn from_desktop(&self, area: Rect, space: CoordinateSpace) -> Rect {
+        let bounds = self.bounds();
+
+        self.physical_region(
+            Rect::new(
+                area.x - bounds.x,
+                area.y - bounds.y,
+                area.width,
+                area.height,
+            ),
+            space,
+        )
+    }
+
+    /// Converts `region` of a capture of this display to the coordinates of the displays'
+    /// positions, measured in `space`. The opposite of [`Display::from_desktop`].
+    pub fn to_desktop(&self, region: Rect, space: CoordinateSpace) -> Rect {
+        let bounds = self.bounds();
+        let region = match space {
+            CoordinateSpace::Physical => region,
+            CoordinateSpace::Logical => self.logical_region(region, CoordinateSpace::Physical),
+        };
+
+        Rect::new(
+            bounds.x + region.x,
+            bounds.y + region.y,
+            region.width,
+            region.height,
+        )
+    }
+
+    /// Returns the area the display covers, in pixels.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn bounds(&self) -> Rect {
+        Rect::new(
+            self.left as i32,
+            self.top as i32,
+            self.width as u32,
+            self.height as u32,
+        )
+    }
+}
+
+/// Multiplies `rect` by `factor`, growing it to the closest whole pixels around it.
+fn scale_rect(rect: Rect, factor: f64) -> Rect {
+    let left = (rect.x as f64 * factor).floor();
+    let top = (rect.y as f64 * factor).floor();
+    let right = ((rect.x as f64 + rect.width as f64) * factor).ceil();
+    let bottom = ((rect.y as f64 + rect.height as f64) * factor).ceil();
+
+    Rect::new(
+        left as i32,
+        top as i32,
+        (right - left) as u32,
+        (bottom - top) as u32,
+    )
+}
+
+/// The windows [`Capturer::exclude_windows`] leaves out of captures.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Exclusions {
+    /// Every window that belongs to the current process.
+    pub own_windows: bool,
+    /// X11 window ids, the frames the window manager puts around them are left out too.
+    pub windows: Vec<u32>,
+}
+
+impl Exclusions {
+    pub fn is_empty(&self) -> bool {
+        !self.own_windows && self.windows.is_empty()
+    }
+}
+
+/// How the displays are arranged, which can be saved and fed back into a [`MockCapturer`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Layout {
+    pub displays: Vec<Display>,
+    /// The index of the primary display in `displays`.
+    pub primary: usize,
+}
+
+/// A rectangle in pixel coordinates.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// Returns the overlapping part of both rectangles, if there is any.
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let left = self.x.max(other.x);
+        let top = self.y.max(other.y);
+        let right = (self.x as i64 + self.width as i64).min(other.x as i64 + other.width as i64);
+        let bottom = (self.y as i64 + self.height as i64).min(other.y as i64 + other.height as i64);
+
+        if right <= left as i64 || bottom <= top as i64 {
+            return None;
+        }
+
+        Some(Rect::new(
+            left,
+            top,
+            (right - left as i64) as u32,
+            (bottom - top as i64) as u32,
+        ))
+    }
 }
 
 #[cfg(target_os = "windows")]
 pub fn init_capturer() -> Result<impl Capturer, Error> {
     use windows::*;
-    Ok(WindowsCapturer::new()?)
+    WindowsCapturer::new()
 }
 
 #[cfg(target_os = "linux")]
 pub fn init_capturer() -> Result<impl Capturer, Error> {
     use linux::*;
-    Ok(X11Capturer::new()?)
+    X11Capturer::new(X11Options::default())
+}
+
+/// Connects to the X server and lists the displays of the screens `options` asks for, see
+/// [`X11Options`].
+#[cfg(target_os = "linux")]
+pub fn init_x11_capturer(options: X11Options) -> Result<X11Capturer, Error> {
+    X11Capturer::new(options)
 }
 
 #[cfg(target_os = "macos")]
 pub fn init_capturer() -> Result<impl Capturer, Error> {
     use macos::*;
-    Ok(MacOSCapturer::new()?)
+    MacOSCapturer::new()
 }
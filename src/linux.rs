@@ -1,38 +1,368 @@
 #![cfg(target_os = "linux")]
 
-use super::*;
+use super::{
+    convert::{bgrx_row_to_rgb, bgrx_to_rgb},
+    scale::{self, scale_rows},
+    *,
+};
 
 use libc::{shmat, shmctl, shmdt, shmget, IPC_CREAT, IPC_PRIVATE, IPC_RMID, SHM_RDONLY};
 use std::{
+    fs,
     io::{Error, ErrorKind},
-    mem, ptr,
+    iter,
+    path::{Path, PathBuf},
+    ptr,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::SystemTime,
 };
 use x11rb::{
     connection::{Connection, RequestConnection},
-    errors::ConnectionError,
+    cookie::Cookie,
+    errors::{ConnectError, ConnectionError, ReplyError},
     protocol::{
+        composite::{self, ConnectionExt as CompositeConnectionExt, Redirect},
         randr::{self, ConnectionExt},
         shm::{self, ConnectionExt as XShmConnectionExt},
-        xproto::{ConnectionExt as XProtoConnectionExt, ImageFormat},
+        xproto::{
+            Atom, AtomEnum, ConnectionExt as XProtoConnectionExt, Drawable, GetGeometryReply,
+            GetWindowAttributesReply, ImageFormat, ImageOrder, MapState, Screen, Setup,
+            TranslateCoordinatesReply, VisualClass, Visualid, Window,
+        },
     },
-    rust_connection::RustConnection,
+    reexports::x11rb_protocol::parse_display::parse_display,
+    rust_connection::{DefaultStream, RustConnection},
 };
 
 const PLANE_MASK: u32 = !1;
 
-pub(crate) struct X11Capturer {
-    screen: usize,
+/// How far below a top-level window excluded windows are looked for, deep enough for the
+/// frames of every common window manager.
+const EXCLUDED_WINDOW_DEPTH: u32 = 3;
+
+/// Which X server [`init_x11_capturer`](crate::init_x11_capturer) connects to, and which of
+/// its screens it captures.
+///
+/// ```no_run
+/// use captis::{init_x11_capturer, Capturer, X11Options};
+///
+/// // A virtual display started with `Xvfb :99 -screen 0 1280x720x24 -screen 1 800x600x24`.
+/// let capturer = init_x11_capturer(
+///     X11Options::new()
+///         .display(":99")
+///         .auth_file("/tmp/xvfb.auth")
+///         .all_screens(),
+/// )
+/// .expect("Couldn't Initialize Capturer");
+///
+/// for (index, display) in capturer.displays().iter().enumerate() {
+///     capturer
+///         .capture(index)
+///         .expect("Couldn't Capture Screen")
+///         .save(format!("display-{}.png", index))
+///         .expect("Couldn't Save Image");
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct X11Options {
+    display: Option<String>,
+    auth_file: Option<PathBuf>,
+    screens: ScreenSelection,
+}
+
+#[derive(Debug, Clone)]
+enum ScreenSelection {
+    /// The screen the display string names, `0` unless it ends in something like `.1`.
+    Default,
+    All,
+    Only(Vec<usize>),
+}
+
+impl Default for ScreenSelection {
+    fn default() -> Self {
+        ScreenSelection::Default
+    }
+}
+
+impl X11Options {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Connects to `display`, like `:1` or `host:0.1`, instead of the one in `$DISPLAY`.
+    pub fn display(mut self, display: impl Into<String>) -> Self {
+        self.display = Some(display.into());
+        self
+    }
+
+    /// Authenticates with the cookie for the display in the Xauthority file at `path`, like
+    /// the one given to `Xvfb -auth`, instead of the one `$XAUTHORITY` points to.
+    pub fn auth_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.auth_file = Some(path.into());
+        self
+    }
+
+    /// Lists the displays of `screens`, in that order, instead of only the screen the display
+    /// string names.
+    ///
+    /// Every screen has its own root window, so displays keep the coordinates of their own
+    /// screen and the ones of different screens can overlap. Desktop coordinates, like the
+    /// ones [`Capturer::capture_at`] takes and [`Capturer::find_on_screen`] returns, only tell
+    /// the displays of the first screen apart from each other.
+    pub fn screens(mut self, screens: impl IntoIterator<Item = usize>) -> Self {
+        self.screens = ScreenSelection::Only(screens.into_iter().collect());
+        self
+    }
+
+    /// Lists the displays of every screen of the server, see [`X11Options::screens`] about
+    /// their coordinates.
+    pub fn all_screens(mut self) -> Self {
+        self.screens = ScreenSelection::All;
+        self
+    }
+
+    /// Connects to the server, returning the connection and the screen the display string
+    /// names.
+    fn connect(&self) -> Result<(RustConnection, usize), ConnectionError> {
+        let auth_file = match &self.auth_file {
+            Some(auth_file) => auth_file,
+            None => return x11rb::connect(self.display.as_deref()).map_err(connect_error),
+        };
+
+        let parsed = parse_display(self.display.as_deref())
+            .ok_or_else(|| connect_error(ConnectError::DisplayParsingError))?;
+        let screen = parsed.screen as usize;
+
+        let (auth_name, auth_data) = read_auth_file(auth_file, parsed.display)?.unwrap_or_default();
+
+        let mut error = None;
+
+        for address in parsed.connect_instruction() {
+            match DefaultStream::connect(address) {
+                Ok(stream) => {
+                    let connection = RustConnection::connect_to_stream_with_auth_info(
+                        stream,
+                        screen,
+                        auth_name.clone(),
+                        auth_data.clone(),
+                    )
+                    .map_err(connect_error)?;
+
+                    return Ok((connection, screen));
+                }
+                Err(stream_error) => error = Some(stream_error),
+            }
+        }
+
+        Err(error.map_or(ConnectionError::UnknownError, ConnectionError::IoError))
+    }
+
+    /// Returns the screens to capture on a server with `count` of them.
+    fn screen_numbers(&self, default: usize, count: usize) -> Result<Vec<usize>, ConnectionError> {
+        let numbers = match &self.screens {
+            ScreenSelection::Default => vec![default],
+            ScreenSelection::All => (0..count).collect(),
+            ScreenSelection::Only(numbers) => numbers.clone(),
+        };
+
+        if numbers.is_empty() {
+            return Err(ConnectionError::IoError(Error::new(
+                ErrorKind::InvalidInput,
+                "No screens to capture",
+            )));
+        }
+
+        match numbers.iter().find(|number| **number >= count) {
+            Some(number) => Err(ConnectionError::IoError(Error::new(
+                ErrorKind::NotFound,
+                format!("Couldn't find screen {}", number),
+            ))),
+            None => Ok(numbers),
+        }
+    }
+}
+
+/// Turns the error of connecting to the server into the error of the capturer, keeping what
+/// went wrong.
+fn connect_error(error: ConnectError) -> ConnectionError {
+    match error {
+        ConnectError::IoError(error) => ConnectionError::IoError(error),
+        ConnectError::InsufficientMemory => ConnectionError::InsufficientMemory,
+        error => ConnectionError::IoError(Error::new(
+            ErrorKind::Other,
+            format!("Couldn't connect to the X server: {}", error),
+        )),
+    }
+}
+
+/// Turns the error of a request whose errors are checked into the error of the capturer.
+fn reply_error(error: ReplyError) -> ConnectionError {
+    match error {
+        ReplyError::ConnectionError(error) => error,
+        ReplyError::X11Error(error) => ConnectionError::IoError(Error::new(
+            ErrorKind::Other,
+            format!("The X server returned {:?}", error.error_kind),
+        )),
+    }
+}
+
+/// The name of an authorization protocol and the data it sends, like a cookie.
+type AuthInfo = (Vec<u8>, Vec<u8>);
+
+/// Returns the name and data of the first `MIT-MAGIC-COOKIE-1` entry for `display` in the
+/// Xauthority file at `path`. The files handed to a single server rarely have more than one
+/// entry, so which host an entry is for isn't checked.
+fn read_auth_file(path: &Path, display: u16) -> Result<Option<AuthInfo>, ConnectionError> {
+    // Every field is prefixed by its big-endian length.
+    fn field<'a>(rest: &mut &'a [u8]) -> Option<&'a [u8]> {
+        let length = u16::from_be_bytes([*rest.first()?, *rest.get(1)?]) as usize;
+        let field = rest.get(2..2 + length)?;
+        *rest = &rest[2 + length..];
+        Some(field)
+    }
+
+    let file = fs::read(path).map_err(ConnectionError::IoError)?;
+    let display = display.to_string();
+    let mut rest = &file[..];
+
+    // Entries are a two byte family followed by the address, display number, name and data.
+    while rest.len() >= 2 {
+        rest = &rest[2..];
+
+        let entry = (
+            field(&mut rest),
+            field(&mut rest),
+            field(&mut rest),
+            field(&mut rest),
+        );
+
+        let (number, name, data) = match entry {
+            (Some(_), Some(number), Some(name), Some(data)) => (number, name, data),
+            _ => {
+                return Err(ConnectionError::IoError(Error::new(
+                    ErrorKind::InvalidData,
+                    "Couldn't read the Xauthority file",
+                )))
+            }
+        };
+
+        if (number.is_empty() || number == display.as_bytes()) && name == b"MIT-MAGIC-COOKIE-1" {
+            return Ok(Some((name.to_vec(), data.to_vec())));
+        }
+    }
+
+    Ok(None)
+}
+
+/// The capturer on Linux, which [`init_x11_capturer`](crate::init_x11_capturer) returns so
+/// X11 specific methods like [`X11Capturer::capture_drawable`] can be reached.
+pub struct X11Capturer {
     connection: RustConnection,
+    /// The screens the displays are on, their displays are listed in the same order.
+    screens: Vec<CapturedScreen>,
+    redactions: Redactions,
+    exclusions: Exclusions,
+    /// Whether the top-level windows are redirected offscreen, which lets them be captured
+    /// one at a time even where they're covered.
+    redirected: bool,
+    /// The `_NET_WM_PID` atom, interned the first time windows are excluded by process.
+    net_wm_pid: Option<Atom>,
     displays: Vec<Display>,
+    /// The index in `screens` of the screen every display is on.
+    display_screens: Vec<usize>,
     primary_display_index: usize,
-    shm_addr: *const u8,
-    shm_id: Option<i32>,
-    seg: Option<u32>,
+    /// Locked for the whole capture, the server writes every capture into the same segment.
+    shm: Option<Mutex<ShmSegment>>,
+}
+
+/// A screen of the server, which has its own root window and possibly its own depth.
+struct CapturedScreen {
+    number: usize,
+    root: Window,
+    format: PixelFormat,
+}
+
+/// The replies about a window [`X11Capturer::locate_windows`] waits for.
+type LocationCookies<'c> = (
+    Cookie<'c, RustConnection, GetWindowAttributesReply>,
+    Cookie<'c, RustConnection, GetGeometryReply>,
+    Cookie<'c, RustConnection, TranslateCoordinatesReply>,
+);
+
+/// Where the masked windows are, asked for by [`X11Capturer::request_masks`].
+struct PendingMasks<'c> {
+    root: Window,
+    windows: Vec<Window>,
+    cookies: Vec<LocationCookies<'c>>,
+}
+
+/// A shared memory segment the server writes captures into.
+struct ShmSegment {
+    seg: shm::Seg,
+    id: i32,
+    addr: *const u8,
+    size: usize,
+}
+
+// The segment is mapped into the whole process rather than the thread that attached it, and
+// `X11Capturer` only hands it out behind a mutex.
+unsafe impl Send for ShmSegment {}
+
+impl ShmSegment {
+    /// Creates a segment of `size` bytes and attaches it to the server, or returns `None` if
+    /// the server can't use it, like over a remote connection.
+    fn attach(connection: &RustConnection, size: usize) -> Result<Option<Self>, ConnectionError> {
+        let seg = match connection.generate_id() {
+            Ok(seg) => seg,
+            Err(_) => return Ok(None),
+        };
+
+        unsafe {
+            // Only readable and writable by us, the server checks our credentials when it
+            // attaches rather than its own.
+            let id = shmget(IPC_PRIVATE, size, IPC_CREAT | 0o600);
+
+            if id < 0 {
+                return Err(ConnectionError::IoError(Error::last_os_error()));
+            }
+
+            let addr = shmat(id, ptr::null(), SHM_RDONLY);
+
+            if (addr as isize) < 0 {
+                shmctl(id, IPC_RMID, ptr::null_mut());
+                return Err(ConnectionError::IoError(Error::last_os_error()));
+            }
+
+            match connection.shm_attach(seg, id as u32, false)?.check() {
+                Ok(()) => Ok(Some(ShmSegment {
+                    seg,
+                    id,
+                    addr: addr as *const u8,
+                    size,
+                })),
+                Err(ReplyError::X11Error(_)) => {
+                    shmdt(addr);
+                    shmctl(id, IPC_RMID, ptr::null_mut());
+                    Ok(None)
+                }
+                Err(ReplyError::ConnectionError(error)) => Err(error),
+            }
+        }
+    }
+
+    /// Detaches the segment from the server and removes it.
+    fn detach(&self, connection: &RustConnection) {
+        connection.shm_detach(self.seg).ok();
+        unsafe {
+            shmdt(self.addr as _);
+            shmctl(self.id, IPC_RMID, ptr::null_mut());
+        }
+    }
 }
 
 impl X11Capturer {
-    pub(crate) fn new() -> Result<X11Capturer, ConnectionError> {
-        let (connection, screen) = x11rb::connect(None).or(Err(ConnectionError::UnknownError))?;
+    pub(crate) fn new(options: X11Options) -> Result<X11Capturer, ConnectionError> {
+        let (connection, default_screen) = options.connect()?;
 
         if connection
             .extension_information(randr::X11_EXTENSION_NAME)?
@@ -41,165 +371,1220 @@ impl X11Capturer {
             return Err(ConnectionError::UnsupportedExtension);
         }
 
-        let (seg, shm_id, shm_addr) = if connection
-            .extension_information(shm::X11_EXTENSION_NAME)?
-            .is_some()
-        {
-            (None, None, ptr::null())
-        } else {
-            let screen = &connection.setup().roots[screen];
-
-            match connection.generate_id() {
-                Ok(seg) => unsafe {
-                    let shm_id = shmget(
-                        IPC_PRIVATE,
-                        (screen.width_in_pixels as usize * screen.height_in_pixels as usize)
-                            * mem::size_of::<Bgr>(),
-                        IPC_CREAT | 0o777,
-                    );
-
-                    if shm_id < 0 {
-                        return Err(ConnectionError::IoError(Error::last_os_error()));
-                    }
-
-                    let shm_addr = shmat(shm_id, ptr::null(), SHM_RDONLY);
+        let setup = connection.setup();
+
+        let screens = options
+            .screen_numbers(default_screen, setup.roots.len())?
+            .into_iter()
+            .map(|number| {
+                let screen = &setup.roots[number];
+
+                Ok(CapturedScreen {
+                    number,
+                    root: screen.root,
+                    format: PixelFormat::new(setup, screen)?,
+                })
+            })
+            .collect::<Result<Vec<_>, ConnectionError>>()?;
+
+        let shm = if connection
+            .extension_information(shm::X11_EXTENSION_NAME)?
+            .is_some()
+        {
+            // Big enough for the largest screen, captures only ever cover one of them.
+            let size = screens
+                .iter()
+                .map(|screen| {
+                    let roots = &setup.roots[screen.number];
+
+                    screen.format.stride(roots.width_in_pixels) * roots.height_in_pixels as usize
+                })
+                .max()
+                .unwrap_or(0);
+
+            ShmSegment::attach(&connection, size)?.map(Mutex::new)
+        } else {
+            None
+        };
+
+        let (primary_display_index, displays, display_screens) =
+            get_all_displays(&connection, &screens)?;
+
+        Ok(X11Capturer {
+            screens,
+            redactions: Redactions::new(),
+            exclusions: Exclusions::default(),
+            redirected: false,
+            net_wm_pid: None,
+            displays,
+            display_screens,
+            primary_display_index,
+            connection,
+            shm,
+        })
+    }
+
+    /// Returns the display at `index` and the screen it's on.
+    fn display(&self, index: usize) -> Result<(&Display, &CapturedScreen), ConnectionError> {
+        match (self.displays.get(index), self.display_screens.get(index)) {
+            (Some(display), Some(screen)) => Ok((display, &self.screens[*screen])),
+            _ => Err(ConnectionError::IoError(Error::new(
+                ErrorKind::NotFound,
+                "Couldn't find specified Display",
+            ))),
+        }
+    }
+
+    /// Locks the shared memory segment, if there is one. A capture that panicked can't have
+    /// left anything behind in it that matters, the next one overwrites it.
+    fn lock_shm(&self) -> Option<MutexGuard<'_, ShmSegment>> {
+        self.shm
+            .as_ref()
+            .map(|shm| shm.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Captures `area` of `drawable` using standard protocols, which are a lot less
+    /// inefficient.
+    fn capture_standard<T>(
+        &self,
+        drawable: Drawable,
+        area: Rect,
+        decode: impl FnOnce(&[u8]) -> T,
+    ) -> Result<T, ConnectionError> {
+        let x11_image = self
+            .connection
+            .get_image(
+                ImageFormat::Z_PIXMAP,
+                drawable,
+                area.x as i16,
+                area.y as i16,
+                area.width as u16,
+                area.height as u16,
+                PLANE_MASK,
+            )?
+            .reply_unchecked()?
+            .ok_or(ConnectionError::UnknownError)?;
+
+        Ok(decode(&x11_image.data))
+    }
+
+    /// Captures `area` of `drawable` using the XShm protocol and shared memory causing the
+    /// program to run hella lot faster.
+    fn capture_shm<T>(
+        &self,
+        shm: &ShmSegment,
+        drawable: Drawable,
+        area: Rect,
+        decode: impl FnOnce(&[u8]) -> T,
+    ) -> Result<T, ConnectionError> {
+        let reply = self
+            .connection
+            .shm_get_image(
+                drawable,
+                area.x as i16,
+                area.y as i16,
+                area.width as u16,
+                area.height as u16,
+                PLANE_MASK,
+                ImageFormat::Z_PIXMAP.into(),
+                shm.seg,
+                0,
+            )?
+            .reply_unchecked()?
+            .ok_or(ConnectionError::UnknownError)?;
+
+        let data = unsafe { std::slice::from_raw_parts(shm.addr, reply.size as usize) };
+
+        Ok(decode(data))
+    }
+
+    /// Captures `area` of `drawable`, whose images are in `format`, with whichever protocol
+    /// is available, and returns which one that was. Areas too large for the shared memory
+    /// segment go the standard way.
+    fn capture_area<T>(
+        &self,
+        drawable: Drawable,
+        area: Rect,
+        format: &PixelFormat,
+        decode: impl FnOnce(&[u8]) -> T,
+    ) -> Result<(T, Backend), ConnectionError> {
+        let size = format.stride(area.width as u16) * area.height as usize;
+
+        // The segment is unlocked again before falling back, so nothing waits for it meanwhile.
+        if let Some(shm) = self.lock_shm() {
+            if size <= shm.size {
+                return Ok((
+                    self.capture_shm(&shm, drawable, area, decode)?,
+                    Backend::Shm,
+                ));
+            }
+        }
+
+        Ok((
+            self.capture_standard(drawable, area, decode)?,
+            Backend::Standard,
+        ))
+    }
+
+    /// Captures the display at `index`, see [`X11Capturer::capture_area`].
+    fn capture_with<T>(
+        &self,
+        index: usize,
+        decode: impl FnOnce(&[u8], &Display, &PixelFormat) -> T,
+    ) -> Result<(T, Backend), ConnectionError> {
+        let (display, screen) = self.display(index)?;
+
+        self.capture_area(screen.root, display.bounds(), &screen.format, |data| {
+            decode(data, display, &screen.format)
+        })
+    }
+
+    /// Captures the display at `index` like [`Capturer::capture`], returning how.
+    fn capture_redacted(&self, index: usize) -> Result<(RgbImage, Backend), ConnectionError> {
+        let (_, screen) = self.display(index)?;
+        let masks = self.request_masks(screen.root)?;
+
+        let (mut image, backend) = if self.exclusions.is_empty() {
+            self.capture_with(index, |data, display, format| {
+                format.decode(data, display.width, display.height)
+            })?
+        } else {
+            (self.capture_composited(index)?, Backend::Composited)
+        };
+
+        self.redact(&mut image, index, masks)?;
+
+        Ok((image, backend))
+    }
+
+    /// Captures `area` of `drawable`, any window or pixmap on the server including the
+    /// Composite overlay window, or all of it if `area` is `None`. `area` is relative to the
+    /// drawable and has to lie within it, and a window has to be mapped.
+    ///
+    /// This is the same path displays are captured through, shared memory included, and the
+    /// masks in [`Capturer::redactions`] are hidden in windows like on the screen. Pixmaps
+    /// aren't on the screen, so they can't be captured while there are any masks.
+    pub fn capture_drawable(
+        &self,
+        drawable: Drawable,
+        area: Option<Rect>,
+    ) -> Result<RgbImage, ConnectionError> {
+        let geometry = self
+            .connection
+            .get_geometry(drawable)?
+            .reply()
+            .map_err(reply_error)?;
+
+        let setup = self.connection.setup();
+
+        let screen = setup
+            .roots
+            .iter()
+            .find(|screen| screen.root == geometry.root)
+            .ok_or(ConnectionError::UnknownError)?;
+
+        // Pixmaps don't have a visual, the first one of their depth says how to decode them.
+        let (format, is_window) = match self.connection.get_window_attributes(drawable)?.reply() {
+            Ok(attributes) => (
+                PixelFormat::of_visual(setup, screen, geometry.depth, attributes.visual)?,
+                true,
+            ),
+            Err(ReplyError::X11Error(_)) => {
+                (PixelFormat::of_depth(setup, screen, geometry.depth)?, false)
+            }
+            Err(ReplyError::ConnectionError(error)) => return Err(error),
+        };
+
+        if !is_window && !self.redactions.is_empty() {
+            return Err(ConnectionError::IoError(Error::new(
+                ErrorKind::Unsupported,
+                "Pixmaps can't be redacted",
+            )));
+        }
+
+        let area =
+            area.unwrap_or_else(|| Rect::new(0, 0, geometry.width as u32, geometry.height as u32));
+
+        let origin = if is_window {
+            Some(
+                self.connection
+                    .translate_coordinates(drawable, screen.root, 0, 0)?
+                    .reply()
+                    .map_err(reply_error)?,
+            )
+        } else {
+            None
+        };
+
+        let masks = self.request_masks(screen.root)?;
+
+        let (mut image, _) = self.capture_area(drawable, area, &format, |data| {
+            format.decode(data, area.width as u16, area.height as u16)
+        })?;
+
+        if let Some(origin) = origin {
+            let bounds = Rect::new(
+                origin.dst_x as i32 + area.x,
+                origin.dst_y as i32 + area.y,
+                area.width,
+                area.height,
+            );
+
+            self.redact_area(&mut image, bounds, masks)?;
+        }
+
+        Ok(image)
+    }
+
+    /// Asks where the masked windows on the screen of `root` are. Sent before the capture
+    /// like the cursor in [`Capturer::capture_frame`], the server answers in order and so
+    /// with where they were when the pixels were taken.
+    fn request_masks(&self, root: Window) -> Result<PendingMasks<'_>, ConnectionError> {
+        let windows = self.redactions.windows();
+        let cookies = self.request_locations(&windows, root)?;
+
+        Ok(PendingMasks {
+            root,
+            windows,
+            cookies,
+        })
+    }
+
+    /// Hides the masks in `image`, a capture of the display at `index`.
+    fn redact(
+        &self,
+        image: &mut RgbImage,
+        index: usize,
+        masks: PendingMasks<'_>,
+    ) -> Result<(), ConnectionError> {
+        let (display, _) = self.display(index)?;
+
+        self.redact_area(image, display.bounds(), masks)
+    }
+
+    /// Hides the masks in `image`, a capture of `bounds` on the screen `masks` were requested
+    /// for.
+    fn redact_area(
+        &self,
+        image: &mut RgbImage,
+        bounds: Rect,
+        masks: PendingMasks<'_>,
+    ) -> Result<(), ConnectionError> {
+        let root = masks.root;
+        let located: Vec<(Window, Option<Rect>)> = masks
+            .windows
+            .into_iter()
+            .zip(self.read_locations(masks.cookies)?)
+            .map(|(window, location)| (window, location.map(|(area, _)| area)))
+            .collect();
+
+        self.redactions.apply(image, bounds, |mask| match *mask {
+            Mask::Window(window) => match located.iter().find(|(w, _)| *w == window) {
+                Some((_, area)) => Ok(*area),
+                // Only masked while the capture was running already.
+                None => Ok(self.locate_window(window, root)?.map(|(area, _)| area)),
+            },
+            Mask::Rect(rect) => Ok(Some(rect)),
+        })
+    }
+
+    /// Returns the area `window` covers on the screen of `root` including its border, and
+    /// the width of the border, or `None` if it isn't visible there or doesn't exist anymore.
+    fn locate_window(
+        &self,
+        window: Window,
+        root: Window,
+    ) -> Result<Option<(Rect, i32)>, ConnectionError> {
+        Ok(self.locate_windows(&[window], root)?.pop().flatten())
+    }
+
+    /// Like [`X11Capturer::locate_window`] for each of `windows`, sending every request
+    /// before waiting for any of the replies.
+    fn locate_windows(
+        &self,
+        windows: &[Window],
+        root: Window,
+    ) -> Result<Vec<Option<(Rect, i32)>>, ConnectionError> {
+        self.read_locations(self.request_locations(windows, root)?)
+    }
+
+    /// Sends the requests [`X11Capturer::locate_windows`] needs for each of `windows`,
+    /// without waiting for the replies.
+    fn request_locations(
+        &self,
+        windows: &[Window],
+        root: Window,
+    ) -> Result<Vec<LocationCookies<'_>>, ConnectionError> {
+        windows
+            .iter()
+            .map(|&window| {
+                Ok((
+                    self.connection.get_window_attributes(window)?,
+                    self.connection.get_geometry(window)?,
+                    self.connection.translate_coordinates(window, root, 0, 0)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Waits for the replies to [`X11Capturer::request_locations`].
+    fn read_locations(
+        &self,
+        cookies: Vec<LocationCookies<'_>>,
+    ) -> Result<Vec<Option<(Rect, i32)>>, ConnectionError> {
+        cookies
+            .into_iter()
+            .map(|(attributes, geometry, translated)| {
+                let (attributes, geometry, translated) = match (
+                    attributes.reply_unchecked()?,
+                    geometry.reply_unchecked()?,
+                    translated.reply_unchecked()?,
+                ) {
+                    (Some(attributes), Some(geometry), Some(translated)) => {
+                        (attributes, geometry, translated)
+                    }
+                    _ => return Ok(None),
+                };
+
+                if attributes.map_state != MapState::VIEWABLE || !translated.same_screen {
+                    return Ok(None);
+                }
+
+                let border = geometry.border_width as i32;
+
+                Ok(Some((
+                    Rect::new(
+                        translated.dst_x as i32 - border,
+                        translated.dst_y as i32 - border,
+                        geometry.width as u32 + 2 * border as u32,
+                        geometry.height as u32 + 2 * border as u32,
+                    ),
+                    border,
+                )))
+            })
+            .collect()
+    }
+
+    /// Paints the display at `index` out of the top-level windows one by one, leaving out
+    /// the excluded ones. Much slower than capturing the root window.
+    ///
+    /// Every step asks about all the windows at once, so the number of round trips doesn't
+    /// grow with the number of windows.
+    fn capture_composited(&self, index: usize) -> Result<RgbImage, ConnectionError> {
+        let (display, screen) = self.display(index)?;
+
+        let bounds = display.bounds();
+
+        let mut image = self.root_background(&bounds, screen)?;
+
+        // Ordered from the bottom of the stack to the top.
+        let windows = self
+            .connection
+            .query_tree(screen.root)?
+            .reply_unchecked()?
+            .ok_or(ConnectionError::UnknownError)?
+            .children;
+
+        let visible: Vec<(Window, Rect, Rect, i32)> = windows
+            .iter()
+            .zip(self.locate_windows(&windows, screen.root)?)
+            .filter_map(|(&window, location)| {
+                let (area, border) = location?;
+                let visible = area.intersection(&bounds)?;
+
+                Some((window, area, visible, border))
+            })
+            .collect();
+
+        let excluded = excluded_windows(
+            &self.exclusions,
+            std::process::id(),
+            &visible
+                .iter()
+                .map(|&(window, ..)| window)
+                .collect::<Vec<_>>(),
+            EXCLUDED_WINDOW_DEPTH,
+            |windows, with_children| self.describe_windows(windows, with_children),
+        )?;
+
+        let cookies = visible
+            .iter()
+            .zip(excluded)
+            .filter(|(_, excluded)| !excluded)
+            .map(|(&(window, area, visible, border), _)| {
+                let cookie = self.connection.get_image(
+                    ImageFormat::Z_PIXMAP,
+                    window,
+                    (visible.x - area.x - border) as i16,
+                    (visible.y - area.y - border) as i16,
+                    visible.width as u16,
+                    visible.height as u16,
+                    PLANE_MASK,
+                )?;
+
+                Ok((visible, cookie))
+            })
+            .collect::<Result<Vec<_>, ConnectionError>>()?;
+
+        for (visible, cookie) in cookies {
+            // The window can disappear at any moment, it's simply left out then.
+            let reply = match cookie.reply_unchecked()? {
+                Some(reply) => reply,
+                None => continue,
+            };
+
+            let format = match PixelFormat::of_visual(
+                self.connection.setup(),
+                &self.connection.setup().roots[screen.number],
+                reply.depth,
+                reply.visual,
+            ) {
+                Ok(format) => format,
+                Err(_) => continue,
+            };
+
+            let pixels = format.decode(&reply.data, visible.width as u16, visible.height as u16);
+
+            image::imageops::replace(
+                &mut image,
+                &pixels,
+                (visible.x - bounds.x) as i64,
+                (visible.y - bounds.y) as i64,
+            );
+        }
+
+        Ok(image)
+    }
+
+    /// Returns the part of the desktop background under `bounds`, as set by the usual
+    /// `_XROOTPMAP_ID` property, or black if there isn't one.
+    fn root_background(
+        &self,
+        bounds: &Rect,
+        screen: &CapturedScreen,
+    ) -> Result<RgbImage, ConnectionError> {
+        let black = RgbImage::new(bounds.width, bounds.height);
+        let root_depth = self.connection.setup().roots[screen.number].root_depth;
+
+        let atom = match self
+            .connection
+            .intern_atom(true, b"_XROOTPMAP_ID")?
+            .reply_unchecked()?
+        {
+            Some(reply) if reply.atom != 0 => reply.atom,
+            _ => return Ok(black),
+        };
+
+        let pixmap = self
+            .connection
+            .get_property(false, screen.root, atom, AtomEnum::PIXMAP, 0, 1)?
+            .reply_unchecked()?
+            .and_then(|reply| reply.value32().and_then(|mut value| value.next()));
+
+        let pixmap = match pixmap {
+            Some(pixmap) if pixmap != 0 => pixmap,
+            _ => return Ok(black),
+        };
+
+        let reply = self
+            .connection
+            .get_image(
+                ImageFormat::Z_PIXMAP,
+                pixmap,
+                bounds.x as i16,
+                bounds.y as i16,
+                bounds.width as u16,
+                bounds.height as u16,
+                PLANE_MASK,
+            )?
+            .reply_unchecked()?;
+
+        // The background usually has the same depth as the root window, which says how to
+        // decode it since pixmaps don't have a visual.
+        Ok(match reply {
+            Some(reply) if reply.depth == root_depth => {
+                screen
+                    .format
+                    .decode(&reply.data, bounds.width as u16, bounds.height as u16)
+            }
+            _ => black,
+        })
+    }
+
+    /// Looks up what [`excluded_windows`] needs to know about `windows`, sending every
+    /// request before waiting for any of the replies. The process is only looked up when
+    /// the capturer's own windows are excluded.
+    fn describe_windows(
+        &self,
+        windows: &[Window],
+        with_children: bool,
+    ) -> Result<Vec<WindowInfo>, ConnectionError> {
+        let pid_atom = self.net_wm_pid.filter(|_| self.exclusions.own_windows);
+
+        let cookies = windows
+            .iter()
+            .map(|&window| {
+                let pid = match pid_atom {
+                    Some(atom) => Some(self.connection.get_property(
+                        false,
+                        window,
+                        atom,
+                        AtomEnum::CARDINAL,
+                        0,
+                        1,
+                    )?),
+                    None => None,
+                };
+
+                let tree = if with_children {
+                    Some(self.connection.query_tree(window)?)
+                } else {
+                    None
+                };
+
+                Ok((pid, tree))
+            })
+            .collect::<Result<Vec<_>, ConnectionError>>()?;
+
+        cookies
+            .into_iter()
+            .map(|(pid, tree)| {
+                let pid = match pid {
+                    Some(cookie) => cookie
+                        .reply_unchecked()?
+                        .and_then(|reply| reply.value32().and_then(|mut value| value.next())),
+                    None => None,
+                };
+
+                let children = match tree {
+                    Some(cookie) => cookie
+                        .reply_unchecked()?
+                        .map_or_else(Vec::new, |tree| tree.children),
+                    None => vec![],
+                };
+
+                Ok(WindowInfo { pid, children })
+            })
+            .collect()
+    }
+
+    /// Captures the area covering every display of `screen` with a single XShm request and
+    /// cuts the displays out of it, so they're all from the very same moment. Returns `None`
+    /// if the area doesn't fit the segment, which happens once the screen grew.
+    fn capture_all_shm(
+        &self,
+        shm: &ShmSegment,
+        screen: &CapturedScreen,
+    ) -> Result<Option<Vec<RgbImage>>, ConnectionError> {
+        let format = &screen.format;
+
+        let left = self
+            .displays
+            .iter()
+            .map(|d| d.left as i32)
+            .min()
+            .unwrap_or(0);
+        let top = self
+            .displays
+            .iter()
+            .map(|d| d.top as i32)
+            .min()
+            .unwrap_or(0);
+        let right = self
+            .displays
+            .iter()
+            .map(|d| d.left as i32 + d.width as i32)
+            .max()
+            .unwrap_or(0);
+        let bottom = self
+            .displays
+            .iter()
+            .map(|d| d.top as i32 + d.height as i32)
+            .max()
+            .unwrap_or(0);
+
+        let (width, height) = ((right - left) as u16, (bottom - top) as u16);
+
+        if format.stride(width) * height as usize > shm.size {
+            return Ok(None);
+        }
+
+        let reply = self
+            .connection
+            .shm_get_image(
+                screen.root,
+                left as i16,
+                top as i16,
+                width,
+                height,
+                PLANE_MASK,
+                ImageFormat::Z_PIXMAP.into(),
+                shm.seg,
+                0,
+            )?
+            .reply_unchecked()?
+            .ok_or(ConnectionError::UnknownError)?;
+
+        let data = unsafe { std::slice::from_raw_parts(shm.addr, reply.size as usize) };
+
+        Ok(Some(
+            self.displays
+                .iter()
+                .map(|display| {
+                    let stride = format.stride(width);
+                    let offset = (display.top as i32 - top) as usize * stride
+                        + (display.left as i32 - left) as usize * format.bytes_per_pixel();
+
+                    format.decode_rows(&data[offset..], stride, display.width, display.height)
+                })
+                .collect(),
+        ))
+    }
+
+    /// Sends the requests for every display before waiting for any of the replies, so the
+    /// server handles them back to back.
+    fn capture_all_pipelined(&self) -> Result<Vec<RgbImage>, ConnectionError> {
+        let cookies = self
+            .displays
+            .iter()
+            .zip(&self.display_screens)
+            .map(|(display, screen)| {
+                self.connection.get_image(
+                    ImageFormat::Z_PIXMAP,
+                    self.screens[*screen].root,
+                    display.left,
+                    display.top,
+                    display.width,
+                    display.height,
+                    PLANE_MASK,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        cookies
+            .into_iter()
+            .zip(self.displays.iter().zip(&self.display_screens))
+            .map(|(cookie, (display, screen))| {
+                let x11_image = cookie
+                    .reply_unchecked()?
+                    .ok_or(ConnectionError::UnknownError)?;
+
+                Ok(self.screens[*screen].format.decode(
+                    &x11_image.data,
+                    display.width,
+                    display.height,
+                ))
+            })
+            .collect()
+    }
+}
+
+/// What [`excluded_windows`] needs to know about a window.
+struct WindowInfo {
+    /// The process that created the window, as its `_NET_WM_PID` property says.
+    pid: Option<u32>,
+    children: Vec<Window>,
+}
+
+/// Returns whether each of the top-level `windows` is excluded, because it or one of its
+/// descendants up to `depth` levels down is, which finds the windows the window manager
+/// wraps in frames. `own_pid` is the process whose windows
+/// [`Exclusions::own_windows`] means.
+///
+/// `describe` is asked about all the windows of a level at once, and only for their
+/// children above the last one, so a capture takes a round trip per level rather than per
+/// window.
+fn excluded_windows<E>(
+    exclusions: &Exclusions,
+    own_pid: u32,
+    windows: &[Window],
+    depth: u32,
+    mut describe: impl FnMut(&[Window], bool) -> Result<Vec<WindowInfo>, E>,
+) -> Result<Vec<bool>, E> {
+    let mut excluded = vec![false; windows.len()];
+
+    // The windows of the current level, each with the index of the top-level one it's in.
+    let mut level: Vec<(Window, usize)> = windows.iter().copied().zip(0..).collect();
+
+    for remaining in (0..=depth).rev() {
+        for &(window, top) in &level {
+            if exclusions.windows.contains(&window) {
+                excluded[top] = true;
+            }
+        }
+
+        level.retain(|&(_, top)| !excluded[top]);
+
+        let with_children = remaining > 0;
+
+        if level.is_empty() || !(exclusions.own_windows || with_children) {
+            break;
+        }
+
+        let infos = describe(
+            &level.iter().map(|&(window, _)| window).collect::<Vec<_>>(),
+            with_children,
+        )?;
+
+        let mut next = vec![];
+
+        for (&(_, top), info) in level.iter().zip(infos) {
+            if exclusions.own_windows && info.pid == Some(own_pid) {
+                excluded[top] = true;
+            }
+
+            next.extend(info.children.into_iter().map(|child| (child, top)));
+        }
+
+        next.retain(|&(_, top)| !excluded[top]);
+        level = next;
+    }
+
+    Ok(excluded)
+}
+
+/// How the pixels of the root window are laid out in ZPixmap images, which depends on the
+/// depth of its visual and on the server.
+struct PixelFormat {
+    bits_per_pixel: u8,
+    scanline_pad: u8,
+    msb_first: bool,
+    red_mask: u32,
+    green_mask: u32,
+    blue_mask: u32,
+}
+
+impl PixelFormat {
+    /// The format of the root window's images.
+    fn new(setup: &Setup, screen: &Screen) -> Result<PixelFormat, ConnectionError> {
+        PixelFormat::of_visual(setup, screen, screen.root_depth, screen.root_visual)
+    }
+
+    /// The format of images of a `depth` deep drawable without a visual, like a pixmap. It's
+    /// the root window's if the depth matches, otherwise the first TrueColor visual's.
+    fn of_depth(setup: &Setup, screen: &Screen, depth: u8) -> Result<PixelFormat, ConnectionError> {
+        if depth == screen.root_depth {
+            return PixelFormat::new(setup, screen);
+        }
+
+        let visual = screen
+            .allowed_depths
+            .iter()
+            .filter(|allowed| allowed.depth == depth)
+            .flat_map(|allowed| &allowed.visuals)
+            .find(|visual| visual.class == VisualClass::TRUE_COLOR)
+            .ok_or_else(|| {
+                ConnectionError::IoError(Error::new(
+                    ErrorKind::Unsupported,
+                    "Couldn't find a visual of the depth",
+                ))
+            })?;
+
+        PixelFormat::of_visual(setup, screen, depth, visual.visual_id)
+    }
+
+    /// The format of images of a `depth` deep drawable with `visual`.
+    fn of_visual(
+        setup: &Setup,
+        screen: &Screen,
+        depth: u8,
+        visual: Visualid,
+    ) -> Result<PixelFormat, ConnectionError> {
+        let unsupported = |message: &str| {
+            ConnectionError::IoError(Error::new(ErrorKind::Unsupported, message.to_owned()))
+        };
+
+        let pixmap_format = setup
+            .pixmap_formats
+            .iter()
+            .find(|format| format.depth == depth)
+            .ok_or_else(|| unsupported("Couldn't find the pixmap format of the depth"))?;
+
+        let visual = screen
+            .allowed_depths
+            .iter()
+            .flat_map(|depth| &depth.visuals)
+            .find(|candidate| candidate.visual_id == visual)
+            .ok_or_else(|| unsupported("Couldn't find the visual"))?;
+
+        if visual.class != VisualClass::TRUE_COLOR && visual.class != VisualClass::DIRECT_COLOR {
+            return Err(unsupported(
+                "Only TrueColor and DirectColor visuals are supported",
+            ));
+        }
+
+        if !matches!(pixmap_format.bits_per_pixel, 8 | 16 | 24 | 32) {
+            return Err(unsupported("Unsupported amount of bits per pixel"));
+        }
+
+        Ok(PixelFormat {
+            bits_per_pixel: pixmap_format.bits_per_pixel,
+            scanline_pad: pixmap_format.scanline_pad,
+            msb_first: setup.image_byte_order == ImageOrder::MSB_FIRST,
+            red_mask: visual.red_mask,
+            green_mask: visual.green_mask,
+            blue_mask: visual.blue_mask,
+        })
+    }
+
+    fn bytes_per_pixel(&self) -> usize {
+        self.bits_per_pixel as usize / 8
+    }
+
+    /// How many bytes a row of `width` pixels takes up, including the padding at its end.
+    fn stride(&self, width: u16) -> usize {
+        let pad = self.scanline_pad.max(8) as usize;
+        let bits = width as usize * self.bits_per_pixel as usize;
+
+        (bits + pad - 1) / pad * pad / 8
+    }
+
+    fn decode(&self, data: &[u8], width: u16, height: u16) -> RgbImage {
+        self.decode_rows(data, self.stride(width), width, height)
+    }
+
+    /// Decodes `height` rows of `width` pixels which start `stride` bytes apart.
+    fn decode_rows(&self, data: &[u8], stride: usize, width: u16, height: u16) -> RgbImage {
+        if self.is_bgrx() {
+            return bgrx_to_rgb(data, stride, width as u32, height as u32);
+        }
+
+        let mut image = RgbImage::new(width as u32, height as u32);
+        let row = width as usize * self.bytes_per_pixel();
+
+        if width == 0 {
+            return image;
+        }
+
+        for (y, target) in image.chunks_exact_mut(width as usize * 3).enumerate() {
+            self.decode_row(&data[y * stride..y * stride + row], target);
+        }
+
+        image
+    }
+
+    /// Decodes a row of pixels in `source` into RGB pixels in `target`.
+    fn decode_row(&self, source: &[u8], target: &mut [u8]) {
+        if self.is_bgrx() {
+            return bgrx_row_to_rgb(source, target);
+        }
+
+        let channels = [
+            Channel::new(self.red_mask),
+            Channel::new(self.green_mask),
+            Channel::new(self.blue_mask),
+        ];
+
+        for (bytes, rgb) in source
+            .chunks_exact(self.bytes_per_pixel())
+            .zip(target.chunks_exact_mut(3))
+        {
+            let pixel = if self.msb_first {
+                bytes
+                    .iter()
+                    .fold(0, |pixel, &byte| pixel << 8 | byte as u32)
+            } else {
+                bytes
+                    .iter()
+                    .rev()
+                    .fold(0, |pixel, &byte| pixel << 8 | byte as u32)
+            };
+
+            for (value, channel) in rgb.iter_mut().zip(&channels) {
+                *value = channel.extract(pixel);
+            }
+        }
+    }
+
+    /// By far the most common format, little-endian 24-bit colour in 32-bit pixels, which
+    /// has a fast path.
+    fn is_bgrx(&self) -> bool {
+        self.bits_per_pixel == 32
+            && !self.msb_first
+            && (self.red_mask, self.green_mask, self.blue_mask) == (0xff0000, 0xff00, 0xff)
+    }
+}
+
+/// Where a colour channel sits in a pixel, given by the mask of its visual.
+struct Channel {
+    mask: u32,
+    shift: u32,
+    bits: u32,
+}
+
+impl Channel {
+    fn new(mask: u32) -> Channel {
+        Channel {
+            mask,
+            shift: mask.trailing_zeros().min(31),
+            bits: mask.count_ones(),
+        }
+    }
+
+    /// Extracts the channel from `pixel`, scaled to 8 bits.
+    fn extract(&self, pixel: u32) -> u8 {
+        let value = (pixel & self.mask) >> self.shift;
+
+        match self.bits {
+            0 => 0,
+            bits if bits >= 8 => (value >> (bits - 8)) as u8,
+            bits => {
+                let max = (1 << bits) - 1;
+                ((value * 255 + max / 2) / max) as u8
+            }
+        }
+    }
+}
+
+impl Drop for X11Capturer {
+    fn drop(&mut self) {
+        if let Some(shm) = &mut self.shm {
+            shm.get_mut()
+                .unwrap_or_else(PoisonError::into_inner)
+                .detach(&self.connection);
+        }
+    }
+}
+
+impl Capturer for X11Capturer {
+    fn capture(&self, index: usize) -> Result<RgbImage, ConnectionError> {
+        Ok(self.capture_redacted(index)?.0)
+    }
+
+    fn capture_frame(&self, index: usize) -> Result<Frame, ConnectionError> {
+        let (display, screen) = self.display(index)?;
+
+        let started_at = SystemTime::now();
+
+        // Sent before the capture, so the server answers with where the cursor was then.
+        let pointer = self.connection.query_pointer(screen.root)?;
+
+        let (image, backend) = self.capture_redacted(index)?;
+
+        let finished_at = SystemTime::now();
+
+        let cursor = pointer
+            .reply_unchecked()?
+            .filter(|pointer| pointer.same_screen)
+            .map(|pointer| (pointer.root_x as i32, pointer.root_y as i32));
+
+        Ok(Frame {
+            image,
+            index,
+            display: *display,
+            started_at,
+            finished_at,
+            backend,
+            cursor,
+        })
+    }
+
+    fn capture_primary(&self) -> Result<RgbImage, ConnectionError> {
+        self.capture(self.primary_display_index)
+    }
+
+    fn capture_all(&self) -> Result<Vec<RgbImage>, ConnectionError> {
+        let mut vec = vec![];
+        for i in 0..self.displays.len() {
+            vec.push(self.capture(i)?);
+        }
+        Ok(vec)
+    }
+
+    fn capture_all_at_once(&self) -> Result<(SystemTime, Vec<RgbImage>), ConnectionError> {
+        let captured_at = SystemTime::now();
 
-                    if (shm_addr as isize) < 0 {
-                        return Err(ConnectionError::IoError(Error::last_os_error()));
-                    }
+        if !self.exclusions.is_empty() {
+            return Ok((captured_at, self.capture_all()?));
+        }
 
-                    connection.shm_attach(seg, shm_id as u32, false)?;
+        let masks = (0..self.displays.len())
+            .map(|index| {
+                let (_, screen) = self.display(index)?;
+                self.request_masks(screen.root)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
-                    (Some(seg), Some(shm_id), shm_addr as *const u8)
-                },
-                Err(_) => (None, None, ptr::null()),
+        // A single request only covers one root window.
+        let captured = match (self.lock_shm(), &self.screens[..]) {
+            (Some(shm), [screen]) if !self.displays.is_empty() => {
+                self.capture_all_shm(&shm, screen)?
             }
+            _ => None,
         };
 
-        let (primary_display_index, displays) = get_displays(&connection, screen)?;
+        let mut images = match captured {
+            Some(images) => images,
+            None => self.capture_all_pipelined()?,
+        };
 
-        Ok(X11Capturer {
-            screen,
-            displays,
-            primary_display_index,
-            connection,
-            shm_addr,
-            shm_id,
-            seg,
-        })
+        for ((index, image), masks) in images.iter_mut().enumerate().zip(masks) {
+            self.redact(image, index, masks)?;
+        }
+
+        Ok((captured_at, images))
     }
 
-    /// Captures the screen using standard protocols, which are a lot less inefficient.
-    fn capture_standard(&self, index: usize) -> Result<RgbImage, ConnectionError> {
-        let display = self.displays.get(index).ok_or_else(|| {
-            ConnectionError::IoError(Error::new(
-                ErrorKind::NotFound,
-                "Couldn't find specified Display",
-            ))
+    fn capture_scaled(
+        &self,
+        index: usize,
+        scale: Scale,
+        filter: ScaleFilter,
+    ) -> Result<RgbImage, ConnectionError> {
+        if !self.exclusions.is_empty() {
+            return Ok(scale::scale_image(&self.capture(index)?, scale, filter));
+        }
+
+        let (_, screen) = self.display(index)?;
+        let masks = self.request_masks(screen.root)?;
+
+        let (mut image, _) = self.capture_with(index, |data, display, format| {
+            let stride = format.stride(display.width);
+            let row = display.width as usize * format.bytes_per_pixel();
+            let size = (display.width as u32, display.height as u32);
+
+            scale_rows(
+                size,
+                scale.target_size(size.0, size.1),
+                filter,
+                |y, target| format.decode_row(&data[y * stride..y * stride + row], target),
+            )
         })?;
 
-        let screen = &self.connection.setup().roots[self.screen];
+        self.redact(&mut image, index, masks)?;
 
-        let root = screen.root;
+        Ok(image)
+    }
 
-        let x11_image = self
-            .connection
-            .get_image(
-                ImageFormat::Z_PIXMAP,
-                root,
-                display.left as i16,
-                display.top as i16,
-                display.width,
-                display.height,
-                PLANE_MASK,
-            )?
-            .reply_unchecked()?
-            .ok_or(ConnectionError::UnknownError)?;
+    #[cfg(any(feature = "png", feature = "jpeg", feature = "webp", feature = "qoi"))]
+    fn capture_encoded(
+        &self,
+        index: usize,
+        format: crate::ImageFormat,
+        quality: u8,
+    ) -> Result<Vec<u8>, EncodeError> {
+        let (_, screen) = self.display(index)?;
 
-        let data: &[Bgr] = unsafe {
-            std::slice::from_raw_parts(x11_image.data.as_ptr() as _, x11_image.data.len())
-        };
+        if !self.exclusions.is_empty() || !self.redactions.is_empty() || !screen.format.is_bgrx() {
+            let mut encoded = vec![];
+            crate::encode::encode(&self.capture(index)?, &mut encoded, format, quality)?;
+            return Ok(encoded);
+        }
 
-        Ok(bgr_to_rgb_image(
-            data,
-            display.width as u32,
-            display.height as u32,
-        ))
+        self.capture_with(index, |data, display, pixel_format| {
+            let mut encoded = vec![];
+
+            encode_bgrx(
+                data,
+                pixel_format.stride(display.width),
+                display.width as u32,
+                display.height as u32,
+                &mut encoded,
+                format,
+                quality,
+            )?;
+
+            Ok(encoded)
+        })?
+        .0
     }
 
-    /// Captures the screen using the XShm protocol and shared memory causing the program to run
-    /// hella lot faster.
-    fn capture_shm(&self, index: usize) -> Result<RgbImage, ConnectionError> {
-        let display = self.displays.get(index).ok_or_else(|| {
-            ConnectionError::IoError(Error::new(
-                ErrorKind::NotFound,
-                "Couldn't find specified Display",
-            ))
-        })?;
+    /// Only asks the server for the pixels under `region`, so small regions and single
+    /// pixels are cheap.
+    fn capture_region(
+        &self,
+        index: usize,
+        region: Rect,
+        space: CoordinateSpace,
+    ) -> Result<RgbImage, ConnectionError> {
+        if !self.exclusions.is_empty() {
+            let (display, _) = self.display(index)?;
+            let region = display.physical_region(region, space);
 
-        let screen = &self.connection.setup().roots[self.screen];
+            return Ok(crop_visible(&self.capture(index)?, region));
+        }
 
-        let root = screen.root;
+        let (display, screen) = self.display(index)?;
+        let bounds = display.bounds();
 
-        let reply = self
-            .connection
-            .shm_get_image(
-                root,
-                display.left as i16,
-                display.top as i16,
-                display.width,
-                display.height,
-                PLANE_MASK,
-                ImageFormat::Z_PIXMAP.into(),
-                unsafe { self.seg.unwrap_unchecked() },
-                0,
-            )?
-            .reply_unchecked()?
-            .ok_or(ConnectionError::UnknownError)?;
+        let visible = match display
+            .physical_region(region, space)
+            .intersection(&Rect::new(0, 0, bounds.width, bounds.height))
+        {
+            Some(visible) => visible,
+            None => return Ok(RgbImage::new(0, 0)),
+        };
 
-        let data: &[Bgr] =
-            unsafe { std::slice::from_raw_parts(self.shm_addr as _, reply.size as usize) };
+        let area = Rect::new(
+            bounds.x + visible.x,
+            bounds.y + visible.y,
+            visible.width,
+            visible.height,
+        );
 
-        Ok(bgr_to_rgb_image(
-            data,
-            display.width as u32,
-            display.height as u32,
-        ))
+        let masks = self.request_masks(screen.root)?;
+
+        let (mut image, _) = self.capture_area(screen.root, area, &screen.format, |data| {
+            screen
+                .format
+                .decode(data, area.width as u16, area.height as u16)
+        })?;
+
+        self.redact_area(&mut image, area, masks)?;
+
+        Ok(image)
     }
-}
 
-impl Drop for X11Capturer {
-    fn drop(&mut self) {
-        if let Some(seg) = self.seg {
-            self.connection.shm_detach(seg).ok();
-            unsafe {
-                shmdt(self.shm_addr as _);
-                shmctl(self.shm_id.unwrap(), IPC_RMID, ptr::null_mut());
+    fn exclude_windows(&mut self, exclusions: Exclusions) -> Result<(), ConnectionError> {
+        if exclusions.is_empty() {
+            if self.redirected {
+                for screen in &self.screens {
+                    self.connection
+                        .composite_unredirect_subwindows(screen.root, Redirect::AUTOMATIC)?;
+                }
+                self.redirected = false;
+            }
+        } else if !self.redirected {
+            if self
+                .connection
+                .extension_information(composite::X11_EXTENSION_NAME)?
+                .is_none()
+            {
+                return Err(ConnectionError::UnsupportedExtension);
             }
+
+            // The server only offers the newer requests after this.
+            self.connection
+                .composite_query_version(0, 4)?
+                .reply_unchecked()?;
+            for screen in &self.screens {
+                self.connection
+                    .composite_redirect_subwindows(screen.root, Redirect::AUTOMATIC)?;
+            }
+            self.redirected = true;
         }
-    }
-}
 
-impl Capturer for X11Capturer {
-    fn capture(&self, index: usize) -> Result<RgbImage, ConnectionError> {
-        let image = match self.seg {
-            Some(_) => self.capture_shm(index)?,
-            None => self.capture_standard(index)?,
-        };
+        if exclusions.own_windows && self.net_wm_pid.is_none() {
+            // Created if no window set it yet, so it stays valid when one does.
+            self.net_wm_pid = Some(
+                self.connection
+                    .intern_atom(false, b"_NET_WM_PID")?
+                    .reply()
+                    .map_err(reply_error)?
+                    .atom,
+            );
+        }
 
-        Ok(image)
+        self.exclusions = exclusions;
+        Ok(())
     }
 
-    fn capture_primary(&self) -> Result<RgbImage, ConnectionError> {
-        Ok(self.capture(self.primary_display_index)?)
+    fn primary_display(&self) -> usize {
+        self.primary_display_index
     }
 
-    fn capture_all(&self) -> Result<Vec<RgbImage>, ConnectionError> {
-        let mut vec = vec![];
-        for i in 0..self.displays.len() {
-            vec.push(self.capture(i)?);
-        }
-        Ok(vec)
+    fn redactions(&self) -> &Redactions {
+        &self.redactions
     }
 
     fn displays(&self) -> &[Display] {
@@ -207,25 +1592,37 @@ impl Capturer for X11Capturer {
     }
 
     fn refresh_displays(&mut self) -> Result<(), ConnectionError> {
-        let (primary_display_index, displays) = get_displays(&self.connection, self.screen)?;
+        let (primary_display_index, displays, display_screens) =
+            get_all_displays(&self.connection, &self.screens)?;
         self.primary_display_index = primary_display_index;
         self.displays = displays;
+        self.display_screens = display_screens;
         Ok(())
     }
 }
 
-fn bgr_to_rgb_image(data: &[Bgr], width: u32, height: u32) -> RgbImage {
-    let mut image = RgbImage::new(width, height);
+/// Lists the displays of every screen in `screens` together with the index of the screen
+/// each of them is on. The primary display is the one of the first screen.
+fn get_all_displays(
+    connection: &RustConnection,
+    screens: &[CapturedScreen],
+) -> Result<(usize, Vec<Display>, Vec<usize>), ConnectionError> {
+    let mut primary_display_index = 0;
+    let mut displays = vec![];
+    let mut display_screens = vec![];
+
+    for (index, screen) in screens.iter().enumerate() {
+        let (primary, screen_displays) = get_displays(connection, screen.number)?;
 
-    let mut i = 0;
+        if index == 0 {
+            primary_display_index = primary;
+        }
 
-    for pixel in image.pixels_mut() {
-        let Bgr { r, g, b, .. } = data[i];
-        pixel.0 = [r, g, b];
-        i += 1;
+        display_screens.extend(iter::repeat(index).take(screen_displays.len()));
+        displays.extend(screen_displays);
     }
 
-    image
+    Ok((primary_display_index, displays, display_screens))
 }
 
 fn get_displays(
@@ -263,13 +1660,21 @@ fn get_displays(
         }
     };
 
+    let xft_dpi = xft_dpi(connection, screen.root)?;
+
     for crtc in crtcs {
         if let Some(crtc_info) = connection.randr_get_crtc_info(crtc, 0)?.reply_unchecked()? {
+            let scale_factor = match xft_dpi {
+                Some(dpi) => dpi / 96.0,
+                None => physical_scale_factor(connection, &crtc_info)?,
+            };
+
             let display = Display {
-                top: crtc_info.y.into(),
-                left: crtc_info.x.into(),
-                width: crtc_info.width.into(),
-                height: crtc_info.height.into(),
+                top: crtc_info.y,
+                left: crtc_info.x,
+                width: crtc_info.width,
+                height: crtc_info.height,
+                scale_factor,
             };
             if display.top == 0 && display.left == 0 {
                 primary_display_index = displays.len();
@@ -280,3 +1685,646 @@ fn get_displays(
 
     Ok((primary_display_index, displays))
 }
+
+/// Returns `Xft.dpi` from the resource database, which desktop environments set for HiDPI
+/// screens. X11 only has the one for every display.
+fn xft_dpi(connection: &RustConnection, root: Window) -> Result<Option<f64>, ConnectionError> {
+    let resources = match connection
+        .get_property(
+            false,
+            root,
+            AtomEnum::RESOURCE_MANAGER,
+            AtomEnum::STRING,
+            0,
+            u32::MAX,
+        )?
+        .reply_unchecked()?
+    {
+        Some(reply) => reply.value,
+        None => return Ok(None),
+    };
+
+    Ok(String::from_utf8_lossy(&resources)
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim() == "Xft.dpi")
+        .and_then(|(_, dpi)| dpi.trim().parse::<f64>().ok())
+        .filter(|dpi| *dpi > 0.0))
+}
+
+/// Guesses the scale factor of a CRTC from the physical size of its first output. Monitors
+/// only report their size roughly, so it's rounded to a quarter and never goes below 1.
+fn physical_scale_factor(
+    connection: &RustConnection,
+    crtc_info: &randr::GetCrtcInfoReply,
+) -> Result<f64, ConnectionError> {
+    let output = match crtc_info.outputs.first() {
+        Some(output) => *output,
+        None => return Ok(1.0),
+    };
+
+    let output_info = match connection
+        .randr_get_output_info(output, 0)?
+        .reply_unchecked()?
+    {
+        Some(output_info) => output_info,
+        None => return Ok(1.0),
+    };
+
+    // Rotated CRTCs swap their width and height, the output's size doesn't change.
+    let pixels = crtc_info.width.max(crtc_info.height) as f64;
+    let millimeters = output_info.mm_width.max(output_info.mm_height) as f64;
+
+    if millimeters == 0.0 {
+        return Ok(1.0);
+    }
+
+    let dpi = pixels * 25.4 / millimeters;
+
+    Ok(((dpi / 96.0 * 4.0).round() / 4.0).max(1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x11rb::protocol::xproto::{Depth, Format, Visualtype};
+
+    /// Connects to the server in `$DISPLAY`. Tests that need one are ignored, run them with
+    /// `cargo test -- --ignored` under a server whose screen doesn't change, like Xvfb's.
+    fn connect() -> X11Capturer {
+        X11Capturer::new(X11Options::default()).expect("Couldn't connect to $DISPLAY")
+    }
+
+    #[test]
+    #[ignore = "needs an X server"]
+    fn shm_and_pipelined_captures_match() {
+        let capturer = connect();
+
+        if capturer.screens.len() != 1 {
+            return;
+        }
+
+        let through_shm = match capturer.lock_shm() {
+            Some(shm) => capturer
+                .capture_all_shm(&shm, &capturer.screens[0])
+                .unwrap(),
+            None => return,
+        };
+        let pipelined = capturer.capture_all_pipelined().unwrap();
+
+        let through_shm = through_shm.expect("The displays don't fit the segment");
+
+        assert_eq!(through_shm.len(), pipelined.len());
+        assert!(through_shm == pipelined, "The captures differ");
+    }
+
+    #[test]
+    #[ignore = "needs an X server"]
+    fn captures_fall_back_when_the_segment_is_too_small() {
+        let mut capturer = connect();
+
+        if capturer.screens.len() != 1 || capturer.shm.is_none() {
+            return;
+        }
+
+        let expected = capturer.capture_all_pipelined().unwrap();
+
+        // Like after the screen grew past the size the segment was made for.
+        let small = ShmSegment::attach(&capturer.connection, 16)
+            .unwrap()
+            .expect("Couldn't Attach Segment");
+        let original = capturer.shm.replace(Mutex::new(small)).unwrap();
+        original
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+            .detach(&capturer.connection);
+
+        assert!(capturer
+            .capture_all_shm(&capturer.lock_shm().unwrap(), &capturer.screens[0])
+            .unwrap()
+            .is_none());
+
+        let (_, images) = capturer.capture_all_at_once().unwrap();
+
+        assert!(images == expected, "The captures differ");
+    }
+
+    #[test]
+    #[ignore = "needs an X server"]
+    fn frames_report_the_protocol_they_were_captured_with() {
+        let mut capturer = connect();
+
+        let expected = match capturer.shm {
+            Some(_) => Backend::Shm,
+            None => Backend::Standard,
+        };
+        assert_eq!(capturer.capture_frame(0).unwrap().backend, expected);
+
+        if let Some(shm) = capturer.shm.take() {
+            let small = ShmSegment::attach(&capturer.connection, 16)
+                .unwrap()
+                .expect("Couldn't Attach Segment");
+            capturer.shm = Some(Mutex::new(small));
+
+            shm.into_inner()
+                .unwrap_or_else(PoisonError::into_inner)
+                .detach(&capturer.connection);
+
+            // Too big for the segment, so it went through get_image.
+            assert_eq!(
+                capturer.capture_frame(0).unwrap().backend,
+                Backend::Standard
+            );
+        }
+
+        capturer
+            .exclude_windows(Exclusions {
+                own_windows: true,
+                windows: vec![],
+            })
+            .unwrap();
+
+        assert_eq!(
+            capturer.capture_frame(0).unwrap().backend,
+            Backend::Composited
+        );
+    }
+
+    #[test]
+    #[ignore = "needs an X server"]
+    fn drawables_are_redacted_like_the_screen() {
+        use x11rb::protocol::xproto::{CreateWindowAux, WindowClass};
+
+        let capturer = connect();
+
+        let connection = &capturer.connection;
+        let screen = &connection.setup().roots[capturer.screens[0].number];
+
+        let window = connection.generate_id().unwrap();
+        connection
+            .create_window(
+                screen.root_depth,
+                window,
+                screen.root,
+                10,
+                20,
+                40,
+                30,
+                0,
+                WindowClass::INPUT_OUTPUT,
+                0,
+                &CreateWindowAux::new()
+                    .background_pixel(screen.white_pixel)
+                    .override_redirect(1),
+            )
+            .unwrap();
+        connection.map_window(window).unwrap();
+        connection.clear_area(false, window, 0, 0, 0, 0).unwrap();
+        connection.get_input_focus().unwrap().reply().unwrap();
+
+        let pixmap = connection.generate_id().unwrap();
+        connection
+            .create_pixmap(screen.root_depth, pixmap, screen.root, 8, 8)
+            .unwrap();
+
+        assert!(capturer.capture_drawable(pixmap, None).is_ok());
+
+        // The top left quarter of the window, in screen coordinates.
+        capturer
+            .redactions
+            .add(Mask::Rect(Rect::new(10, 20, 20, 15)), RedactStyle::Black);
+
+        let image = capturer.capture_drawable(window, None).unwrap();
+
+        assert_eq!(image.dimensions(), (40, 30));
+        assert_eq!(image.get_pixel(19, 14).0, [0, 0, 0]);
+        assert_eq!(image.get_pixel(20, 15).0, [255, 255, 255]);
+
+        let image = capturer
+            .capture_drawable(window, Some(Rect::new(10, 10, 20, 10)))
+            .unwrap();
+
+        assert_eq!(image.get_pixel(9, 4).0, [0, 0, 0]);
+        assert_eq!(image.get_pixel(10, 5).0, [255, 255, 255]);
+
+        assert!(capturer.capture_drawable(pixmap, None).is_err());
+
+        connection.free_pixmap(pixmap).unwrap();
+        connection.destroy_window(window).unwrap();
+        connection.flush().unwrap();
+    }
+
+    /// Writes an Xauthority file with an entry for each `(display number, name, data)`.
+    fn auth_file(name: &str, entries: &[(&str, &str, &[u8])]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("captis-xauth-{}-{}", std::process::id(), name));
+        let mut file = vec![];
+
+        for (number, name, data) in entries {
+            file.extend_from_slice(&256u16.to_be_bytes());
+
+            for field in [&b"host"[..], number.as_bytes(), name.as_bytes(), data] {
+                file.extend_from_slice(&(field.len() as u16).to_be_bytes());
+                file.extend_from_slice(field);
+            }
+        }
+
+        fs::write(&path, file).unwrap();
+        path
+    }
+
+    #[test]
+    fn reads_the_cookie_of_the_display() {
+        let path = auth_file(
+            "display",
+            &[
+                ("0", "MIT-MAGIC-COOKIE-1", b"zero"),
+                ("1", "XDM-AUTHORIZATION-1", b"other"),
+                ("1", "MIT-MAGIC-COOKIE-1", b"one"),
+            ],
+        );
+
+        let cookie = read_auth_file(&path, 1).unwrap();
+        let missing = read_auth_file(&path, 2).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            cookie,
+            Some((b"MIT-MAGIC-COOKIE-1".to_vec(), b"one".to_vec()))
+        );
+        assert_eq!(missing, None);
+    }
+
+    #[test]
+    fn reads_cookies_for_any_display() {
+        let path = auth_file("wildcard", &[("", "MIT-MAGIC-COOKIE-1", b"any")]);
+
+        let cookie = read_auth_file(&path, 7).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            cookie,
+            Some((b"MIT-MAGIC-COOKIE-1".to_vec(), b"any".to_vec()))
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_auth_files() {
+        let path = auth_file("truncated", &[("1", "MIT-MAGIC-COOKIE-1", b"one")]);
+        let file = fs::read(&path).unwrap();
+        fs::write(&path, &file[..file.len() - 2]).unwrap();
+
+        let error = read_auth_file(&path, 1).unwrap_err();
+        fs::remove_file(path).unwrap();
+
+        match error {
+            ConnectionError::IoError(error) => assert_eq!(error.kind(), ErrorKind::InvalidData),
+            error => panic!("Unexpected error {:?}", error),
+        }
+    }
+
+    #[test]
+    fn parses_display_strings() {
+        let parsed = parse_display(Some(":1.2")).unwrap();
+        assert_eq!((parsed.display, parsed.screen), (1, 2));
+
+        let parsed = parse_display(Some("host:3")).unwrap();
+        assert_eq!(
+            (parsed.host.as_str(), parsed.display, parsed.screen),
+            ("host", 3, 0)
+        );
+
+        assert!(parse_display(Some("no display")).is_none());
+    }
+
+    #[test]
+    fn reports_why_it_couldnt_connect() {
+        let error = X11Options::new()
+            .display("no display")
+            .auth_file("/nonexistent")
+            .connect()
+            .unwrap_err();
+
+        assert!(
+            error.to_string().contains("Display parsing error"),
+            "{:?}",
+            error
+        );
+    }
+
+    #[test]
+    fn picks_the_screens_to_capture() {
+        let screens = |options: X11Options, default, count| {
+            options
+                .screen_numbers(default, count)
+                .map_err(|error| match error {
+                    ConnectionError::IoError(error) => error.kind(),
+                    error => panic!("Unexpected error {:?}", error),
+                })
+        };
+
+        assert_eq!(screens(X11Options::new(), 1, 2), Ok(vec![1]));
+        assert_eq!(
+            screens(X11Options::new().all_screens(), 0, 3),
+            Ok(vec![0, 1, 2])
+        );
+        assert_eq!(
+            screens(X11Options::new().screens([2, 0]), 0, 3),
+            Ok(vec![2, 0])
+        );
+        assert_eq!(
+            screens(X11Options::new().screens([0, 3]), 0, 3),
+            Err(ErrorKind::NotFound)
+        );
+        assert_eq!(
+            screens(X11Options::new().screens(None), 0, 3),
+            Err(ErrorKind::InvalidInput)
+        );
+    }
+
+    /// A server whose only screen has a `depth` deep root window, with pixmap format
+    /// `(bits_per_pixel, scanline_pad)` and the colour masks `masks`.
+    fn server(
+        depth: u8,
+        (bits_per_pixel, scanline_pad): (u8, u8),
+        masks: (u32, u32, u32),
+        byte_order: ImageOrder,
+    ) -> Setup {
+        let visual = Visualtype {
+            visual_id: 0x21,
+            class: VisualClass::TRUE_COLOR,
+            red_mask: masks.0,
+            green_mask: masks.1,
+            blue_mask: masks.2,
+            ..Visualtype::default()
+        };
+
+        Setup {
+            image_byte_order: byte_order,
+            pixmap_formats: vec![Format {
+                depth,
+                bits_per_pixel,
+                scanline_pad,
+            }],
+            roots: vec![Screen {
+                root_visual: visual.visual_id,
+                root_depth: depth,
+                allowed_depths: vec![Depth {
+                    depth,
+                    visuals: vec![visual],
+                }],
+                ..Screen::default()
+            }],
+            ..Setup::default()
+        }
+    }
+
+    fn root_format(setup: &Setup) -> PixelFormat {
+        PixelFormat::new(setup, &setup.roots[0]).unwrap()
+    }
+
+    const RGB565: (u32, u32, u32) = (0xf800, 0x07e0, 0x001f);
+    const RGB888: (u32, u32, u32) = (0xff0000, 0x00ff00, 0x0000ff);
+
+    #[test]
+    fn decodes_16_bit_565() {
+        let format = root_format(&server(16, (16, 32), RGB565, ImageOrder::LSB_FIRST));
+
+        assert!(!format.is_bgrx());
+        // Three pixels take 6 bytes, padded to a multiple of 32 bits.
+        assert_eq!(format.stride(3), 8);
+
+        let pixels: [u16; 3] = [0xf800, 0x07e0, 16 << 11 | 32 << 5 | 8];
+        let mut data = vec![];
+
+        for _ in 0..2 {
+            for pixel in pixels {
+                data.extend_from_slice(&pixel.to_le_bytes());
+            }
+            data.extend_from_slice(&[0xee, 0xee]);
+        }
+
+        let image = format.decode(&data, 3, 2);
+
+        for y in 0..2 {
+            assert_eq!(image.get_pixel(0, y).0, [255, 0, 0]);
+            assert_eq!(image.get_pixel(1, y).0, [0, 255, 0]);
+            // 16 of 31, 32 of 63 and 8 of 31, scaled to 255 and rounded.
+            assert_eq!(image.get_pixel(2, y).0, [132, 130, 66]);
+        }
+    }
+
+    #[test]
+    fn decodes_16_bit_565_msb_first() {
+        let format = root_format(&server(16, (16, 16), RGB565, ImageOrder::MSB_FIRST));
+
+        assert_eq!(format.stride(3), 6);
+
+        let data: Vec<u8> = [0xf800u16, 0x001f, 0xffff]
+            .iter()
+            .flat_map(|pixel| pixel.to_be_bytes())
+            .collect();
+
+        let image = format.decode(&data, 3, 1);
+
+        assert_eq!(image.as_raw(), &[255, 0, 0, 0, 0, 255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn decodes_24_bit_in_32_bit_pixels() {
+        let lsb = root_format(&server(24, (32, 32), RGB888, ImageOrder::LSB_FIRST));
+        let msb = root_format(&server(24, (32, 32), RGB888, ImageOrder::MSB_FIRST));
+
+        assert!(lsb.is_bgrx());
+        assert!(!msb.is_bgrx());
+        assert_eq!(lsb.stride(5), 20);
+
+        let lsb_data = [0x30, 0x20, 0x10, 0xff, 0x03, 0x02, 0x01, 0x00];
+        let msb_data = [0xff, 0x10, 0x20, 0x30, 0x00, 0x01, 0x02, 0x03];
+
+        let expected = [0x10, 0x20, 0x30, 0x01, 0x02, 0x03];
+
+        assert_eq!(lsb.decode(&lsb_data, 2, 1).as_raw(), &expected);
+        assert_eq!(msb.decode(&msb_data, 2, 1).as_raw(), &expected);
+    }
+
+    #[test]
+    fn decodes_packed_24_bit_pixels_with_padding() {
+        let format = root_format(&server(24, (24, 32), RGB888, ImageOrder::LSB_FIRST));
+
+        assert!(!format.is_bgrx());
+        // 9 bytes padded to 12, and 3 padded to 4.
+        assert_eq!(format.stride(3), 12);
+        assert_eq!(format.stride(1), 4);
+
+        let mut data = vec![];
+
+        for row in 0..2u8 {
+            data.extend_from_slice(&[row, 0, 0xff, 0, 0xff, row, 0xff, row, 0]);
+            data.extend_from_slice(&[0xee; 3]);
+        }
+
+        let image = format.decode(&data, 3, 2);
+
+        for row in 0..2u8 {
+            let y = row as u32;
+
+            assert_eq!(image.get_pixel(0, y).0, [0xff, 0, row]);
+            assert_eq!(image.get_pixel(1, y).0, [row, 0xff, 0]);
+            assert_eq!(image.get_pixel(2, y).0, [0, row, 0xff]);
+        }
+    }
+
+    #[test]
+    fn pixmaps_use_a_visual_of_their_depth() {
+        let mut setup = server(24, (32, 32), RGB888, ImageOrder::LSB_FIRST);
+
+        setup.pixmap_formats.push(Format {
+            depth: 16,
+            bits_per_pixel: 16,
+            scanline_pad: 32,
+        });
+        setup.roots[0].allowed_depths.push(Depth {
+            depth: 16,
+            visuals: vec![Visualtype {
+                visual_id: 0x42,
+                class: VisualClass::TRUE_COLOR,
+                red_mask: RGB565.0,
+                green_mask: RGB565.1,
+                blue_mask: RGB565.2,
+                ..Visualtype::default()
+            }],
+        });
+
+        let screen = &setup.roots[0];
+
+        assert!(PixelFormat::of_depth(&setup, screen, 24).unwrap().is_bgrx());
+
+        let format = PixelFormat::of_depth(&setup, screen, 16).unwrap();
+        assert_eq!(format.bits_per_pixel, 16);
+        assert_eq!(format.red_mask, RGB565.0);
+
+        assert!(PixelFormat::of_depth(&setup, screen, 8).is_err());
+    }
+
+    #[test]
+    fn rejects_unsupported_formats() {
+        let mut setup = server(24, (32, 32), RGB888, ImageOrder::LSB_FIRST);
+        setup.roots[0].allowed_depths[0].visuals[0].class = VisualClass::PSEUDO_COLOR;
+
+        assert!(PixelFormat::new(&setup, &setup.roots[0]).is_err());
+
+        let setup = server(4, (4, 32), (0x8, 0x4, 0x2), ImageOrder::LSB_FIRST);
+
+        assert!(PixelFormat::new(&setup, &setup.roots[0]).is_err());
+    }
+
+    #[test]
+    fn channels_scale_to_8_bits() {
+        let red = Channel::new(0xf800);
+        assert_eq!((red.shift, red.bits), (11, 5));
+        assert_eq!(red.extract(0xffff), 255);
+        assert_eq!(red.extract(0x07ff), 0);
+
+        // 10 bits per channel, as in 30-bit colour, keeps the top 8.
+        let deep = Channel::new(0x3ff0_0000);
+        assert_eq!(deep.extract(0x3ff0_0000), 255);
+        assert_eq!(deep.extract(0x2000_0000), 128);
+
+        assert_eq!(Channel::new(0).extract(u32::MAX), 0);
+    }
+
+    /// A window manager's frames around client windows, as `(window, pid, children)`.
+    const TREE: &[(Window, Option<u32>, &[Window])] = &[
+        (1, None, &[11]),
+        (11, Some(100), &[]),
+        (2, None, &[21]),
+        (21, None, &[211]),
+        (211, Some(200), &[]),
+        (3, Some(300), &[]),
+    ];
+
+    /// Runs [`excluded_windows`] on [`TREE`]'s top-level windows, returning which are
+    /// excluded and the windows of every lookup.
+    fn exclude(exclusions: Exclusions, depth: u32) -> (Vec<bool>, Vec<Vec<Window>>) {
+        let mut lookups = vec![];
+
+        let excluded =
+            excluded_windows(&exclusions, 200, &[1, 2, 3], depth, |windows, children| {
+                lookups.push(windows.to_vec());
+
+                Ok::<_, ()>(
+                    windows
+                        .iter()
+                        .map(|window| {
+                            let &(_, pid, descendants) =
+                                TREE.iter().find(|(id, ..)| id == window).unwrap();
+
+                            WindowInfo {
+                                pid,
+                                children: if children {
+                                    descendants.to_vec()
+                                } else {
+                                    vec![]
+                                },
+                            }
+                        })
+                        .collect(),
+                )
+            })
+            .unwrap();
+
+        (excluded, lookups)
+    }
+
+    #[test]
+    fn excludes_frames_around_excluded_windows() {
+        let (excluded, lookups) = exclude(
+            Exclusions {
+                own_windows: false,
+                windows: vec![11, 3],
+            },
+            3,
+        );
+
+        assert_eq!(excluded, [true, false, true]);
+        // Every level is looked up at once, windows already excluded aren't looked into.
+        assert_eq!(lookups, [vec![1, 2], vec![21], vec![211]]);
+    }
+
+    #[test]
+    fn excludes_own_windows_by_process() {
+        let (excluded, lookups) = exclude(
+            Exclusions {
+                own_windows: true,
+                windows: vec![],
+            },
+            3,
+        );
+
+        assert_eq!(excluded, [false, true, false]);
+        assert_eq!(lookups, [vec![1, 2, 3], vec![11, 21], vec![211]]);
+    }
+
+    #[test]
+    fn stops_at_the_depth() {
+        let exclusions = Exclusions {
+            own_windows: true,
+            windows: vec![211],
+        };
+
+        assert_eq!(exclude(exclusions.clone(), 1).0, [false, false, false]);
+        assert_eq!(exclude(exclusions, 2).0, [false, true, false]);
+
+        let (excluded, lookups) = exclude(
+            Exclusions {
+                own_windows: false,
+                windows: vec![1],
+            },
+            0,
+        );
+
+        assert_eq!(excluded, [true, false, false]);
+        assert!(lookups.is_empty());
+    }
+}
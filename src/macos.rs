@@ -1,19 +1,25 @@
 #![cfg(target_os = "macos")]
 #![allow(non_upper_case_globals)]
 
-use super::*;
+use super::{
+    convert::{bgrx_to_rgb, bgrx_to_rgb_scaled},
+    *,
+};
 use core_graphics::{
     base::CGError,
     display::{kCGWindowListOptionAll, CGDisplay, CGRect},
+    event::CGEvent,
+    event_source::{CGEventSource, CGEventSourceStateID},
     geometry::{CGPoint, CGSize},
 };
-use std::{error::Error, fmt};
+use std::{error::Error, fmt, time::SystemTime};
 
 #[derive(Debug, Copy, Clone)]
 pub enum MacOSError {
     CoreGraphicsError(CGError),
     CouldntFindDisplay,
     CouldntScreenshot,
+    ExcludingWindowsUnsupported,
 }
 
 impl From<i32> for MacOSError {
@@ -32,13 +38,17 @@ impl Error for MacOSError {}
 
 pub(crate) struct MacOSCapturer {
     displays: Vec<Display>,
+    redactions: Redactions,
 }
 
 impl MacOSCapturer {
     pub(crate) fn new() -> Result<Self, MacOSError> {
         let displays = Self::get_displays()?;
 
-        Ok(Self { displays })
+        Ok(Self {
+            displays,
+            redactions: Redactions::new(),
+        })
     }
 
     fn get_displays() -> Result<Vec<Display>, MacOSError> {
@@ -58,15 +68,38 @@ impl MacOSCapturer {
                 cg_rect.size.height = width;
             }
 
-            displays.push(cg_rect.into());
+            let mut captured: Display = cg_rect.into();
+
+            // The bounds are in points, the mode says how many pixels each of them has.
+            if let Some(mode) = display.display_mode() {
+                if mode.width() > 0 {
+                    captured.scale_factor = mode.pixel_width() as f64 / mode.width() as f64;
+                }
+            }
+
+            displays.push(captured);
         }
 
         Ok(displays)
     }
-}
 
-impl Capturer for MacOSCapturer {
-    fn capture(&self, index: usize) -> Result<RgbImage, MacOSError> {
+    /// Hides the masks in `image`, a capture of the display at `index`.
+    fn redact(&self, image: &mut RgbImage, index: usize) {
+        let bounds = self.displays[index].bounds();
+
+        // Only rectangles can be masked here, so there's nothing to look up.
+        self.redactions
+            .apply(image, bounds, |_| Ok::<_, ()>(None))
+            .ok();
+    }
+
+    /// Takes a screenshot of the display at `index` and hands its BGRX pixels, the length of
+    /// a row in bytes, and its width and height to `decode`.
+    fn capture_with<T>(
+        &self,
+        index: usize,
+        decode: impl FnOnce(&[u8], usize, u32, u32) -> T,
+    ) -> Result<T, MacOSError> {
         use MacOSError::*;
 
         let display = *self.displays.get(index).ok_or(CouldntFindDisplay)?;
@@ -76,28 +109,64 @@ impl Capturer for MacOSCapturer {
 
         let data = cg_image.data();
 
-        let bytes = data.bytes();
-
-        let pixels =
-            unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const Bgr, bytes.len()) };
-
         let (width, height) = (cg_image.width(), cg_image.height());
 
-        let mut image = RgbImage::new(width as u32, height as u32);
+        Ok(decode(
+            data.bytes(),
+            cg_image.bytes_per_row(),
+            width as u32,
+            height as u32,
+        ))
+    }
+}
 
-        let mut i = 0;
+impl Capturer for MacOSCapturer {
+    fn capture(&self, index: usize) -> Result<RgbImage, MacOSError> {
+        let mut image = self.capture_with(index, bgrx_to_rgb)?;
+        self.redact(&mut image, index);
+        Ok(image)
+    }
 
-        for pixel in image.pixels_mut() {
-            let Bgr { r, g, b, .. } = pixels[i];
-            pixel.0 = [r, g, b];
-            i += 1;
-        }
+    fn capture_frame(&self, index: usize) -> Result<Frame, MacOSError> {
+        let started_at = SystemTime::now();
+
+        // An empty event carries where the cursor is, in the same points as the displays.
+        let cursor = CGEventSource::new(CGEventSourceStateID::CombinedSessionState)
+            .and_then(CGEvent::new)
+            .ok()
+            .map(|event| {
+                let location = event.location();
+                (location.x as i32, location.y as i32)
+            });
+
+        let image = self.capture(index)?;
+
+        Ok(Frame {
+            image,
+            index,
+            display: self.displays[index],
+            started_at,
+            finished_at: SystemTime::now(),
+            backend: Backend::CoreGraphics,
+            cursor,
+        })
+    }
 
+    fn capture_scaled(
+        &self,
+        index: usize,
+        scale: Scale,
+        filter: ScaleFilter,
+    ) -> Result<RgbImage, MacOSError> {
+        let mut image = self.capture_with(index, |data, stride, width, height| {
+            bgrx_to_rgb_scaled(data, stride, width, height, scale, filter)
+        })?;
+        self.redact(&mut image, index);
         Ok(image)
     }
 
     fn capture_primary(&self) -> Result<RgbImage, MacOSError> {
-        Ok(self.capture(0)?)
+        Ok(self.capture(self.primary_display())?)
     }
 
     fn capture_all(&self) -> Result<Vec<RgbImage>, MacOSError> {
@@ -108,6 +177,22 @@ impl Capturer for MacOSCapturer {
         Ok(vec)
     }
 
+    fn exclude_windows(&mut self, exclusions: Exclusions) -> Result<(), MacOSError> {
+        if exclusions.is_empty() {
+            Ok(())
+        } else {
+            Err(MacOSError::ExcludingWindowsUnsupported)
+        }
+    }
+
+    fn primary_display(&self) -> usize {
+        0
+    }
+
+    fn redactions(&self) -> &Redactions {
+        &self.redactions
+    }
+
     fn displays(&self) -> &[Display] {
         &self.displays
     }
@@ -125,6 +210,7 @@ impl From<CGRect> for Display {
             top: cg_rect.origin.y,
             width: cg_rect.size.width,
             height: cg_rect.size.height,
+            scale_factor: 1.0,
         }
     }
 }
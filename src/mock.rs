@@ -0,0 +1,189 @@
+use super::{
+    Backend, Capturer, CoordinateSpace, Display, Error, Exclusions, Frame, Layout, Redactions,
+    RgbImage,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::SystemTime,
+};
+
+/// A capturer without a screen, which captures whatever frames it was given, for tests and
+/// for running code that expects a [`Capturer`] where there's no display.
+///
+/// Displays that weren't given a frame capture a gradient that's different for every
+/// display. The masks in [`Capturer::redactions`] are applied like on a real screen, window
+/// exclusions are accepted but there are no windows to leave out.
+///
+/// ```
+/// use captis::{Capturer, Display, Layout, MockCapturer};
+///
+/// let capturer = MockCapturer::new(Layout {
+///     displays: vec![
+///         Display::from_pixels(0, 0, 64, 48),
+///         Display::from_pixels(64, 0, 32, 24),
+///     ],
+///     primary: 0,
+/// });
+///
+/// assert_eq!(capturer.capture(1).unwrap().dimensions(), (32, 24));
+/// assert_eq!(capturer.layout().displays.len(), 2);
+/// ```
+pub struct MockCapturer {
+    layout: Layout,
+    frames: Mutex<Vec<Option<RgbImage>>>,
+    cursor: Mutex<Option<(i32, i32)>>,
+    failing: AtomicBool,
+    redactions: Redactions,
+}
+
+impl MockCapturer {
+    pub fn new(layout: Layout) -> Self {
+        Self {
+            frames: Mutex::new(vec![None; layout.displays.len()]),
+            cursor: Mutex::new(None),
+            failing: AtomicBool::new(false),
+            layout,
+            redactions: Redactions::new(),
+        }
+    }
+
+    /// Makes the display at `index` capture `frame` from now on, which is handed out as it
+    /// is even if its size doesn't match the display's.
+    pub fn set_frame(&self, index: usize, frame: RgbImage) {
+        if let Some(slot) = self.frames.lock().unwrap().get_mut(index) {
+            *slot = Some(frame);
+        }
+    }
+
+    /// Goes back to capturing the gradient on the display at `index`.
+    pub fn clear_frame(&self, index: usize) {
+        if let Some(slot) = self.frames.lock().unwrap().get_mut(index) {
+            *slot = None;
+        }
+    }
+
+    /// Puts the cursor [`Capturer::capture_frame`] reports at `cursor`, or nowhere.
+    pub fn set_cursor(&self, cursor: Option<(i32, i32)>) {
+        *self.cursor.lock().unwrap() = cursor;
+    }
+
+    /// Makes every capture fail like the displays were gone while `failing` is set.
+    pub fn set_failing(&self, failing: bool) {
+        self.failing.store(failing, Ordering::SeqCst);
+    }
+}
+
+impl Capturer for MockCapturer {
+    fn capture(&self, index: usize) -> Result<RgbImage, Error> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(display_not_found());
+        }
+
+        let display = self
+            .layout
+            .displays
+            .get(index)
+            .ok_or_else(display_not_found)?;
+
+        let mut image = match &self.frames.lock().unwrap()[index] {
+            Some(frame) => frame.clone(),
+            None => gradient(display, index),
+        };
+
+        self.redactions
+            .apply(&mut image, display.bounds(), |_| Ok::<_, Error>(None))?;
+
+        Ok(image)
+    }
+
+    fn capture_frame(&self, index: usize) -> Result<Frame, Error> {
+        let started_at = SystemTime::now();
+        let cursor = *self.cursor.lock().unwrap();
+        let image = self.capture(index)?;
+
+        Ok(Frame {
+            image,
+            index,
+            display: self.layout.displays[index],
+            started_at,
+            finished_at: SystemTime::now(),
+            backend: Backend::Mock,
+            cursor,
+        })
+    }
+
+    fn capture_primary(&self) -> Result<RgbImage, Error> {
+        self.capture(self.layout.primary)
+    }
+
+    fn capture_all(&self) -> Result<Vec<RgbImage>, Error> {
+        (0..self.layout.displays.len())
+            .map(|index| self.capture(index))
+            .collect()
+    }
+
+    fn exclude_windows(&mut self, _exclusions: Exclusions) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn redactions(&self) -> &Redactions {
+        &self.redactions
+    }
+
+    fn displays(&self) -> &[Display] {
+        &self.layout.displays
+    }
+
+    fn primary_display(&self) -> usize {
+        self.layout.primary
+    }
+
+    fn layout(&self) -> Layout {
+        self.layout.clone()
+    }
+
+    fn refresh_displays(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Frames are meant to be the size of their display, so the displays are measured in
+    /// physical pixels on every platform.
+    fn desktop_space(&self) -> CoordinateSpace {
+        CoordinateSpace::Physical
+    }
+}
+
+/// A horizontal and vertical ramp, tinted by `index` so every display looks different.
+fn gradient(display: &Display, index: usize) -> RgbImage {
+    let bounds = display.bounds();
+    let (width, height) = (bounds.width.max(1), bounds.height.max(1));
+
+    RgbImage::from_fn(bounds.width, bounds.height, |x, y| {
+        image::Rgb([
+            (x * 255 / width) as u8,
+            (y * 255 / height) as u8,
+            (index * 64) as u8,
+        ])
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn display_not_found() -> Error {
+    Error::IoError(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "Couldn't find specified Display",
+    ))
+}
+
+#[cfg(target_os = "windows")]
+fn display_not_found() -> Error {
+    Error::CouldntFindDisplay
+}
+
+#[cfg(target_os = "macos")]
+fn display_not_found() -> Error {
+    Error::CouldntFindDisplay
+}
@@ -0,0 +1,282 @@
+use super::{
+    broadcast::{Listeners, Mailbox, ServerError, Subscription},
+    Capturer, RgbImage,
+};
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder},
+    ColorType, ImageEncoder,
+};
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+const BOUNDARY: &str = "captis-frame";
+
+const INDEX: &str = "<!DOCTYPE html>\
+<html><head><title>captis</title></head>\
+<body style=\"margin:0;background:#000\">\
+<img src=\"/stream.mjpg\" style=\"width:100%;height:100vh;object-fit:contain\">\
+</body></html>";
+
+/// An HTTP server which lets browsers watch the displays of a [`Capturer`].
+///
+/// Every display is served on its own address with the following endpoints:
+///
+/// - `/` a page showing the live stream.
+/// - `/stream.mjpg` a `multipart/x-mixed-replace` MJPEG stream.
+/// - `/snapshot.jpg` and `/snapshot.png` a single frame, the first one captured after the
+///   request arrived.
+///
+/// All the clients of a display share the same capture loop, which only runs while someone is
+/// watching, and every frame is encoded once no matter how many clients there are.
+///
+/// ```no_run
+/// use captis::{init_capturer, PreviewServer};
+///
+/// let capturer = init_capturer().expect("Couldn't Initialize Capturer");
+///
+/// PreviewServer::new()
+///     .frame_rate(5)
+///     .bind(0, "0.0.0.0:8080")
+///     .expect("Couldn't Bind Server")
+///     .run(&capturer)
+///     .expect("Server Failed");
+/// ```
+pub struct PreviewServer {
+    frame_rate: u32,
+    quality: u8,
+    listeners: Listeners,
+}
+
+impl Default for PreviewServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PreviewServer {
+    pub fn new() -> Self {
+        Self {
+            frame_rate: 10,
+            quality: 75,
+            listeners: Listeners::new(),
+        }
+    }
+
+    /// Sets the maximum amount of frames per second sent to the clients.
+    pub fn frame_rate(mut self, frame_rate: u32) -> Self {
+        self.frame_rate = frame_rate.max(1);
+        self
+    }
+
+    /// Sets the JPEG quality of the stream, from 1 to 100.
+    pub fn quality(mut self, quality: u8) -> Self {
+        self.quality = quality.clamp(1, 100);
+        self
+    }
+
+    /// Sets how many clients are served at once over all displays, 32 by default. Anyone
+    /// connecting beyond that is disconnected right away.
+    pub fn max_clients(mut self, max_clients: usize) -> Self {
+        self.listeners.max_clients(max_clients);
+        self
+    }
+
+    /// Serves the display at `index` on `addr`, can be called once for every display.
+    pub fn bind<A: ToSocketAddrs>(mut self, index: usize, addr: A) -> Result<Self, ServerError> {
+        self.listeners.bind(index, addr)?;
+        Ok(self)
+    }
+
+    /// Returns the addresses the server listens on, in the order they were bound.
+    pub fn local_addrs(&self) -> Result<Vec<SocketAddr>, ServerError> {
+        Ok(self.listeners.local_addrs()?)
+    }
+
+    /// Starts accepting clients and keeps capturing on the current thread, only returns if
+    /// capturing fails. The clients are disconnected then.
+    pub fn run<C: Capturer>(self, capturer: &C) -> Result<(), ServerError> {
+        let interval = Duration::from_secs(1) / self.frame_rate;
+        let quality = self.quality;
+
+        self.listeners.run(
+            capturer,
+            interval,
+            |image| Preview::new(image, quality),
+            |stream, mailbox| handle_client(stream, &mailbox),
+        )
+    }
+}
+
+/// A captured frame whose JPEG encoding is done lazily by the first client that needs it.
+struct Preview {
+    image: RgbImage,
+    quality: u8,
+    jpeg: Mutex<Option<Arc<Vec<u8>>>>,
+}
+
+impl Preview {
+    fn new(image: RgbImage, quality: u8) -> Self {
+        Self {
+            image,
+            quality,
+            jpeg: Mutex::new(None),
+        }
+    }
+
+    fn jpeg(&self) -> io::Result<Arc<Vec<u8>>> {
+        let mut jpeg = self.jpeg.lock().unwrap();
+
+        if let Some(jpeg) = &*jpeg {
+            return Ok(jpeg.clone());
+        }
+
+        let mut buffer = vec![];
+
+        JpegEncoder::new_with_quality(&mut buffer, self.quality)
+            .encode(
+                &self.image,
+                self.image.width(),
+                self.image.height(),
+                ColorType::Rgb8,
+            )
+            .map_err(|error| io::Error::new(ErrorKind::Other, error))?;
+
+        let buffer = Arc::new(buffer);
+        *jpeg = Some(buffer.clone());
+
+        Ok(buffer)
+    }
+
+    fn png(&self) -> io::Result<Vec<u8>> {
+        let mut buffer = vec![];
+
+        PngEncoder::new(&mut buffer)
+            .write_image(
+                &self.image,
+                self.image.width(),
+                self.image.height(),
+                ColorType::Rgb8,
+            )
+            .map_err(|error| io::Error::new(ErrorKind::Other, error))?;
+
+        Ok(buffer)
+    }
+}
+
+fn handle_client(mut stream: TcpStream, mailbox: &Arc<Mailbox<Preview>>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+
+    let mut head = Vec::with_capacity(1024);
+    let mut buffer = [0; 1024];
+
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        if head.len() > 16 * 1024 {
+            return respond(
+                &mut stream,
+                "431 Request Header Fields Too Large",
+                "text/plain",
+                b"",
+            );
+        }
+
+        match stream.read(&mut buffer)? {
+            0 => return Ok(()),
+            read => head.extend_from_slice(&buffer[..read]),
+        }
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+
+    let (method, target) = (
+        request_line.next().unwrap_or_default(),
+        request_line.next().unwrap_or_default(),
+    );
+
+    if method != "GET" {
+        return respond(&mut stream, "405 Method Not Allowed", "text/plain", b"");
+    }
+
+    match target.split('?').next().unwrap_or_default() {
+        "/" => respond(&mut stream, "200 OK", "text/html", INDEX.as_bytes()),
+        "/stream.mjpg" => stream_frames(&mut stream, &mailbox.subscribe()),
+        "/snapshot.jpg" => {
+            let preview = fresh_preview(&mailbox.subscribe())?;
+            respond(&mut stream, "200 OK", "image/jpeg", &preview.jpeg()?)
+        }
+        "/snapshot.png" => {
+            let preview = fresh_preview(&mailbox.subscribe())?;
+            respond(&mut stream, "200 OK", "image/png", &preview.png()?)
+        }
+        _ => respond(&mut stream, "404 Not Found", "text/plain", b""),
+    }
+}
+
+fn stream_frames(stream: &mut TcpStream, mailbox: &Subscription<Preview>) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\n\
+        Content-Type: multipart/x-mixed-replace; boundary={}\r\n\
+        Cache-Control: no-cache, no-store\r\n\
+        Connection: close\r\n\r\n",
+        BOUNDARY
+    )?;
+
+    let mut sequence = 0;
+
+    loop {
+        let (latest, preview) = next_preview(mailbox, sequence)?;
+
+        sequence = latest;
+
+        let jpeg = preview.jpeg()?;
+
+        write!(
+            stream,
+            "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+            BOUNDARY,
+            jpeg.len()
+        )?;
+        stream.write_all(&jpeg)?;
+        stream.write_all(b"\r\n")?;
+    }
+}
+
+/// Blocks until a frame newer than `sequence` was captured, or fails if the server stopped
+/// capturing.
+fn next_preview(mailbox: &Subscription<Preview>, sequence: u64) -> io::Result<(u64, Arc<Preview>)> {
+    loop {
+        if let Some(latest) = mailbox.wait_newer(sequence, Duration::from_secs(1))? {
+            return Ok(latest);
+        }
+    }
+}
+
+/// Blocks until a frame is captured, instead of handing out the one the streams are showing.
+fn fresh_preview(mailbox: &Subscription<Preview>) -> io::Result<Arc<Preview>> {
+    Ok(next_preview(mailbox, mailbox.sequence())?.1)
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\n\
+        Content-Type: {}\r\n\
+        Content-Length: {}\r\n\
+        Cache-Control: no-cache, no-store\r\n\
+        Connection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)
+}
@@ -0,0 +1,1087 @@
+use super::{Error as CaptureError, RgbImage};
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+#[derive(Debug)]
+pub enum RecordError {
+    Io(io::Error),
+    Capture(CaptureError),
+    Image(image::ImageError),
+    FrameSizeChanged,
+    EncoderFailed,
+    /// The format needs to seek back into the file, so it can't be streamed.
+    NotStreamable,
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for RecordError {}
+
+impl From<io::Error> for RecordError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<CaptureError> for RecordError {
+    fn from(error: CaptureError) -> Self {
+        Self::Capture(error)
+    }
+}
+
+impl From<image::ImageError> for RecordError {
+    fn from(error: image::ImageError) -> Self {
+        Self::Image(error)
+    }
+}
+
+/// The container a recording is written in.
+///
+/// Y4M, AVI and MP4 have a constant frame rate, frames are repeated or dropped to match their
+/// timestamps. Animated PNG and GIF keep every frame for as long as it was on screen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VideoFormat {
+    /// Uncompressed YUV 4:4:4 in a YUV4MPEG2 stream.
+    Y4m,
+    /// Uncompressed 24-bit RGB in an AVI file, which can't grow past 4 GiB.
+    Avi,
+    /// Animated PNG, only the changed part of every frame is stored.
+    #[cfg(feature = "apng")]
+    Apng,
+    /// Animated GIF, meant for short clips since every frame is quantized to 256 colors.
+    #[cfg(feature = "gif")]
+    Gif,
+    /// H.264 (or whatever the default encoder is) MP4, encoded by the `ffmpeg` executable.
+    #[cfg(feature = "mp4")]
+    Mp4,
+}
+
+impl VideoFormat {
+    /// Guesses the format from the extension of `path`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+
+        match extension.as_str() {
+            "y4m" => Some(Self::Y4m),
+            "avi" => Some(Self::Avi),
+            #[cfg(feature = "apng")]
+            "png" | "apng" => Some(Self::Apng),
+            #[cfg(feature = "gif")]
+            "gif" => Some(Self::Gif),
+            #[cfg(feature = "mp4")]
+            "mp4" => Some(Self::Mp4),
+            _ => None,
+        }
+    }
+}
+
+/// Lets other threads pause, resume or stop a [`Recording`].
+#[derive(Debug, Clone, Default)]
+pub struct RecordControls {
+    paused: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl RecordControls {
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+}
+
+/// Writes captured frames into a video file.
+///
+/// ```no_run
+/// use captis::{init_capturer, FrameStream, Recorder, VideoFormat};
+/// use std::time::Duration;
+///
+/// let capturer = init_capturer().expect("Couldn't Initialize Capturer");
+///
+/// Recorder::new(VideoFormat::Avi)
+///     .frame_rate(30)
+///     .max_duration(Duration::from_secs(10))
+///     .create("recording.avi")
+///     .expect("Couldn't Create Recording")
+///     .record(FrameStream::new(&capturer, 0, 30))
+///     .expect("Couldn't Record");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    format: VideoFormat,
+    frame_rate: u32,
+    max_duration: Option<Duration>,
+    max_size: Option<u64>,
+}
+
+impl Recorder {
+    pub fn new(format: VideoFormat) -> Self {
+        Self {
+            format,
+            frame_rate: 30,
+            max_duration: None,
+            max_size: None,
+        }
+    }
+
+    /// Sets the frame rate of the formats that have a constant one.
+    pub fn frame_rate(mut self, frame_rate: u32) -> Self {
+        self.frame_rate = frame_rate.max(1);
+        self
+    }
+
+    /// Stops the recording once it's this long, not counting the time it was paused.
+    pub fn max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Stops the recording once the file is about this many bytes. For MP4 it's the bytes
+    /// piped into `ffmpeg`, which are a lot more than end up in the file.
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Creates the file at `path` and starts a recording into it.
+    pub fn create<P: AsRef<Path>>(&self, path: P) -> Result<Recording, RecordError> {
+        let path = path.as_ref();
+        let written = Arc::new(AtomicU64::new(0));
+
+        let writer: Box<dyn VideoWriter> = match self.format {
+            VideoFormat::Y4m => Box::new(Y4mWriter::new(
+                Counter::new(BufWriter::new(File::create(path)?), written.clone()),
+                self.frame_rate,
+            )),
+            VideoFormat::Avi => Box::new(AviWriter::new(
+                Counter::new(BufWriter::new(File::create(path)?), written.clone()),
+                self.frame_rate,
+            )),
+            #[cfg(feature = "apng")]
+            VideoFormat::Apng => Box::new(ApngWriter::new(Counter::new(
+                BufWriter::new(File::create(path)?),
+                written.clone(),
+            ))),
+            #[cfg(feature = "gif")]
+            VideoFormat::Gif => Box::new(GifWriter::new(Counter::new(
+                BufWriter::new(File::create(path)?),
+                written.clone(),
+            ))?),
+            #[cfg(feature = "mp4")]
+            VideoFormat::Mp4 => Box::new(Mp4Writer::new(path, self.frame_rate, written.clone())?),
+        };
+
+        Ok(self.start(writer, written))
+    }
+
+    /// Starts a recording into `out`, like a pipe or a socket, for the formats that are
+    /// written front to back: Y4M and GIF.
+    pub fn create_stream<W: Write + 'static>(&self, out: W) -> Result<Recording, RecordError> {
+        let written = Arc::new(AtomicU64::new(0));
+
+        let writer: Box<dyn VideoWriter> = match self.format {
+            VideoFormat::Y4m => Box::new(Y4mWriter::new(
+                Counter::new(BufWriter::new(out), written.clone()),
+                self.frame_rate,
+            )),
+            #[cfg(feature = "gif")]
+            VideoFormat::Gif => Box::new(GifWriter::new(Counter::new(
+                BufWriter::new(out),
+                written.clone(),
+            ))?),
+            _ => return Err(RecordError::NotStreamable),
+        };
+
+        Ok(self.start(writer, written))
+    }
+
+    fn start(&self, writer: Box<dyn VideoWriter>, written: Arc<AtomicU64>) -> Recording {
+        Recording {
+            writer,
+            written,
+            controls: RecordControls::default(),
+            max_duration: self.max_duration,
+            max_size: self.max_size,
+            interval: Duration::from_secs(1) / self.frame_rate,
+            dimensions: None,
+            paused_for: Duration::ZERO,
+            was_paused: false,
+            last: None,
+            end: Duration::ZERO,
+        }
+    }
+}
+
+/// A recording in progress, created by [`Recorder::create`].
+pub struct Recording {
+    writer: Box<dyn VideoWriter>,
+    written: Arc<AtomicU64>,
+    controls: RecordControls,
+    max_duration: Option<Duration>,
+    max_size: Option<u64>,
+    interval: Duration,
+    dimensions: Option<(u32, u32)>,
+    /// How long the recording was paused in total, which is cut out of the timeline.
+    paused_for: Duration,
+    was_paused: bool,
+    /// The timestamp of the last frame written, as it was given.
+    last: Option<Duration>,
+    /// The timestamp of the last frame written, in the timeline of the file.
+    end: Duration,
+}
+
+impl Recording {
+    /// Returns the controls of this recording, which can be sent to other threads.
+    pub fn controls(&self) -> RecordControls {
+        self.controls.clone()
+    }
+
+    pub fn pause(&self) {
+        self.controls.pause();
+    }
+
+    pub fn resume(&self) {
+        self.controls.resume();
+    }
+
+    /// Writes a frame captured at `timestamp`, which can be measured from any point as long as
+    /// it keeps increasing. Frames given while paused are ignored.
+    ///
+    /// Returns `false` once the recording was stopped or reached one of its limits, after
+    /// which it should be finished.
+    pub fn write_frame(
+        &mut self,
+        frame: &RgbImage,
+        timestamp: Duration,
+    ) -> Result<bool, RecordError> {
+        if self.controls.is_stopped() {
+            return Ok(false);
+        }
+
+        if self.controls.is_paused() {
+            self.was_paused = true;
+            return Ok(true);
+        }
+
+        if let Some(last) = self.last {
+            // Continue right after the last frame written before the pause.
+            if self.was_paused {
+                self.paused_for += timestamp.saturating_sub(last + self.interval);
+            }
+        } else {
+            // The first frame is the start of the recording.
+            self.paused_for = timestamp;
+        }
+
+        self.was_paused = false;
+
+        let position = timestamp.saturating_sub(self.paused_for);
+
+        let limit_reached = self
+            .max_duration
+            .map_or(false, |max_duration| position >= max_duration)
+            || self.max_size.map_or(false, |max_size| {
+                self.written.load(Ordering::Relaxed) >= max_size
+            });
+
+        if limit_reached {
+            self.controls.stop();
+            return Ok(false);
+        }
+
+        match self.dimensions {
+            Some(dimensions) if dimensions != frame.dimensions() => {
+                return Err(RecordError::FrameSizeChanged)
+            }
+            _ => self.dimensions = Some(frame.dimensions()),
+        }
+
+        self.writer.write_frame(frame, position)?;
+        self.last = Some(timestamp);
+        self.end = position;
+
+        if self.writer.is_full() {
+            self.controls.stop();
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    /// Writes every frame of `frames` until the recording stops, then finishes it.
+    pub fn record<I>(mut self, frames: I) -> Result<(), RecordError>
+    where
+        I: IntoIterator<Item = Result<(Duration, RgbImage), CaptureError>>,
+    {
+        for frame in frames {
+            let (timestamp, image) = frame?;
+
+            if !self.write_frame(&image, timestamp)? {
+                break;
+            }
+        }
+
+        self.finish()
+    }
+
+    /// Writes everything that's left and closes the file.
+    pub fn finish(self) -> Result<(), RecordError> {
+        self.writer.finish(self.end + self.interval)
+    }
+}
+
+trait VideoWriter {
+    /// `timestamp` is when the frame should be shown, relative to the first frame.
+    fn write_frame(&mut self, frame: &RgbImage, timestamp: Duration) -> Result<(), RecordError>;
+    /// `end` is when the last frame should stop being shown.
+    fn finish(self: Box<Self>, end: Duration) -> Result<(), RecordError>;
+    /// Whether the format can't hold any more frames, the ones written so far are still
+    /// finished normally.
+    fn is_full(&self) -> bool {
+        false
+    }
+}
+
+/// Keeps count of the bytes written in a way the [`Recording`] can read it, even after the
+/// writer was moved into an encoder.
+struct Counter<W> {
+    inner: W,
+    written: Arc<AtomicU64>,
+}
+
+impl<W> Counter<W> {
+    fn new(inner: W, written: Arc<AtomicU64>) -> Self {
+        Self { inner, written }
+    }
+}
+
+impl<W: Write> Write for Counter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written.fetch_add(written as u64, Ordering::Relaxed);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Seek> Seek for Counter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// Returns how many times the previous frame has to be repeated before a frame shown at
+/// `timestamp`, or `None` if it should be dropped since its slot is already taken.
+fn constant_rate_slot(timestamp: Duration, interval: Duration, frames: u64) -> Option<u64> {
+    let slot = ((timestamp.as_secs_f64() / interval.as_secs_f64()) + 0.5) as u64;
+
+    if frames > 0 && slot < frames {
+        None
+    } else {
+        Some(slot.saturating_sub(frames))
+    }
+}
+
+struct Y4mWriter<W: Write> {
+    out: W,
+    frame_rate: u32,
+    interval: Duration,
+    frames: u64,
+    previous: Vec<u8>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    fn new(out: W, frame_rate: u32) -> Self {
+        Self {
+            out,
+            frame_rate,
+            interval: Duration::from_secs(1) / frame_rate,
+            frames: 0,
+            previous: vec![],
+        }
+    }
+
+    fn write_previous(&mut self) -> io::Result<()> {
+        self.out.write_all(b"FRAME\n")?;
+        self.out.write_all(&self.previous)?;
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+impl<W: Write> VideoWriter for Y4mWriter<W> {
+    fn write_frame(&mut self, frame: &RgbImage, timestamp: Duration) -> Result<(), RecordError> {
+        let repeats = match constant_rate_slot(timestamp, self.interval, self.frames) {
+            Some(repeats) => repeats,
+            None => return Ok(()),
+        };
+
+        if self.frames == 0 {
+            writeln!(
+                self.out,
+                "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+                frame.width(),
+                frame.height(),
+                self.frame_rate
+            )?;
+        } else {
+            for _ in 0..repeats {
+                self.write_previous()?;
+            }
+        }
+
+        // Planar BT.601 limited range, which is what everything reading Y4M expects.
+        let pixels = frame.as_raw().chunks_exact(3);
+        let plane = pixels.len();
+
+        self.previous.clear();
+        self.previous.resize(plane * 3, 0);
+
+        let (y, chroma) = self.previous.split_at_mut(plane);
+        let (u, v) = chroma.split_at_mut(plane);
+
+        for (i, pixel) in pixels.enumerate() {
+            let (r, g, b) = (pixel[0] as i32, pixel[1] as i32, pixel[2] as i32);
+            y[i] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+            u[i] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+            v[i] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+        }
+
+        self.write_previous()?;
+
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>, _end: Duration) -> Result<(), RecordError> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+const AVI_HEADER_SIZE: usize = 224;
+const AVI_MOVI_OFFSET: u64 = 220;
+
+struct AviWriter<W: Write + Seek> {
+    out: W,
+    frame_rate: u32,
+    interval: Duration,
+    width: u32,
+    height: u32,
+    /// Offset and size of every chunk in the movi list.
+    index: Vec<(u32, u32)>,
+    position: u64,
+    previous: Vec<u8>,
+    /// Set once another frame would take the file past the 4 GiB that RIFF sizes can count.
+    full: bool,
+}
+
+impl<W: Write + Seek> AviWriter<W> {
+    fn new(out: W, frame_rate: u32) -> Self {
+        Self {
+            out,
+            frame_rate,
+            interval: Duration::from_secs(1) / frame_rate,
+            width: 0,
+            height: 0,
+            index: vec![],
+            position: AVI_HEADER_SIZE as u64,
+            previous: vec![],
+            full: false,
+        }
+    }
+
+    /// Writes the headers, the fields that depend on the amount of frames are filled in when
+    /// the recording finishes.
+    fn write_header(&mut self) -> io::Result<()> {
+        let (width, height) = (self.width, self.height);
+        let frame_size = self.previous.len() as u32;
+
+        let mut header = Vec::with_capacity(AVI_HEADER_SIZE);
+
+        fn u32_le(header: &mut Vec<u8>, value: u32) {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+
+        header.extend_from_slice(b"RIFF");
+        u32_le(&mut header, 0);
+        header.extend_from_slice(b"AVI LIST");
+        u32_le(&mut header, 192);
+        header.extend_from_slice(b"hdrlavih");
+        u32_le(&mut header, 56);
+        u32_le(&mut header, 1_000_000 / self.frame_rate);
+        u32_le(&mut header, frame_size.saturating_mul(self.frame_rate));
+        u32_le(&mut header, 0);
+        // AVIF_HASINDEX
+        u32_le(&mut header, 0x10);
+        u32_le(&mut header, 0);
+        u32_le(&mut header, 0);
+        u32_le(&mut header, 1);
+        u32_le(&mut header, frame_size);
+        u32_le(&mut header, width);
+        u32_le(&mut header, height);
+        header.extend_from_slice(&[0; 16]);
+
+        header.extend_from_slice(b"LIST");
+        u32_le(&mut header, 116);
+        header.extend_from_slice(b"strlstrh");
+        u32_le(&mut header, 56);
+        header.extend_from_slice(b"vidsDIB ");
+        u32_le(&mut header, 0);
+        u32_le(&mut header, 0);
+        u32_le(&mut header, 0);
+        u32_le(&mut header, 1);
+        u32_le(&mut header, self.frame_rate);
+        u32_le(&mut header, 0);
+        u32_le(&mut header, 0);
+        u32_le(&mut header, frame_size);
+        u32_le(&mut header, u32::MAX);
+        u32_le(&mut header, 0);
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&(width as u16).to_le_bytes());
+        header.extend_from_slice(&(height as u16).to_le_bytes());
+
+        header.extend_from_slice(b"strf");
+        u32_le(&mut header, 40);
+        u32_le(&mut header, 40);
+        u32_le(&mut header, width);
+        u32_le(&mut header, height);
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&24u16.to_le_bytes());
+        // BI_RGB
+        u32_le(&mut header, 0);
+        u32_le(&mut header, frame_size);
+        header.extend_from_slice(&[0; 16]);
+
+        header.extend_from_slice(b"LIST");
+        u32_le(&mut header, 0);
+        header.extend_from_slice(b"movi");
+
+        self.out.write_all(&header)
+    }
+
+    fn write_previous(&mut self) -> Result<(), RecordError> {
+        let size = self.previous.len() as u64;
+
+        if self.full
+            || self.position + size + 8 + (self.index.len() as u64 + 1) * 16 > u32::MAX as u64
+        {
+            self.full = true;
+            return Ok(());
+        }
+
+        self.index
+            .push(((self.position - AVI_MOVI_OFFSET) as u32, size as u32));
+
+        self.out.write_all(b"00db")?;
+        self.out.write_all(&(size as u32).to_le_bytes())?;
+        self.out.write_all(&self.previous)?;
+        self.position += size + 8;
+
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> VideoWriter for AviWriter<W> {
+    fn write_frame(&mut self, frame: &RgbImage, timestamp: Duration) -> Result<(), RecordError> {
+        let frames = self.index.len() as u64;
+
+        let repeats = match constant_rate_slot(timestamp, self.interval, frames) {
+            Some(repeats) => repeats,
+            None => return Ok(()),
+        };
+
+        if frames > 0 {
+            for _ in 0..repeats {
+                self.write_previous()?;
+            }
+        }
+
+        // Bottom-up BGR rows padded to four bytes, as DIBs are.
+        let (width, height) = frame.dimensions();
+        let row = width as usize * 3;
+        let stride = (row + 3) & !3;
+
+        self.previous.clear();
+        self.previous.resize(stride * height as usize, 0);
+
+        for (source, target) in frame
+            .as_raw()
+            .chunks_exact(row)
+            .zip(self.previous.chunks_exact_mut(stride).rev())
+        {
+            for (rgb, bgr) in source.chunks_exact(3).zip(target.chunks_exact_mut(3)) {
+                bgr.copy_from_slice(&[rgb[2], rgb[1], rgb[0]]);
+            }
+        }
+
+        if frames == 0 {
+            self.width = width;
+            self.height = height;
+            self.write_header()?;
+        }
+
+        self.write_previous()
+    }
+
+    fn finish(mut self: Box<Self>, _end: Duration) -> Result<(), RecordError> {
+        if self.index.is_empty() {
+            self.out.flush()?;
+            return Ok(());
+        }
+
+        let movi_size = (self.position - AVI_MOVI_OFFSET) as u32;
+
+        let mut index = Vec::with_capacity(8 + self.index.len() * 16);
+        index.extend_from_slice(b"idx1");
+        index.extend_from_slice(&(self.index.len() as u32 * 16).to_le_bytes());
+
+        for (offset, size) in &self.index {
+            index.extend_from_slice(b"00db");
+            // AVIIF_KEYFRAME
+            index.extend_from_slice(&0x10u32.to_le_bytes());
+            index.extend_from_slice(&offset.to_le_bytes());
+            index.extend_from_slice(&size.to_le_bytes());
+        }
+
+        self.out.write_all(&index)?;
+
+        let riff_size = (self.position + index.len() as u64 - 8) as u32;
+        let frames = self.index.len() as u32;
+
+        for (offset, value) in [
+            (4, riff_size),
+            (48, frames),
+            (140, frames),
+            (216, movi_size),
+        ] {
+            self.out.seek(SeekFrom::Start(offset))?;
+            self.out.write_all(&value.to_le_bytes())?;
+        }
+
+        self.out.flush()?;
+
+        Ok(())
+    }
+
+    fn is_full(&self) -> bool {
+        self.full
+    }
+}
+
+#[cfg(any(feature = "apng", feature = "gif"))]
+fn frame_delay(from: Duration, to: Duration) -> Duration {
+    to.saturating_sub(from).max(Duration::from_millis(10))
+}
+
+#[cfg(feature = "apng")]
+const APNG_ACTL_OFFSET: u64 = 33;
+
+/// The animated formats hold on to the latest frame until the next different one arrives,
+/// which is when it's known for how long it was on screen.
+#[cfg(feature = "apng")]
+struct ApngWriter<W: Write + Seek> {
+    out: W,
+    previous: Option<RgbImage>,
+    /// The part of the latest frame that changed since the one before, and when it was shown.
+    pending: Option<(super::Rect, Duration)>,
+    frames: u32,
+    sequence: u32,
+}
+
+#[cfg(feature = "apng")]
+impl<W: Write + Seek> ApngWriter<W> {
+    fn new(out: W) -> Self {
+        Self {
+            out,
+            previous: None,
+            pending: None,
+            frames: 0,
+            sequence: 0,
+        }
+    }
+
+    fn write_chunk(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+        let mut crc = flate2::Crc::new();
+        crc.update(kind);
+        crc.update(data);
+
+        out.write_all(&(data.len() as u32).to_be_bytes())?;
+        out.write_all(kind)?;
+        out.write_all(data)?;
+        out.write_all(&crc.sum().to_be_bytes())
+    }
+
+    fn animation_control(frames: u32) -> [u8; 8] {
+        let mut data = [0; 8];
+        data[..4].copy_from_slice(&frames.to_be_bytes());
+        data
+    }
+
+    /// Writes the pending frame, which is shown for `delay`.
+    fn flush_pending(&mut self, delay: Duration) -> io::Result<()> {
+        let (region, frame) = match (self.pending.take(), &self.previous) {
+            (Some((region, _)), Some(frame)) => (region, frame),
+            _ => return Ok(()),
+        };
+
+        if self.frames == 0 {
+            let mut header = Vec::with_capacity(13);
+            header.extend_from_slice(&frame.width().to_be_bytes());
+            header.extend_from_slice(&frame.height().to_be_bytes());
+            // 8-bit RGB, no interlacing.
+            header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+            self.out.write_all(b"\x89PNG\r\n\x1a\n")?;
+            Self::write_chunk(&mut self.out, b"IHDR", &header)?;
+            Self::write_chunk(&mut self.out, b"acTL", &Self::animation_control(0))?;
+        }
+
+        let delay = delay.as_millis().min(u16::MAX as u128) as u16;
+
+        let mut control = Vec::with_capacity(26);
+        control.extend_from_slice(&self.sequence.to_be_bytes());
+        control.extend_from_slice(&region.width.to_be_bytes());
+        control.extend_from_slice(&region.height.to_be_bytes());
+        control.extend_from_slice(&(region.x as u32).to_be_bytes());
+        control.extend_from_slice(&(region.y as u32).to_be_bytes());
+        control.extend_from_slice(&delay.to_be_bytes());
+        control.extend_from_slice(&1000u16.to_be_bytes());
+        // APNG_DISPOSE_OP_NONE, APNG_BLEND_OP_SOURCE
+        control.extend_from_slice(&[0, 0]);
+
+        self.sequence += 1;
+
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+
+        let stride = frame.width() as usize * 3;
+        let (start, end) = (
+            region.x as usize * 3,
+            (region.x as usize + region.width as usize) * 3,
+        );
+
+        for y in region.y as usize..region.y as usize + region.height as usize {
+            // Filter type None.
+            encoder.write_all(&[0])?;
+            encoder.write_all(&frame.as_raw()[y * stride + start..y * stride + end])?;
+        }
+
+        let data = encoder.finish()?;
+
+        Self::write_chunk(&mut self.out, b"fcTL", &control)?;
+
+        if self.frames == 0 {
+            Self::write_chunk(&mut self.out, b"IDAT", &data)?;
+        } else {
+            // Frame data chunks start with their sequence number, outside of the zlib stream.
+            let mut frame_data = Vec::with_capacity(4 + data.len());
+            frame_data.extend_from_slice(&self.sequence.to_be_bytes());
+            frame_data.extend_from_slice(&data);
+
+            self.sequence += 1;
+            Self::write_chunk(&mut self.out, b"fdAT", &frame_data)?;
+        }
+
+        self.frames += 1;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "apng")]
+impl<W: Write + Seek> VideoWriter for ApngWriter<W> {
+    fn write_frame(&mut self, frame: &RgbImage, timestamp: Duration) -> Result<(), RecordError> {
+        let region = match &self.previous {
+            Some(previous) => {
+                let regions = super::changed_regions(previous, frame, super::TILE_SIZE);
+
+                match bounding_rect(&regions) {
+                    Some(region) => region,
+                    // Nothing changed, the pending frame just stays on screen for longer.
+                    None => return Ok(()),
+                }
+            }
+            None => super::Rect::new(0, 0, frame.width(), frame.height()),
+        };
+
+        if let Some((_, shown)) = self.pending {
+            self.flush_pending(frame_delay(shown, timestamp))?;
+        }
+
+        self.previous = Some(frame.clone());
+        self.pending = Some((region, timestamp));
+
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>, end: Duration) -> Result<(), RecordError> {
+        if let Some((_, shown)) = self.pending {
+            self.flush_pending(frame_delay(shown, end))?;
+        }
+
+        if self.frames > 0 {
+            Self::write_chunk(&mut self.out, b"IEND", &[])?;
+
+            // Now that the amount of frames is known, rewrite the acTL chunk with it.
+            self.out.seek(SeekFrom::Start(APNG_ACTL_OFFSET))?;
+            Self::write_chunk(
+                &mut self.out,
+                b"acTL",
+                &Self::animation_control(self.frames),
+            )?;
+        }
+
+        self.out.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "apng")]
+fn bounding_rect(regions: &[super::Rect]) -> Option<super::Rect> {
+    let first = regions.first()?;
+
+    let (mut left, mut top) = (first.x, first.y);
+    let (mut right, mut bottom) = (first.x + first.width as i32, first.y + first.height as i32);
+
+    for region in regions {
+        left = left.min(region.x);
+        top = top.min(region.y);
+        right = right.max(region.x + region.width as i32);
+        bottom = bottom.max(region.y + region.height as i32);
+    }
+
+    Some(super::Rect::new(
+        left,
+        top,
+        (right - left) as u32,
+        (bottom - top) as u32,
+    ))
+}
+
+#[cfg(feature = "gif")]
+struct GifWriter<W: Write> {
+    encoder: image::codecs::gif::GifEncoder<W>,
+    previous: Option<RgbImage>,
+    /// When the latest frame was shown.
+    pending: Option<Duration>,
+}
+
+#[cfg(feature = "gif")]
+impl<W: Write> GifWriter<W> {
+    fn new(out: W) -> Result<Self, RecordError> {
+        let mut encoder = image::codecs::gif::GifEncoder::new_with_speed(out, 10);
+        encoder.set_repeat(image::codecs::gif::Repeat::Infinite)?;
+
+        Ok(Self {
+            encoder,
+            previous: None,
+            pending: None,
+        })
+    }
+
+    fn flush_pending(&mut self, delay: Duration) -> Result<(), RecordError> {
+        let frame = match (self.pending.take(), &self.previous) {
+            (Some(_), Some(frame)) => frame,
+            _ => return Ok(()),
+        };
+
+        let rgba = image::RgbaImage::from_fn(frame.width(), frame.height(), |x, y| {
+            let [r, g, b] = frame.get_pixel(x, y).0;
+            image::Rgba([r, g, b, 255])
+        });
+
+        self.encoder.encode_frame(image::Frame::from_parts(
+            rgba,
+            0,
+            0,
+            image::Delay::from_saturating_duration(delay),
+        ))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "gif")]
+impl<W: Write> VideoWriter for GifWriter<W> {
+    fn write_frame(&mut self, frame: &RgbImage, timestamp: Duration) -> Result<(), RecordError> {
+        if let Some(previous) = &self.previous {
+            if previous.as_raw() == frame.as_raw() {
+                return Ok(());
+            }
+        }
+
+        if let Some(shown) = self.pending {
+            self.flush_pending(frame_delay(shown, timestamp))?;
+        }
+
+        self.previous = Some(frame.clone());
+        self.pending = Some(timestamp);
+
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>, end: Duration) -> Result<(), RecordError> {
+        if let Some(shown) = self.pending {
+            self.flush_pending(frame_delay(shown, end))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Pipes the frames as Y4M into `ffmpeg`, which has to be in the `PATH`.
+#[cfg(feature = "mp4")]
+struct Mp4Writer {
+    child: std::process::Child,
+    y4m: Y4mWriter<Counter<BufWriter<std::process::ChildStdin>>>,
+}
+
+#[cfg(feature = "mp4")]
+impl Mp4Writer {
+    fn new(path: &Path, frame_rate: u32, written: Arc<AtomicU64>) -> Result<Self, RecordError> {
+        use std::process::{Command, Stdio};
+
+        let mut child = Command::new("ffmpeg")
+            .args(["-loglevel", "error", "-y", "-f", "yuv4mpegpipe", "-i", "-"])
+            // Most players only handle 4:2:0 with even dimensions.
+            .args([
+                "-vf",
+                "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+                "-pix_fmt",
+                "yuv420p",
+            ])
+            .args(["-an", "-movflags", "+faststart", "-f", "mp4"])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()?;
+
+        let stdin = child.stdin.take().ok_or(RecordError::EncoderFailed)?;
+
+        Ok(Self {
+            child,
+            y4m: Y4mWriter::new(Counter::new(BufWriter::new(stdin), written), frame_rate),
+        })
+    }
+}
+
+#[cfg(feature = "mp4")]
+impl VideoWriter for Mp4Writer {
+    fn write_frame(&mut self, frame: &RgbImage, timestamp: Duration) -> Result<(), RecordError> {
+        self.y4m.write_frame(frame, timestamp)
+    }
+
+    fn finish(self: Box<Self>, end: Duration) -> Result<(), RecordError> {
+        let Mp4Writer { mut child, y4m } = *self;
+
+        // Dropping the writer closes the pipe, which tells ffmpeg there are no more frames.
+        Box::new(y4m).finish(end)?;
+
+        if !child.wait()?.success() {
+            return Err(RecordError::EncoderFailed);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+    use std::io::Cursor;
+
+    #[test]
+    fn full_avis_are_finished() {
+        let mut avi = AviWriter::new(Cursor::new(vec![]), 10);
+        let frame = RgbImage::from_pixel(4, 4, Rgb([1, 2, 3]));
+
+        avi.write_frame(&frame, Duration::ZERO).unwrap();
+        assert!(!avi.is_full());
+
+        // Pretend the file already grew close to what RIFF sizes can count.
+        avi.position = u32::MAX as u64 - 10;
+        avi.write_frame(&frame, Duration::from_millis(100)).unwrap();
+        avi.write_frame(&frame, Duration::from_millis(200)).unwrap();
+
+        assert!(avi.is_full());
+        assert_eq!(avi.index.len(), 1);
+
+        Box::new(avi).finish(Duration::from_millis(300)).unwrap();
+    }
+
+    /// Holds `capacity` frames.
+    struct Limited {
+        capacity: usize,
+        frames: usize,
+    }
+
+    impl VideoWriter for Limited {
+        fn write_frame(&mut self, _: &RgbImage, _: Duration) -> Result<(), RecordError> {
+            self.frames += 1;
+            Ok(())
+        }
+
+        fn finish(self: Box<Self>, _: Duration) -> Result<(), RecordError> {
+            Ok(())
+        }
+
+        fn is_full(&self) -> bool {
+            self.frames >= self.capacity
+        }
+    }
+
+    #[test]
+    fn recordings_stop_when_full() {
+        let writer = Limited {
+            capacity: 2,
+            frames: 0,
+        };
+        let mut recording =
+            Recorder::new(VideoFormat::Y4m).start(Box::new(writer), Arc::new(AtomicU64::new(0)));
+        let frame = RgbImage::new(2, 2);
+
+        assert!(recording.write_frame(&frame, Duration::ZERO).unwrap());
+        assert!(!recording
+            .write_frame(&frame, Duration::from_millis(100))
+            .unwrap());
+        assert!(recording.controls().is_stopped());
+        assert!(!recording
+            .write_frame(&frame, Duration::from_millis(200))
+            .unwrap());
+
+        recording.finish().unwrap();
+    }
+}
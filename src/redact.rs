@@ -0,0 +1,350 @@
+use super::{Rect, RgbImage};
+use std::sync::{Arc, Mutex};
+
+/// Something on the screen that has to be hidden.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Mask {
+    /// A fixed area, in the same coordinates as the displays.
+    Rect(Rect),
+    /// An X11 window, which is looked up on every capture so the mask follows it around.
+    /// Nothing is hidden while it's unmapped or after it's destroyed.
+    #[cfg(target_os = "linux")]
+    Window(u32),
+}
+
+/// How a masked area is hidden.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RedactStyle {
+    /// Paints it black.
+    Black,
+    /// Blurs it with a box blur of this radius, in pixels of the captured image.
+    Blur(u32),
+}
+
+/// Identifies a mask so it can be removed again.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct RedactionId(u64);
+
+/// The masks a capturer hides in every image before handing it out, which covers every
+/// stream, recording and server built on top of it.
+///
+/// Cloning gives another handle to the same masks, so they can be changed from another
+/// thread while the capturer is in use.
+///
+/// ```no_run
+/// use captis::{init_capturer, Capturer, Mask, Rect, RedactStyle};
+///
+/// let capturer = init_capturer().expect("Couldn't Initialize Capturer");
+///
+/// let id = capturer
+///     .redactions()
+///     .add(Mask::Rect(Rect::new(0, 0, 400, 300)), RedactStyle::Blur(16));
+///
+/// let image = capturer.capture(0).expect("Couldn't Capture Screen");
+///
+/// capturer.redactions().remove(id);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Redactions {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    next_id: u64,
+    masks: Vec<(RedactionId, Mask, RedactStyle)>,
+}
+
+impl Redactions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&self, mask: Mask, style: RedactStyle) -> RedactionId {
+        let mut inner = self.inner.lock().unwrap();
+
+        let id = RedactionId(inner.next_id);
+        inner.next_id += 1;
+        inner.masks.push((id, mask, style));
+
+        id
+    }
+
+    /// Removes the mask with `id`, returning whether it was still there.
+    pub fn remove(&self, id: RedactionId) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let len = inner.masks.len();
+
+        inner.masks.retain(|(mask_id, _, _)| *mask_id != id);
+        inner.masks.len() != len
+    }
+
+    pub fn clear(&self) {
+        self.inner.lock().unwrap().masks.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().unwrap().masks.is_empty()
+    }
+
+    /// Returns the windows masked by [`Mask::Window`], so a capture can ask where they are
+    /// before it starts.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn windows(&self) -> Vec<u32> {
+        let inner = self.inner.lock().unwrap();
+
+        inner
+            .masks
+            .iter()
+            .filter_map(|(_, mask, _)| match *mask {
+                Mask::Window(window) => Some(window),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Hides every mask in `image`, a capture of the area at `bounds`, which may have been
+    /// scaled since. `locate` returns where a mask that isn't a plain [`Mask::Rect`] currently
+    /// is, or `None` if it isn't on the screen.
+    pub(crate) fn apply<E>(
+        &self,
+        image: &mut RgbImage,
+        bounds: Rect,
+        mut locate: impl FnMut(&Mask) -> Result<Option<Rect>, E>,
+    ) -> Result<(), E> {
+        // Copied so nothing is looked up while holding the lock.
+        let masks = self.inner.lock().unwrap().masks.clone();
+
+        for (_, mask, style) in masks {
+            let area = match mask {
+                Mask::Rect(rect) => Some(rect),
+                #[allow(unreachable_patterns)]
+                _ => locate(&mask)?,
+            };
+
+            let area = match area.and_then(|area| area.intersection(&bounds)) {
+                Some(area) => area,
+                None => continue,
+            };
+
+            redact(image, &to_image(area, bounds, image), style);
+        }
+
+        Ok(())
+    }
+}
+
+/// Moves `area` from screen coordinates into the ones of `image`, rounding outwards so
+/// scaling never uncovers any of it.
+fn to_image(area: Rect, bounds: Rect, image: &RgbImage) -> Rect {
+    let scale_x = image.width() as f64 / bounds.width.max(1) as f64;
+    let scale_y = image.height() as f64 / bounds.height.max(1) as f64;
+
+    let left = ((area.x - bounds.x) as f64 * scale_x).floor() as u32;
+    let top = ((area.y - bounds.y) as f64 * scale_y).floor() as u32;
+    let right = (((area.x - bounds.x) as f64 + area.width as f64) * scale_x).ceil() as u32;
+    let bottom = (((area.y - bounds.y) as f64 + area.height as f64) * scale_y).ceil() as u32;
+
+    let right = right.min(image.width());
+    let bottom = bottom.min(image.height());
+
+    Rect::new(
+        left as i32,
+        top as i32,
+        right.saturating_sub(left),
+        bottom.saturating_sub(top),
+    )
+}
+
+fn redact(image: &mut RgbImage, area: &Rect, style: RedactStyle) {
+    if area.is_empty() {
+        return;
+    }
+
+    let stride = image.width() as usize * 3;
+    let (left, top) = (area.x as usize, area.y as usize);
+    let (width, height) = (area.width as usize, area.height as usize);
+    let raw: &mut [u8] = image;
+
+    let radius = match style {
+        RedactStyle::Black => {
+            for y in top..top + height {
+                raw[y * stride + left * 3..y * stride + (left + width) * 3].fill(0);
+            }
+
+            return;
+        }
+        RedactStyle::Blur(radius) => radius.max(1) as usize,
+    };
+
+    // Three box blurs in a row come close to a gaussian one.
+    for _ in 0..3 {
+        for y in top..top + height {
+            blur_line(raw, y * stride + left * 3, 3, width, radius);
+        }
+
+        for x in left..left + width {
+            blur_line(raw, top * stride + x * 3, stride, height, radius);
+        }
+    }
+}
+
+/// Blurs `len` pixels of `raw` which start at `start` and are `step` bytes apart, only
+/// sampling pixels inside the line so nothing from outside the mask bleeds in or out.
+fn blur_line(raw: &mut [u8], start: usize, step: usize, len: usize, radius: usize) {
+    let line: Vec<[u32; 3]> = (0..len)
+        .map(|i| {
+            let pixel = start + i * step;
+            [
+                raw[pixel] as u32,
+                raw[pixel + 1] as u32,
+                raw[pixel + 2] as u32,
+            ]
+        })
+        .collect();
+
+    let mut sum = [0u32; 3];
+    let (mut from, mut to) = (0, 0);
+
+    for i in 0..len {
+        let (window_from, window_to) = (i.saturating_sub(radius), (i + radius + 1).min(len));
+
+        while to < window_to {
+            sum.iter_mut().zip(&line[to]).for_each(|(s, v)| *s += v);
+            to += 1;
+        }
+
+        while from < window_from {
+            sum.iter_mut().zip(&line[from]).for_each(|(s, v)| *s -= v);
+            from += 1;
+        }
+
+        let count = (to - from) as u32;
+        let pixel = start + i * step;
+
+        for c in 0..3 {
+            raw[pixel + c] = ((sum[c] + count / 2) / count) as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    const GREY: Rgb<u8> = Rgb([128, 128, 128]);
+    const BLACK: Rgb<u8> = Rgb([0, 0, 0]);
+
+    fn redacted(masks: &[Rect], bounds: Rect, width: u32, height: u32) -> RgbImage {
+        let redactions = Redactions::new();
+
+        for &mask in masks {
+            redactions.add(Mask::Rect(mask), RedactStyle::Black);
+        }
+
+        let mut image = RgbImage::from_pixel(width, height, GREY);
+        redactions
+            .apply(&mut image, bounds, |_| Ok::<_, ()>(None))
+            .unwrap();
+
+        image
+    }
+
+    /// The area of `image` that was painted black, or `None` if nothing was.
+    fn black_area(image: &RgbImage) -> Option<Rect> {
+        let black: Vec<_> = image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| **pixel == BLACK)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+
+        let left = black.iter().map(|&(x, _)| x).min()?;
+        let top = black.iter().map(|&(_, y)| y).min()?;
+        let right = black.iter().map(|&(x, _)| x).max()? + 1;
+        let bottom = black.iter().map(|&(_, y)| y).max()? + 1;
+
+        // Everything in between has to be black too, or it isn't a rectangle.
+        assert_eq!(black.len() as u32, (right - left) * (bottom - top));
+
+        Some(Rect::new(
+            left as i32,
+            top as i32,
+            right - left,
+            bottom - top,
+        ))
+    }
+
+    #[test]
+    fn masks_overlapping_the_edges() {
+        let bounds = Rect::new(0, 0, 40, 30);
+
+        for (mask, expected) in [
+            (Rect::new(-5, 10, 10, 5), Rect::new(0, 10, 5, 5)),
+            (Rect::new(10, -5, 5, 10), Rect::new(10, 0, 5, 5)),
+            (Rect::new(35, 10, 10, 5), Rect::new(35, 10, 5, 5)),
+            (Rect::new(10, 25, 5, 10), Rect::new(10, 25, 5, 5)),
+            (Rect::new(-10, -10, 60, 50), bounds),
+        ] {
+            let image = redacted(&[mask], bounds, 40, 30);
+            assert_eq!(black_area(&image), Some(expected), "{:?}", mask);
+        }
+
+        for mask in [Rect::new(-10, 0, 10, 30), Rect::new(40, 30, 5, 5)] {
+            assert_eq!(black_area(&redacted(&[mask], bounds, 40, 30)), None);
+        }
+    }
+
+    #[test]
+    fn masks_are_in_screen_coordinates() {
+        // A region of a display that starts at (1920, 100), like a second monitor's.
+        let bounds = Rect::new(1920 + 30, 100 + 20, 40, 30);
+
+        let image = redacted(&[Rect::new(1920 + 40, 100 + 10, 10, 20)], bounds, 40, 30);
+        assert_eq!(black_area(&image), Some(Rect::new(10, 0, 10, 10)));
+
+        // The same mask relative to the region, or the display, misses it.
+        let image = redacted(
+            &[Rect::new(10, 0, 10, 10), Rect::new(40, 10, 10, 20)],
+            bounds,
+            40,
+            30,
+        );
+        assert_eq!(black_area(&image), None);
+    }
+
+    #[test]
+    fn scaled_images_round_outwards() {
+        let bounds = Rect::new(100, 100, 40, 30);
+
+        // Shrunk to a third, the 5 pixel wide mask covers a pixel and a bit more.
+        let image = redacted(&[Rect::new(107, 103, 5, 5)], bounds, 13, 10);
+        assert_eq!(black_area(&image), Some(Rect::new(2, 1, 2, 2)));
+
+        // Doubled, and cut off at the right edge.
+        let image = redacted(&[Rect::new(135, 110, 10, 5)], bounds, 80, 60);
+        assert_eq!(black_area(&image), Some(Rect::new(70, 20, 10, 10)));
+    }
+
+    #[test]
+    fn blurs_stay_inside_the_mask() {
+        let redactions = Redactions::new();
+        redactions.add(Mask::Rect(Rect::new(-4, 4, 12, 8)), RedactStyle::Blur(3));
+
+        let mut image =
+            RgbImage::from_fn(16, 16, |x, y| if (x + y) % 2 == 0 { BLACK } else { GREY });
+        let original = image.clone();
+
+        redactions
+            .apply(&mut image, Rect::new(0, 0, 16, 16), |_| Ok::<_, ()>(None))
+            .unwrap();
+
+        for (x, y, pixel) in image.enumerate_pixels() {
+            if x < 8 && (4..12).contains(&y) {
+                assert_ne!(pixel, original.get_pixel(x, y), "({}, {})", x, y);
+            } else {
+                assert_eq!(pixel, original.get_pixel(x, y), "({}, {})", x, y);
+            }
+        }
+    }
+}
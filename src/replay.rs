@@ -0,0 +1,449 @@
+use super::{
+    changed_regions, Capturer, FrameStream, RecordError, Recorder, Rect, RgbImage, VideoFormat,
+    TILE_SIZE,
+};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// How often a full frame is stored, everything in between only stores the tiles that
+/// changed. The buffer is trimmed a whole keyframe interval at a time.
+const KEYFRAME_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Keeps the last few seconds of a display in memory, so they can be saved after something
+/// interesting happened.
+///
+/// Frames are stored compressed and frames that didn't change aren't stored at all. Cloning
+/// the buffer gives another handle to it, which is how it's saved or stopped from other
+/// threads while [`ReplayBuffer::run`] keeps capturing.
+///
+/// ```no_run
+/// use captis::{init_capturer, ReplayBuffer, VideoFormat};
+/// use std::{thread, time::Duration};
+///
+/// let capturer = init_capturer().expect("Couldn't Initialize Capturer");
+///
+/// let replay = ReplayBuffer::new(Duration::from_secs(30), 10).max_memory(256 * 1024 * 1024);
+/// let handle = replay.clone();
+///
+/// thread::spawn(move || {
+///     // Wait for the hotkey...
+///     handle
+///         .save("replay.avi", VideoFormat::Avi)
+///         .expect("Couldn't Save Replay");
+/// });
+///
+/// replay.run(&capturer, 0).expect("Couldn't Capture");
+/// ```
+#[derive(Clone)]
+pub struct ReplayBuffer {
+    buffer: Arc<Mutex<Buffer>>,
+    /// The last frame that was stored, which new frames are compared against. It's locked
+    /// for a whole push, so pushes stay in order while the buffer is only locked around
+    /// inserting the compressed frame.
+    previous: Arc<Mutex<Option<RgbImage>>>,
+    stopped: Arc<AtomicBool>,
+    frame_rate: u32,
+}
+
+struct Buffer {
+    length: Duration,
+    max_memory: usize,
+    memory: usize,
+    frames: VecDeque<Arc<StoredFrame>>,
+    /// When the last frame was pushed, whether it was stored or not.
+    latest: Duration,
+}
+
+struct StoredFrame {
+    timestamp: Duration,
+    keyframe: bool,
+    width: u32,
+    height: u32,
+    /// The compressed rows of every region that changed, a keyframe has one covering the
+    /// whole frame.
+    regions: Vec<(Rect, Vec<u8>)>,
+}
+
+impl StoredFrame {
+    fn size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self
+                .regions
+                .iter()
+                .map(|(_, data)| data.len() + std::mem::size_of::<(Rect, Vec<u8>)>())
+                .sum::<usize>()
+    }
+}
+
+impl ReplayBuffer {
+    /// Creates a buffer that keeps at least the last `length` of frames, captured `frame_rate`
+    /// times per second.
+    pub fn new(length: Duration, frame_rate: u32) -> Self {
+        Self {
+            buffer: Arc::new(Mutex::new(Buffer {
+                length,
+                max_memory: usize::MAX,
+                memory: 0,
+                frames: VecDeque::new(),
+                latest: Duration::ZERO,
+            })),
+            previous: Arc::new(Mutex::new(None)),
+            stopped: Arc::new(AtomicBool::new(false)),
+            frame_rate: frame_rate.max(1),
+        }
+    }
+
+    /// Limits how many bytes the compressed frames may take up, the oldest ones are dropped
+    /// early to stay under it. The newest second of frames is always kept.
+    pub fn max_memory(self, max_memory: usize) -> Self {
+        self.buffer.lock().unwrap().max_memory = max_memory;
+        self
+    }
+
+    /// Returns how many bytes the compressed frames currently take up.
+    pub fn memory_usage(&self) -> usize {
+        self.buffer.lock().unwrap().memory
+    }
+
+    /// Keeps capturing the display at `index` into the buffer until [`ReplayBuffer::stop`] is
+    /// called.
+    pub fn run<C: Capturer>(&self, capturer: &C, index: usize) -> Result<(), RecordError> {
+        for frame in FrameStream::new(capturer, index, self.frame_rate) {
+            if self.stopped.load(Ordering::Relaxed) {
+                break;
+            }
+
+            let (timestamp, image) = frame?;
+            self.push(&image, timestamp)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    /// Adds a frame captured at `timestamp`, for when frames come from somewhere other than
+    /// [`ReplayBuffer::run`]. A frame that couldn't be compressed isn't stored.
+    pub fn push(&self, image: &RgbImage, timestamp: Duration) -> io::Result<()> {
+        let mut previous = self.previous.lock().unwrap();
+
+        let last_keyframe = {
+            let mut buffer = self.buffer.lock().unwrap();
+
+            buffer.latest = timestamp;
+
+            buffer
+                .frames
+                .iter()
+                .rev()
+                .find(|frame| frame.keyframe)
+                .map(|frame| frame.timestamp)
+        };
+
+        let whole = Rect::new(0, 0, image.width(), image.height());
+
+        let (keyframe, regions) = match (&*previous, last_keyframe) {
+            (Some(previous), Some(last_keyframe))
+                if previous.dimensions() == image.dimensions() =>
+            {
+                let regions = changed_regions(previous, image, TILE_SIZE);
+
+                if regions.is_empty() {
+                    return Ok(());
+                }
+
+                if timestamp.saturating_sub(last_keyframe) >= KEYFRAME_INTERVAL {
+                    (true, vec![whole])
+                } else {
+                    (false, regions)
+                }
+            }
+            _ => (true, vec![whole]),
+        };
+
+        let frame = StoredFrame {
+            timestamp,
+            keyframe,
+            width: image.width(),
+            height: image.height(),
+            regions: regions
+                .into_iter()
+                .map(|region| Ok((region, compress_region(image, &region)?)))
+                .collect::<io::Result<_>>()?,
+        };
+
+        *previous = Some(image.clone());
+
+        let mut buffer = self.buffer.lock().unwrap();
+
+        buffer.memory += frame.size();
+        buffer.frames.push_back(Arc::new(frame));
+        buffer.trim();
+
+        Ok(())
+    }
+
+    /// Writes everything in the buffer into a video file at `path`, the buffer keeps filling
+    /// up in the meantime.
+    pub fn save<P: AsRef<Path>>(&self, path: P, format: VideoFormat) -> Result<(), RecordError> {
+        let (frames, latest, length) = {
+            let buffer = self.buffer.lock().unwrap();
+            (buffer.frames.clone(), buffer.latest, buffer.length)
+        };
+
+        let start = latest.saturating_sub(length);
+
+        let mut recording = Recorder::new(format)
+            .frame_rate(self.frame_rate)
+            .create(path)?;
+
+        let mut image = RgbImage::new(0, 0);
+
+        for (i, frame) in frames.iter().enumerate() {
+            if image.dimensions() != (frame.width, frame.height) {
+                image = RgbImage::new(frame.width, frame.height);
+            }
+
+            for (region, data) in &frame.regions {
+                decompress_region(&mut image, region, data)?;
+            }
+
+            // Frames from before the start are only needed to build the first one.
+            let superseded = frames
+                .get(i + 1)
+                .map_or(false, |next| next.timestamp <= start);
+
+            if !superseded {
+                recording.write_frame(&image, frame.timestamp.saturating_sub(start))?;
+            }
+        }
+
+        // Unchanged frames aren't stored, so the last one is repeated to fill the clip.
+        if !frames.is_empty() {
+            recording.write_frame(&image, latest.saturating_sub(start))?;
+        }
+
+        recording.finish()
+    }
+}
+
+impl Buffer {
+    /// Drops the oldest keyframe intervals that are either too old, or too big to fit.
+    fn trim(&mut self) {
+        loop {
+            let second_keyframe = self
+                .frames
+                .iter()
+                .skip(1)
+                .position(|frame| frame.keyframe)
+                .map(|position| position + 1);
+
+            let second_keyframe = match second_keyframe {
+                Some(second_keyframe) => second_keyframe,
+                None => return,
+            };
+
+            let expired =
+                self.frames[second_keyframe].timestamp <= self.latest.saturating_sub(self.length);
+
+            if !expired && self.memory <= self.max_memory {
+                return;
+            }
+
+            for frame in self.frames.drain(..second_keyframe) {
+                self.memory -= frame.size();
+            }
+        }
+    }
+}
+
+fn compress_region(image: &RgbImage, region: &Rect) -> io::Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(vec![], Compression::fast());
+
+    let stride = image.width() as usize * 3;
+    let (start, end) = (
+        region.x as usize * 3,
+        (region.x as usize + region.width as usize) * 3,
+    );
+
+    for y in region.y as usize..region.y as usize + region.height as usize {
+        encoder.write_all(&image.as_raw()[y * stride + start..y * stride + end])?;
+    }
+
+    encoder.finish()
+}
+
+fn decompress_region(image: &mut RgbImage, region: &Rect, data: &[u8]) -> io::Result<()> {
+    let stride = image.width() as usize * 3;
+    let (start, end) = (
+        region.x as usize * 3,
+        (region.x as usize + region.width as usize) * 3,
+    );
+
+    let mut decoder = DeflateDecoder::new(data);
+    let raw: &mut [u8] = image;
+
+    for y in region.y as usize..region.y as usize + region.height as usize {
+        decoder.read_exact(&mut raw[y * stride + start..y * stride + end])?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    /// A grey frame with a block that moves along with `step`, and noise in the bottom rows
+    /// when `noisy` so it doesn't compress away.
+    fn frame(step: u32, noisy: bool) -> RgbImage {
+        RgbImage::from_fn(96, 64, |x, y| {
+            if noisy && y >= 48 {
+                let noise = (x + step * 97).wrapping_mul(2_654_435_761) ^ y.wrapping_mul(40_503);
+                Rgb([noise as u8, (noise >> 8) as u8, (noise >> 16) as u8])
+            } else if (step % 6 * 16..step % 6 * 16 + 16).contains(&x) && y < 16 {
+                Rgb([200, step as u8, 30])
+            } else {
+                Rgb([128, 128, 128])
+            }
+        })
+    }
+
+    fn at(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn stored(replay: &ReplayBuffer) -> Vec<Arc<StoredFrame>> {
+        replay
+            .buffer
+            .lock()
+            .unwrap()
+            .frames
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn stored_frames_round_trip() {
+        let replay = ReplayBuffer::new(Duration::from_secs(10), 10);
+        let mut pushed = vec![];
+
+        for step in 0..30 {
+            // Every third frame is the same as the one before, and isn't stored.
+            let step = step - step / 3;
+            let image = frame(step, true);
+
+            replay.push(&image, at(step as u64 * 100)).unwrap();
+
+            if pushed.last() != Some(&image) {
+                pushed.push(image);
+            }
+        }
+
+        let frames = stored(&replay);
+        assert_eq!(frames.len(), pushed.len());
+        assert!(frames.iter().any(|frame| !frame.keyframe));
+
+        let mut image = RgbImage::new(96, 64);
+
+        for (frame, expected) in frames.iter().zip(&pushed) {
+            for (region, data) in &frame.regions {
+                decompress_region(&mut image, region, data).unwrap();
+            }
+
+            assert_eq!(&image, expected, "{:?}", frame.timestamp);
+        }
+    }
+
+    #[test]
+    fn old_frames_are_evicted() {
+        let replay = ReplayBuffer::new(Duration::from_secs(2), 10);
+
+        for step in 0..50 {
+            replay
+                .push(&frame(step, false), at(step as u64 * 100))
+                .unwrap();
+        }
+
+        let frames = stored(&replay);
+        let start = at(4900) - Duration::from_secs(2);
+
+        // Whole keyframe intervals are dropped, so at most one more than needed is kept.
+        assert!(frames[0].keyframe);
+        assert!(frames[0].timestamp <= start);
+        assert!(frames[0].timestamp + KEYFRAME_INTERVAL > start);
+        assert_eq!(frames.last().unwrap().timestamp, at(4900));
+
+        let memory: usize = frames.iter().map(|frame| frame.size()).sum();
+        assert_eq!(replay.memory_usage(), memory);
+    }
+
+    #[test]
+    fn memory_is_capped() {
+        let replay = ReplayBuffer::new(Duration::from_secs(60), 10);
+        replay.push(&frame(0, true), at(0)).unwrap();
+
+        let per_second = replay.memory_usage() * 10;
+        let replay = replay.max_memory(per_second * 3);
+
+        for step in 1..100 {
+            replay
+                .push(&frame(step, true), at(step as u64 * 100))
+                .unwrap();
+
+            let frames = stored(&replay);
+            let keyframes = frames.iter().filter(|frame| frame.keyframe).count();
+
+            assert!(replay.memory_usage() <= per_second * 3 || keyframes == 1);
+        }
+
+        let frames = stored(&replay);
+        assert!(frames[0].keyframe);
+        assert!(frames[0].timestamp >= at(9900) - Duration::from_secs(4));
+
+        // Even without any room, the newest second stays.
+        let replay = replay.max_memory(0);
+        replay.push(&frame(100, true), at(10_000)).unwrap();
+
+        let frames = stored(&replay);
+        assert_eq!(frames.iter().filter(|frame| frame.keyframe).count(), 1);
+        assert_eq!(frames.last().unwrap().timestamp, at(10_000));
+    }
+
+    #[test]
+    fn saves_the_last_seconds() {
+        let replay = ReplayBuffer::new(Duration::from_secs(2), 10);
+
+        for step in 0..50 {
+            replay
+                .push(&frame(step, false), at(step as u64 * 100))
+                .unwrap();
+        }
+
+        let path = std::env::temp_dir().join(format!("captis-replay-{}.y4m", std::process::id()));
+        replay.save(&path, VideoFormat::Y4m).unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Two seconds at 10 frames a second, and the frame they end on.
+        let frames = data
+            .windows(6)
+            .filter(|window| window == b"FRAME\n")
+            .count();
+        assert_eq!(frames, 21);
+    }
+}
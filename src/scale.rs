@@ -0,0 +1,376 @@
+use super::RgbImage;
+
+/// The size a scaled capture should come out at.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Scale {
+    /// Exactly this width and height, the aspect ratio isn't kept.
+    Size(u32, u32),
+    /// The display's size multiplied by this factor, `0.25` gives a quarter of the width and
+    /// height.
+    Factor(f32),
+}
+
+impl Scale {
+    /// Returns the size a `width` by `height` image ends up at, never smaller than a pixel
+    /// unless the image itself is empty.
+    pub fn target_size(&self, width: u32, height: u32) -> (u32, u32) {
+        if width == 0 || height == 0 {
+            return (0, 0);
+        }
+
+        match *self {
+            Scale::Size(width, height) => (width.max(1), height.max(1)),
+            Scale::Factor(factor) => (
+                ((width as f32 * factor).round() as u32).max(1),
+                ((height as f32 * factor).round() as u32).max(1),
+            ),
+        }
+    }
+}
+
+/// How the pixels of a scaled capture are computed from the display's pixels.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ScaleFilter {
+    /// Takes the closest pixel, the fastest but aliases badly when shrinking.
+    Nearest,
+    /// Blends the four closest pixels, smooth when enlarging or shrinking a little.
+    Bilinear,
+    /// Averages every pixel that falls into the target pixel, the best looking one for
+    /// thumbnails.
+    Box,
+}
+
+/// Builds a `target_width` by `target_height` image out of a `width` by `height` one whose
+/// rows are handed out by `read_row`, which converts row `y` into the RGB row it's given.
+///
+/// Only the rows the filter needs are read and at most two of them are kept around, so the
+/// full-size image never exists.
+pub(crate) fn scale_rows<F: FnMut(usize, &mut [u8])>(
+    (width, height): (u32, u32),
+    (target_width, target_height): (u32, u32),
+    filter: ScaleFilter,
+    read_row: F,
+) -> RgbImage {
+    let mut image = RgbImage::new(target_width, target_height);
+
+    if width == 0 || height == 0 || target_width == 0 || target_height == 0 {
+        return image;
+    }
+
+    let mut rows = SourceRows::new(width as usize, read_row);
+    let (width, height) = (width as usize, height as usize);
+    let (target_width, target_height) = (target_width as usize, target_height as usize);
+
+    let target_rows = image.chunks_exact_mut(target_width * 3).enumerate();
+
+    match filter {
+        ScaleFilter::Nearest => {
+            let columns: Vec<usize> = (0..target_width)
+                .map(|x| nearest(x, width, target_width) * 3)
+                .collect();
+
+            for (y, target) in target_rows {
+                let row = rows.row(nearest(y, height, target_height));
+
+                for (rgb, &column) in target.chunks_exact_mut(3).zip(&columns) {
+                    rgb.copy_from_slice(&row[column..column + 3]);
+                }
+            }
+        }
+        ScaleFilter::Bilinear => {
+            let columns: Vec<(usize, usize, u32)> = (0..target_width)
+                .map(|x| {
+                    let (left, right, weight) = bilinear(x, width, target_width);
+                    (left * 3, right * 3, weight)
+                })
+                .collect();
+
+            for (y, target) in target_rows {
+                let (top, bottom, vertical) = bilinear(y, height, target_height);
+                let (top, bottom) = rows.pair(top, bottom);
+
+                for (rgb, &(left, right, horizontal)) in target.chunks_exact_mut(3).zip(&columns) {
+                    for (c, value) in rgb.iter_mut().enumerate() {
+                        let blend = |row: &[u8]| {
+                            row[left + c] as u32 * (256 - horizontal)
+                                + row[right + c] as u32 * horizontal
+                        };
+
+                        *value =
+                            ((blend(top) * (256 - vertical) + blend(bottom) * vertical + (1 << 15))
+                                >> 16) as u8;
+                    }
+                }
+            }
+        }
+        ScaleFilter::Box => {
+            let columns: Vec<(usize, usize)> = (0..target_width)
+                .map(|x| span(x, width, target_width))
+                .collect();
+
+            // A target pixel can cover a whole 8K display, which is more than u32 can add up.
+            let mut sums = vec![0u64; target_width * 3];
+
+            for (y, target) in target_rows {
+                let (start, end) = span(y, height, target_height);
+
+                sums.iter_mut().for_each(|sum| *sum = 0);
+
+                for source_y in start..end {
+                    let row = rows.row(source_y);
+
+                    for (sum, &(left, right)) in sums.chunks_exact_mut(3).zip(&columns) {
+                        for rgb in row[left * 3..right * 3].chunks_exact(3) {
+                            sum[0] += rgb[0] as u64;
+                            sum[1] += rgb[1] as u64;
+                            sum[2] += rgb[2] as u64;
+                        }
+                    }
+                }
+
+                for ((rgb, sum), &(left, right)) in target
+                    .chunks_exact_mut(3)
+                    .zip(sums.chunks_exact(3))
+                    .zip(&columns)
+                {
+                    let count = ((right - left) * (end - start)) as u64;
+
+                    for (value, sum) in rgb.iter_mut().zip(sum) {
+                        *value = ((sum + count / 2) / count) as u8;
+                    }
+                }
+            }
+        }
+    }
+
+    image
+}
+
+/// Scales an image that was already converted, for capturers that can't do it on the fly.
+pub(crate) fn scale_image(image: &RgbImage, scale: Scale, filter: ScaleFilter) -> RgbImage {
+    let size = image.dimensions();
+    let stride = size.0 as usize * 3;
+
+    scale_rows(size, scale.target_size(size.0, size.1), filter, |y, row| {
+        row.copy_from_slice(&image.as_raw()[y * stride..(y + 1) * stride])
+    })
+}
+
+/// The source pixel whose centre is closest to the centre of target pixel `i`.
+fn nearest(i: usize, size: usize, target_size: usize) -> usize {
+    ((2 * i + 1) * size / (2 * target_size)).min(size - 1)
+}
+
+/// The two source pixels around the centre of target pixel `i`, and how much of the second
+/// one to blend in, out of 256.
+fn bilinear(i: usize, size: usize, target_size: usize) -> (usize, usize, u32) {
+    let position = ((i as f64 + 0.5) * size as f64 / target_size as f64 - 0.5).max(0.0);
+    let first = (position as usize).min(size - 1);
+
+    (
+        first,
+        (first + 1).min(size - 1),
+        ((position - first as f64) * 256.0).round().min(256.0) as u32,
+    )
+}
+
+/// The source pixels covered by target pixel `i`, at least one of them.
+fn span(i: usize, size: usize, target_size: usize) -> (usize, usize) {
+    let start = (i * size / target_size).min(size - 1);
+    let end = ((i + 1) * size / target_size).clamp(start + 1, size);
+
+    (start, end)
+}
+
+/// The last two source rows that were read, neighbouring target rows mostly need the same
+/// ones.
+struct SourceRows<F> {
+    read_row: F,
+    indices: [Option<usize>; 2],
+    rows: [Vec<u8>; 2],
+    /// The slot that was used last, the other one is replaced first.
+    last: usize,
+}
+
+impl<F: FnMut(usize, &mut [u8])> SourceRows<F> {
+    fn new(width: usize, read_row: F) -> Self {
+        Self {
+            read_row,
+            indices: [None, None],
+            rows: [vec![0; width * 3], vec![0; width * 3]],
+            last: 1,
+        }
+    }
+
+    fn row(&mut self, y: usize) -> &[u8] {
+        let slot = self.load(y, None);
+        &self.rows[slot]
+    }
+
+    /// Returns rows `first` and `second`, reading the ones that aren't kept around.
+    fn pair(&mut self, first: usize, second: usize) -> (&[u8], &[u8]) {
+        let first_slot = self.load(first, None);
+        let second_slot = self.load(second, Some(first_slot));
+
+        (&self.rows[first_slot], &self.rows[second_slot])
+    }
+
+    fn load(&mut self, y: usize, keep: Option<usize>) -> usize {
+        let slot = match self.indices.iter().position(|&index| index == Some(y)) {
+            Some(slot) => slot,
+            None => {
+                let slot = 1 - keep.unwrap_or(self.last);
+
+                (self.read_row)(y, &mut self.rows[slot]);
+                self.indices[slot] = Some(y);
+
+                slot
+            }
+        };
+
+        self.last = slot;
+        slot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{imageops, imageops::FilterType, Rgb};
+
+    /// A small image with hard edges, where filters that pick or weigh pixels differently
+    /// can't agree by accident.
+    fn input() -> RgbImage {
+        RgbImage::from_fn(12, 9, |x, y| {
+            Rgb([
+                ((x * 37 + y * 91) % 256) as u8,
+                ((x * y * 13) % 256) as u8,
+                if (x + y) % 2 == 0 { 255 } else { 0 },
+            ])
+        })
+    }
+
+    fn max_difference(a: &RgbImage, b: &RgbImage) -> u8 {
+        assert_eq!(a.dimensions(), b.dimensions());
+
+        a.as_raw()
+            .iter()
+            .zip(b.as_raw())
+            .map(|(&a, &b)| a.max(b) - a.min(b))
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn nearest_matches_image() {
+        let input = input();
+
+        for (width, height) in [(24, 18), (36, 27), (6, 3), (5, 4), (7, 11), (4, 3), (12, 9)] {
+            let ours = scale_image(&input, Scale::Size(width, height), ScaleFilter::Nearest);
+            let theirs = imageops::resize(&input, width, height, FilterType::Nearest);
+
+            assert_eq!(ours, theirs, "{}x{}", width, height);
+        }
+    }
+
+    #[test]
+    fn bilinear_matches_image_when_enlarging() {
+        let input = input();
+
+        // `image` widens its triangle filter when shrinking, so only enlarging compares.
+        for (width, height) in [(24, 18), (36, 27), (13, 10), (12, 9)] {
+            let ours = scale_image(&input, Scale::Size(width, height), ScaleFilter::Bilinear);
+            let theirs = imageops::resize(&input, width, height, FilterType::Triangle);
+
+            assert!(max_difference(&ours, &theirs) <= 1, "{}x{}", width, height);
+        }
+    }
+
+    #[test]
+    fn box_averages_a_whole_8k_display_into_one_pixel() {
+        let input = RgbImage::from_pixel(7680, 4320, Rgb([255, 128, 1]));
+
+        let ours = scale_image(&input, Scale::Size(1, 1), ScaleFilter::Box);
+
+        assert_eq!(ours.get_pixel(0, 0).0, [255, 128, 1]);
+    }
+
+    #[test]
+    fn box_matches_thumbnails_by_whole_factors() {
+        let input = input();
+
+        for (width, height) in [(6, 3), (4, 3), (12, 1), (1, 9), (1, 1)] {
+            let ours = scale_image(&input, Scale::Size(width, height), ScaleFilter::Box);
+            let theirs = imageops::thumbnail(&input, width, height);
+
+            assert_eq!(ours, theirs, "{}x{}", width, height);
+        }
+    }
+
+    #[test]
+    fn odd_sizes() {
+        let input = input();
+        let filters = [
+            ScaleFilter::Nearest,
+            ScaleFilter::Bilinear,
+            ScaleFilter::Box,
+        ];
+
+        for filter in filters {
+            for (width, height) in [(1, 1), (1, 7), (7, 1), (5, 5), (11, 8), (13, 10), (25, 19)] {
+                let scaled = scale_image(&input, Scale::Size(width, height), filter);
+                assert_eq!(scaled.dimensions(), (width, height), "{:?}", filter);
+            }
+
+            // A single pixel spreads over the whole image.
+            let pixel = RgbImage::from_pixel(1, 1, Rgb([10, 200, 30]));
+            assert_eq!(
+                scale_image(&pixel, Scale::Size(3, 5), filter),
+                RgbImage::from_pixel(3, 5, Rgb([10, 200, 30])),
+                "{:?}",
+                filter
+            );
+
+            assert_eq!(
+                scale_image(&RgbImage::new(0, 0), Scale::Factor(2.0), filter).dimensions(),
+                (0, 0)
+            );
+        }
+
+        // A 3x3 block shrinks to its centre, or to its average.
+        let block = RgbImage::from_fn(3, 3, |x, y| Rgb([(x * 3 + y) as u8 * 10, 0, 0]));
+
+        assert_eq!(
+            scale_image(&block, Scale::Size(1, 1), ScaleFilter::Nearest).get_pixel(0, 0),
+            &Rgb([40, 0, 0])
+        );
+        assert_eq!(
+            scale_image(&block, Scale::Size(1, 1), ScaleFilter::Bilinear).get_pixel(0, 0),
+            &Rgb([40, 0, 0])
+        );
+        assert_eq!(
+            scale_image(&block, Scale::Size(1, 1), ScaleFilter::Box).get_pixel(0, 0),
+            &Rgb([40, 0, 0])
+        );
+    }
+
+    #[test]
+    fn target_sizes_round_and_never_vanish() {
+        assert_eq!(Scale::Factor(0.5).target_size(7, 5), (4, 3));
+        assert_eq!(Scale::Factor(0.01).target_size(7, 5), (1, 1));
+        assert_eq!(Scale::Size(0, 0).target_size(7, 5), (1, 1));
+        assert_eq!(Scale::Factor(2.0).target_size(0, 5), (0, 0));
+    }
+
+    #[test]
+    fn only_needed_rows_are_read() {
+        let mut read = vec![];
+
+        scale_rows((4, 9), (2, 3), ScaleFilter::Nearest, |y, row| {
+            read.push(y);
+            row.fill(0);
+        });
+
+        assert_eq!(read, [1, 4, 7]);
+    }
+}
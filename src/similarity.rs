@@ -0,0 +1,231 @@
+use super::{
+    scale::{scale_image, Scale, ScaleFilter},
+    RgbImage,
+};
+use std::{cmp::Ordering, f64::consts::PI, fmt};
+
+/// A 64 bit fingerprint of an image, images that look alike have hashes that differ in few
+/// bits.
+///
+/// Hashes of different kinds can't be compared with each other.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImageHash(pub u64);
+
+impl ImageHash {
+    /// The number of bits that differ between the hashes, from 0 for images that look the
+    /// same to 64.
+    pub fn distance(&self, other: &ImageHash) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+
+    /// The share of bits the hashes have in common, from 0 to 1.
+    pub fn similarity(&self, other: &ImageHash) -> f64 {
+        1.0 - self.distance(other) as f64 / 64.0
+    }
+}
+
+impl fmt::Display for ImageHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// The longest side images are scaled down to before [`ssim`] compares them.
+pub const SSIM_SIZE: u32 = 256;
+
+/// Edge length of the windows [`ssim`] compares, they overlap by half.
+const WINDOW: usize = 8;
+
+/// Hashes the image by whether each pixel of an 8x8 thumbnail is brighter than their
+/// average. Cheap, and good at telling apart images with different layouts.
+pub fn average_hash(image: &RgbImage) -> ImageHash {
+    let luma = thumbnail(image, 8, 8);
+
+    if luma.is_empty() {
+        return ImageHash(0);
+    }
+
+    let mean = luma.iter().sum::<f64>() / luma.len() as f64;
+
+    to_hash(luma.iter().map(|&value| value > mean))
+}
+
+/// Hashes the image by whether each pixel of a 9x8 thumbnail is darker than the one to its
+/// right. Unaffected by the overall brightness and contrast.
+pub fn difference_hash(image: &RgbImage) -> ImageHash {
+    let luma = thumbnail(image, 9, 8);
+
+    if luma.is_empty() {
+        return ImageHash(0);
+    }
+
+    to_hash(
+        luma.chunks_exact(9)
+            .flat_map(|row| row.windows(2).map(|pair| pair[1] > pair[0])),
+    )
+}
+
+/// Hashes the lowest 8x8 frequencies of a 32x32 thumbnail's discrete cosine transform by
+/// whether they're above their median. The slowest of the three, but the most tolerant of
+/// blurring, compression and small shifts.
+pub fn perceptual_hash(image: &RgbImage) -> ImageHash {
+    const SIZE: usize = 32;
+
+    let luma = thumbnail(image, SIZE as u32, SIZE as u32);
+
+    if luma.is_empty() {
+        return ImageHash(0);
+    }
+
+    let cosines: Vec<f64> = (0..8)
+        .flat_map(|u| {
+            (0..SIZE).map(move |x| ((2 * x + 1) as f64 * u as f64 * PI / (2 * SIZE) as f64).cos())
+        })
+        .collect();
+
+    // The transform is separable, so the rows go first, then the columns of what came out.
+    let mut rows = vec![0.0; SIZE * 8];
+
+    for (y, row) in luma.chunks_exact(SIZE).enumerate() {
+        for u in 0..8 {
+            rows[y * 8 + u] = dot(row.iter().copied(), &cosines[u * SIZE..(u + 1) * SIZE]);
+        }
+    }
+
+    let mut frequencies = vec![0.0; 64];
+
+    for v in 0..8 {
+        for u in 0..8 {
+            let column = (0..SIZE).map(|y| rows[y * 8 + u]);
+            frequencies[v * 8 + u] = dot(column, &cosines[v * SIZE..(v + 1) * SIZE]);
+        }
+    }
+
+    let mut sorted = frequencies.clone();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let median = (sorted[31] + sorted[32]) / 2.0;
+
+    to_hash(frequencies.iter().map(|&value| value > median))
+}
+
+/// Compares the brightness of two images with the structural similarity index, from 1 for
+/// identical images down to 0 for unrelated ones, it can dip below 0 for inverted ones.
+///
+/// Both are scaled down to at most [`SSIM_SIZE`] pixels on their longer side first, which
+/// also averages away most antialiasing noise. Images of different sizes score 0, two empty
+/// ones score 1.
+pub fn ssim(first: &RgbImage, second: &RgbImage) -> f64 {
+    if first.dimensions() != second.dimensions() {
+        return 0.0;
+    }
+
+    let (width, height) = first.dimensions();
+
+    if width == 0 || height == 0 {
+        return 1.0;
+    }
+
+    let (width, height) = if width.max(height) > SSIM_SIZE {
+        let factor = SSIM_SIZE as f64 / width.max(height) as f64;
+
+        (
+            ((width as f64 * factor).round() as u32).max(1),
+            ((height as f64 * factor).round() as u32).max(1),
+        )
+    } else {
+        (width, height)
+    };
+
+    let (first, second) = (
+        thumbnail(first, width, height),
+        thumbnail(second, width, height),
+    );
+    let (width, height) = (width as usize, height as usize);
+
+    let (window_width, window_height) = (WINDOW.min(width), WINDOW.min(height));
+    let mut total = 0.0;
+    let mut windows = 0;
+
+    for top in offsets(height, window_height) {
+        for left in offsets(width, window_width) {
+            let pixels = (top..top + window_height).flat_map(|y| {
+                let row = y * width + left;
+                (row..row + window_width).map(|i| (first[i], second[i]))
+            });
+
+            total += window_ssim(pixels, (window_width * window_height) as f64);
+            windows += 1;
+        }
+    }
+
+    total / windows as f64
+}
+
+/// SSIM of a single window from its pairs of pixels.
+fn window_ssim(pixels: impl Iterator<Item = (f64, f64)> + Clone, count: f64) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+    let (sum_x, sum_y) = pixels
+        .clone()
+        .fold((0.0, 0.0), |(x, y), (a, b)| (x + a, y + b));
+    let (mean_x, mean_y) = (sum_x / count, sum_y / count);
+
+    let (variance_x, variance_y, covariance) =
+        pixels.fold((0.0, 0.0, 0.0), |(x, y, xy), (a, b)| {
+            let (a, b) = (a - mean_x, b - mean_y);
+            (x + a * a, y + b * b, xy + a * b)
+        });
+    let (variance_x, variance_y, covariance) =
+        (variance_x / count, variance_y / count, covariance / count);
+
+    ((2.0 * mean_x * mean_y + C1) * (2.0 * covariance + C2))
+        / ((mean_x * mean_x + mean_y * mean_y + C1) * (variance_x + variance_y + C2))
+}
+
+/// Where the windows start along a side, half a window apart and always including the last
+/// one that fits.
+fn offsets(size: usize, window: usize) -> impl Iterator<Item = usize> {
+    let last = size - window;
+    let step = (window / 2).max(1);
+
+    (0..=last)
+        .step_by(step)
+        .chain(if last % step != 0 { Some(last) } else { None })
+}
+
+/// Scales the image to `width` by `height` with a box filter and returns the brightness of
+/// each pixel, row by row.
+fn thumbnail(image: &RgbImage, width: u32, height: u32) -> Vec<f64> {
+    if image.dimensions() == (width, height) {
+        return luma(image);
+    }
+
+    luma(&scale_image(
+        image,
+        Scale::Size(width, height),
+        ScaleFilter::Box,
+    ))
+}
+
+/// The brightness of each pixel with the BT.601 weights, from 0 to 255.
+fn luma(image: &RgbImage) -> Vec<f64> {
+    image
+        .as_raw()
+        .chunks_exact(3)
+        .map(|rgb| 0.299 * rgb[0] as f64 + 0.587 * rgb[1] as f64 + 0.114 * rgb[2] as f64)
+        .collect()
+}
+
+fn dot(values: impl Iterator<Item = f64>, weights: &[f64]) -> f64 {
+    values
+        .zip(weights)
+        .map(|(value, weight)| value * weight)
+        .sum()
+}
+
+/// Packs up to 64 bits into a hash, the first one becomes the most significant bit.
+fn to_hash(bits: impl Iterator<Item = bool>) -> ImageHash {
+    ImageHash(bits.fold(0, |hash, bit| hash << 1 | bit as u64))
+}
@@ -0,0 +1,54 @@
+use super::{Capturer, Error, RgbImage};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Captures a display at a steady rate, yielding every frame together with the time it was
+/// captured at, relative to the start of the stream.
+///
+/// Frames that can't be captured in time are skipped rather than delivered late, so the
+/// timestamps aren't always evenly spaced. The stream never ends on its own.
+pub struct FrameStream<'a, C: Capturer> {
+    capturer: &'a C,
+    index: usize,
+    interval: Duration,
+    started: Instant,
+    next: Instant,
+}
+
+impl<'a, C: Capturer> FrameStream<'a, C> {
+    pub fn new(capturer: &'a C, index: usize, frame_rate: u32) -> Self {
+        let now = Instant::now();
+
+        Self {
+            capturer,
+            index,
+            interval: Duration::from_secs(1) / frame_rate.max(1),
+            started: now,
+            next: now,
+        }
+    }
+}
+
+impl<'a, C: Capturer> Iterator for FrameStream<'a, C> {
+    type Item = Result<(Duration, RgbImage), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        thread::sleep(self.next.saturating_duration_since(Instant::now()));
+
+        let captured_at = Instant::now();
+
+        self.next += self.interval;
+
+        if self.next < captured_at {
+            self.next = captured_at + self.interval;
+        }
+
+        Some(
+            self.capturer
+                .capture(self.index)
+                .map(|image| (captured_at - self.started, image)),
+        )
+    }
+}
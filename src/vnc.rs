@@ -0,0 +1,635 @@
+use super::{
+    broadcast::{Listeners, ServerError, Subscription},
+    changed_regions, Capturer, Rect, RgbImage, TILE_SIZE,
+};
+use flate2::{Compress, Compression, FlushCompress};
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    sync::Arc,
+    time::Duration,
+};
+
+const ENCODING_RAW: i32 = 0;
+const ENCODING_ZRLE: i32 = 16;
+const ENCODING_DESKTOP_SIZE: i32 = -223;
+
+/// ZRLE always splits rectangles into tiles of this size, regardless of [`TILE_SIZE`].
+const ZRLE_TILE_SIZE: u32 = 64;
+
+/// A read-only RFB (VNC) server which lets any standard VNC viewer watch the displays of a
+/// [`Capturer`].
+///
+/// Every display is served on its own address, any number of viewers can connect to each of
+/// them and only the parts of the screen that changed since their last update are sent.
+///
+/// ```no_run
+/// use captis::{init_capturer, VncServer};
+///
+/// let capturer = init_capturer().expect("Couldn't Initialize Capturer");
+///
+/// VncServer::new()
+///     .bind(0, "0.0.0.0:5900")
+///     .expect("Couldn't Bind Server")
+///     .run(&capturer)
+///     .expect("Server Failed");
+/// ```
+pub struct VncServer {
+    name: String,
+    frame_rate: u32,
+    listeners: Listeners,
+}
+
+impl Default for VncServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VncServer {
+    pub fn new() -> Self {
+        Self {
+            name: String::from("captis"),
+            frame_rate: 15,
+            listeners: Listeners::new(),
+        }
+    }
+
+    /// Sets the desktop name shown by viewers.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Sets how many times per second the displays are captured while someone is watching.
+    pub fn frame_rate(mut self, frame_rate: u32) -> Self {
+        self.frame_rate = frame_rate.max(1);
+        self
+    }
+
+    /// Sets how many clients are served at once over all displays, 32 by default. Anyone
+    /// connecting beyond that is disconnected right away.
+    pub fn max_clients(mut self, max_clients: usize) -> Self {
+        self.listeners.max_clients(max_clients);
+        self
+    }
+
+    /// Serves the display at `index` on `addr`, can be called once for every display.
+    pub fn bind<A: ToSocketAddrs>(mut self, index: usize, addr: A) -> Result<Self, ServerError> {
+        self.listeners.bind(index, addr)?;
+        Ok(self)
+    }
+
+    /// Returns the addresses the server listens on, in the order they were bound.
+    pub fn local_addrs(&self) -> Result<Vec<SocketAddr>, ServerError> {
+        Ok(self.listeners.local_addrs()?)
+    }
+
+    /// Starts accepting viewers and keeps capturing on the current thread, only returns if
+    /// capturing fails.
+    pub fn run<C: Capturer>(self, capturer: &C) -> Result<(), ServerError> {
+        let interval = Duration::from_secs(1) / self.frame_rate;
+        let name = Arc::new(self.name);
+
+        self.listeners.run(
+            capturer,
+            interval,
+            |image| image,
+            move |stream, mailbox| Session::serve(stream, mailbox.subscribe(), &name, interval),
+        )
+    }
+}
+
+/// The state of a single connected viewer.
+struct Session {
+    stream: TcpStream,
+    mailbox: Subscription<RgbImage>,
+    format: PixelFormat,
+    zrle: bool,
+    desktop_size: bool,
+    zlib: Compress,
+    sent: Arc<RgbImage>,
+    sequence: u64,
+    width: u32,
+    height: u32,
+    interval: Duration,
+}
+
+impl Session {
+    fn serve(
+        stream: TcpStream,
+        mailbox: Subscription<RgbImage>,
+        name: &str,
+        interval: Duration,
+    ) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+
+        let (sequence, frame) = loop {
+            if let Some(latest) = mailbox.wait_newer(0, Duration::from_secs(1))? {
+                break latest;
+            }
+        };
+
+        let mut session = Session {
+            stream,
+            mailbox,
+            format: PixelFormat::DEFAULT,
+            zrle: false,
+            desktop_size: false,
+            zlib: Compress::new(Compression::fast(), true),
+            width: frame.width(),
+            height: frame.height(),
+            sent: frame,
+            sequence,
+            interval,
+        };
+
+        session.handshake(name)?;
+        session.process_messages()
+    }
+
+    fn handshake(&mut self, name: &str) -> io::Result<()> {
+        self.stream.write_all(b"RFB 003.008\n")?;
+
+        let mut version = [0; 12];
+        self.stream.read_exact(&mut version)?;
+
+        let minor = match &version {
+            [b'R', b'F', b'B', b' ', b'0', b'0', b'3', b'.', minor @ .., b'\n'] => {
+                std::str::from_utf8(minor)
+                    .ok()
+                    .and_then(|minor| minor.parse::<u16>().ok())
+            }
+            _ => None,
+        }
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "Unsupported RFB version"))?;
+
+        // Only the "None" security type is offered, the server is read-only.
+        if minor >= 7 {
+            self.stream.write_all(&[1, 1])?;
+
+            if self.read_u8()? != 1 {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "Unsupported security type",
+                ));
+            }
+
+            if minor >= 8 {
+                self.stream.write_all(&0u32.to_be_bytes())?;
+            }
+        } else {
+            self.stream.write_all(&1u32.to_be_bytes())?;
+        }
+
+        // The shared flag is irrelevant, viewers never disconnect each other.
+        self.read_u8()?;
+
+        let mut server_init = Vec::with_capacity(24 + name.len());
+        server_init.extend_from_slice(&(self.width as u16).to_be_bytes());
+        server_init.extend_from_slice(&(self.height as u16).to_be_bytes());
+        self.format.write(&mut server_init);
+        server_init.extend_from_slice(&(name.len() as u32).to_be_bytes());
+        server_init.extend_from_slice(name.as_bytes());
+
+        self.stream.write_all(&server_init)
+    }
+
+    fn process_messages(&mut self) -> io::Result<()> {
+        let mut pending: Option<(bool, Rect)> = None;
+
+        loop {
+            if let Some((incremental, area)) = pending {
+                if self.send_update(incremental, area)? {
+                    pending = None;
+                }
+            }
+
+            match self.poll_message_type()? {
+                None => self.mailbox.check_open()?,
+                // SetPixelFormat
+                Some(0) => {
+                    let mut message = [0; 19];
+                    self.stream.read_exact(&mut message)?;
+
+                    let format = PixelFormat::read(&message[3..]);
+
+                    if !format.true_colour {
+                        return Err(io::Error::new(
+                            ErrorKind::InvalidData,
+                            "Colour map pixel formats aren't supported",
+                        ));
+                    }
+
+                    self.format = format;
+                }
+                // SetEncodings
+                Some(2) => {
+                    let mut header = [0; 3];
+                    self.stream.read_exact(&mut header)?;
+
+                    let mut encodings =
+                        vec![0; u16::from_be_bytes([header[1], header[2]]) as usize * 4];
+                    self.stream.read_exact(&mut encodings)?;
+
+                    let encodings: Vec<i32> = encodings
+                        .chunks_exact(4)
+                        .map(|bytes| i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                        .collect();
+
+                    self.zrle = encodings
+                        .iter()
+                        .find(|&&encoding| encoding == ENCODING_RAW || encoding == ENCODING_ZRLE)
+                        == Some(&ENCODING_ZRLE);
+                    self.desktop_size = encodings.contains(&ENCODING_DESKTOP_SIZE);
+                }
+                // FramebufferUpdateRequest
+                Some(3) => {
+                    let mut message = [0; 9];
+                    self.stream.read_exact(&mut message)?;
+
+                    let field = |i: usize| u16::from_be_bytes([message[i], message[i + 1]]);
+                    let area = Rect::new(
+                        field(1) as i32,
+                        field(3) as i32,
+                        field(5) as u32,
+                        field(7) as u32,
+                    );
+                    let incremental = message[0] != 0;
+
+                    pending = Some(match pending {
+                        Some((pending_incremental, pending_area)) => (
+                            incremental && pending_incremental,
+                            bounding_rect(&area, &pending_area),
+                        ),
+                        None => (incremental, area),
+                    });
+                }
+                // KeyEvent and PointerEvent are ignored, the server is read-only.
+                Some(4) => self.skip(7)?,
+                Some(5) => self.skip(5)?,
+                // ClientCutText
+                Some(6) => {
+                    let mut header = [0; 7];
+                    self.stream.read_exact(&mut header)?;
+                    self.skip(
+                        u32::from_be_bytes([header[3], header[4], header[5], header[6]]) as u64,
+                    )?;
+                }
+                Some(message_type) => {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Unsupported client message {}", message_type),
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Sends the requested area if there's anything to send, returns whether it did.
+    fn send_update(&mut self, incremental: bool, area: Rect) -> io::Result<bool> {
+        let (sequence, frame) = match self.mailbox.wait_newer(self.sequence, Duration::ZERO)? {
+            Some(latest) => latest,
+            None if incremental => return Ok(false),
+            None => (self.sequence, self.sent.clone()),
+        };
+
+        self.sequence = sequence;
+
+        let mut rects = vec![];
+        let mut resized = false;
+
+        if frame.dimensions() != (self.width, self.height) && self.desktop_size {
+            self.width = frame.width();
+            self.height = frame.height();
+            resized = true;
+        }
+
+        let bounds = Rect::new(
+            0,
+            0,
+            self.width.min(frame.width()),
+            self.height.min(frame.height()),
+        );
+
+        if resized {
+            rects.push(bounds);
+        } else if let Some(area) = area.intersection(&bounds) {
+            if incremental && self.sent.dimensions() == frame.dimensions() {
+                rects.extend(
+                    changed_regions(&self.sent, &frame, TILE_SIZE)
+                        .iter()
+                        .filter_map(|rect| rect.intersection(&area)),
+                );
+            } else {
+                rects.push(area);
+            }
+        }
+
+        if rects.is_empty() && incremental {
+            return Ok(false);
+        }
+
+        let mut message = vec![0, 0];
+        message.extend_from_slice(&(rects.len() as u16 + resized as u16).to_be_bytes());
+
+        if resized {
+            write_rect_header(
+                &mut message,
+                &Rect::new(0, 0, self.width, self.height),
+                ENCODING_DESKTOP_SIZE,
+            );
+        }
+
+        for rect in &rects {
+            if self.zrle {
+                write_rect_header(&mut message, rect, ENCODING_ZRLE);
+                self.write_zrle(&mut message, &frame, rect)?;
+            } else {
+                write_rect_header(&mut message, rect, ENCODING_RAW);
+                self.write_raw(&mut message, &frame, rect);
+            }
+        }
+
+        self.stream.write_all(&message)?;
+        self.sent = frame;
+
+        Ok(true)
+    }
+
+    fn write_raw(&self, out: &mut Vec<u8>, frame: &RgbImage, rect: &Rect) {
+        out.reserve(rect.width as usize * rect.height as usize * self.format.bytes_per_pixel());
+
+        for y in rect.y as u32..rect.y as u32 + rect.height {
+            for x in rect.x as u32..rect.x as u32 + rect.width {
+                let value = self.format.pixel(frame.get_pixel(x, y).0);
+                self.format.write_pixel(value, out);
+            }
+        }
+    }
+
+    fn write_zrle(&mut self, out: &mut Vec<u8>, frame: &RgbImage, rect: &Rect) -> io::Result<()> {
+        let mut tiles = vec![];
+        let mut pixels = vec![];
+        let mut palette = vec![];
+
+        let (right, bottom) = (rect.x as u32 + rect.width, rect.y as u32 + rect.height);
+
+        for tile_y in (rect.y as u32..bottom).step_by(ZRLE_TILE_SIZE as usize) {
+            for tile_x in (rect.x as u32..right).step_by(ZRLE_TILE_SIZE as usize) {
+                let tile_width = ZRLE_TILE_SIZE.min(right - tile_x);
+                let tile_height = ZRLE_TILE_SIZE.min(bottom - tile_y);
+
+                pixels.clear();
+                palette.clear();
+
+                for y in tile_y..tile_y + tile_height {
+                    for x in tile_x..tile_x + tile_width {
+                        let value = self.format.pixel(frame.get_pixel(x, y).0);
+
+                        if palette.len() <= 16 && !palette.contains(&value) {
+                            palette.push(value);
+                        }
+
+                        pixels.push(value);
+                    }
+                }
+
+                match palette.len() {
+                    // Solid tile.
+                    1 => {
+                        tiles.push(1);
+                        self.format.write_cpixel(palette[0], &mut tiles);
+                    }
+                    // Packed palette tile, every row starts on a byte boundary.
+                    2..=16 => {
+                        tiles.push(palette.len() as u8);
+
+                        for &value in &palette {
+                            self.format.write_cpixel(value, &mut tiles);
+                        }
+
+                        let bits = match palette.len() {
+                            2 => 1,
+                            3..=4 => 2,
+                            _ => 4,
+                        };
+
+                        for row in pixels.chunks(tile_width as usize) {
+                            let (mut byte, mut used) = (0u8, 0);
+
+                            for value in row {
+                                let index = palette.iter().position(|p| p == value).unwrap_or(0);
+                                byte |= (index as u8) << (8 - bits - used);
+                                used += bits;
+
+                                if used == 8 {
+                                    tiles.push(byte);
+                                    byte = 0;
+                                    used = 0;
+                                }
+                            }
+
+                            if used > 0 {
+                                tiles.push(byte);
+                            }
+                        }
+                    }
+                    // Raw tile.
+                    _ => {
+                        tiles.push(0);
+
+                        for &value in &pixels {
+                            self.format.write_cpixel(value, &mut tiles);
+                        }
+                    }
+                }
+            }
+        }
+
+        // The zlib stream lives as long as the connection, every rectangle ends with a sync
+        // flush so the viewer can decode it right away.
+        let mut compressed = Vec::with_capacity(tiles.len() / 2 + 64);
+        let total_in = self.zlib.total_in();
+
+        loop {
+            let consumed = (self.zlib.total_in() - total_in) as usize;
+
+            if compressed.capacity() - compressed.len() < 64 {
+                compressed.reserve(compressed.capacity().max(64));
+            }
+
+            self.zlib
+                .compress_vec(&tiles[consumed..], &mut compressed, FlushCompress::Sync)
+                .map_err(|error| io::Error::new(ErrorKind::Other, error))?;
+
+            if (self.zlib.total_in() - total_in) as usize == tiles.len()
+                && compressed.len() < compressed.capacity()
+            {
+                break;
+            }
+        }
+
+        out.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+        out.extend_from_slice(&compressed);
+
+        Ok(())
+    }
+
+    /// Reads the type of the next client message, waiting at most a frame for it to arrive.
+    fn poll_message_type(&mut self) -> io::Result<Option<u8>> {
+        let mut message_type = [0];
+
+        self.stream.set_read_timeout(Some(self.interval))?;
+        let read = self.stream.read(&mut message_type);
+        self.stream.set_read_timeout(None)?;
+
+        match read {
+            Ok(0) => Err(ErrorKind::UnexpectedEof.into()),
+            Ok(_) => Ok(Some(message_type[0])),
+            Err(error) if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                Ok(None)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    fn read_u8(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    fn skip(&mut self, length: u64) -> io::Result<()> {
+        io::copy(&mut (&mut self.stream).take(length), &mut io::sink())?;
+        Ok(())
+    }
+}
+
+fn write_rect_header(out: &mut Vec<u8>, rect: &Rect, encoding: i32) {
+    out.extend_from_slice(&(rect.x as u16).to_be_bytes());
+    out.extend_from_slice(&(rect.y as u16).to_be_bytes());
+    out.extend_from_slice(&(rect.width as u16).to_be_bytes());
+    out.extend_from_slice(&(rect.height as u16).to_be_bytes());
+    out.extend_from_slice(&encoding.to_be_bytes());
+}
+
+fn bounding_rect(a: &Rect, b: &Rect) -> Rect {
+    let left = a.x.min(b.x);
+    let top = a.y.min(b.y);
+    let right = (a.x + a.width as i32).max(b.x + b.width as i32);
+    let bottom = (a.y + a.height as i32).max(b.y + b.height as i32);
+
+    Rect::new(left, top, (right - left) as u32, (bottom - top) as u32)
+}
+
+/// The pixel format a viewer wants to receive pixels in, only true colour formats are
+/// supported.
+#[derive(Debug, Copy, Clone)]
+struct PixelFormat {
+    bits_per_pixel: u8,
+    depth: u8,
+    big_endian: bool,
+    true_colour: bool,
+    red_max: u16,
+    green_max: u16,
+    blue_max: u16,
+    red_shift: u8,
+    green_shift: u8,
+    blue_shift: u8,
+}
+
+impl PixelFormat {
+    const DEFAULT: PixelFormat = PixelFormat {
+        bits_per_pixel: 32,
+        depth: 24,
+        big_endian: false,
+        true_colour: true,
+        red_max: 255,
+        green_max: 255,
+        blue_max: 255,
+        red_shift: 16,
+        green_shift: 8,
+        blue_shift: 0,
+    };
+
+    fn read(bytes: &[u8]) -> Self {
+        let field = |i: usize| u16::from_be_bytes([bytes[i], bytes[i + 1]]);
+
+        Self {
+            bits_per_pixel: bytes[0],
+            depth: bytes[1],
+            big_endian: bytes[2] != 0,
+            true_colour: bytes[3] != 0,
+            red_max: field(4),
+            green_max: field(6),
+            blue_max: field(8),
+            red_shift: bytes[10],
+            green_shift: bytes[11],
+            blue_shift: bytes[12],
+        }
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[
+            self.bits_per_pixel,
+            self.depth,
+            self.big_endian as u8,
+            self.true_colour as u8,
+        ]);
+        out.extend_from_slice(&self.red_max.to_be_bytes());
+        out.extend_from_slice(&self.green_max.to_be_bytes());
+        out.extend_from_slice(&self.blue_max.to_be_bytes());
+        out.extend_from_slice(&[self.red_shift, self.green_shift, self.blue_shift, 0, 0, 0]);
+    }
+
+    fn bytes_per_pixel(&self) -> usize {
+        (self.bits_per_pixel as usize / 8).max(1)
+    }
+
+    fn pixel(&self, [r, g, b]: [u8; 3]) -> u32 {
+        let channel = |value: u8, max: u16, shift: u8| {
+            ((value as u32 * max as u32 + 127) / 255)
+                .checked_shl(shift as u32)
+                .unwrap_or(0)
+        };
+
+        channel(r, self.red_max, self.red_shift)
+            | channel(g, self.green_max, self.green_shift)
+            | channel(b, self.blue_max, self.blue_shift)
+    }
+
+    fn write_pixel(&self, value: u32, out: &mut Vec<u8>) {
+        match (self.bytes_per_pixel(), self.big_endian) {
+            (1, _) => out.push(value as u8),
+            (2, false) => out.extend_from_slice(&(value as u16).to_le_bytes()),
+            (2, true) => out.extend_from_slice(&(value as u16).to_be_bytes()),
+            (_, false) => out.extend_from_slice(&value.to_le_bytes()),
+            (_, true) => out.extend_from_slice(&value.to_be_bytes()),
+        }
+    }
+
+    /// Writes a ZRLE compressed pixel, which drops the unused byte of 32-bit pixels whenever
+    /// the colour channels fit into the other three.
+    fn write_cpixel(&self, value: u32, out: &mut Vec<u8>) {
+        let used = self.pixel([255, 255, 255]);
+
+        if self.bits_per_pixel != 32 || self.depth > 24 {
+            self.write_pixel(value, out);
+        } else if used & 0xff00_0000 == 0 {
+            if self.big_endian {
+                out.extend_from_slice(&value.to_be_bytes()[1..]);
+            } else {
+                out.extend_from_slice(&value.to_le_bytes()[..3]);
+            }
+        } else if used & 0xff == 0 {
+            if self.big_endian {
+                out.extend_from_slice(&value.to_be_bytes()[..3]);
+            } else {
+                out.extend_from_slice(&value.to_le_bytes()[1..]);
+            }
+        } else {
+            self.write_pixel(value, out);
+        }
+    }
+}
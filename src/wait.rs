@@ -0,0 +1,283 @@
+use super::{Capturer, CoordinateSpace, Error, Rect, RgbImage};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Waits for a display, or a region of it, to change or to settle down, by capturing it
+/// over and over.
+///
+/// Captures are compared pixel for pixel, so anything that keeps changing on its own, like a
+/// blinking caret or a clock, has to be left out of the region or covered by one of the
+/// [`Capturer::redactions`].
+///
+/// ```no_run
+/// use captis::{init_capturer, Waiter};
+/// use std::time::Duration;
+///
+/// let capturer = init_capturer().expect("Couldn't Initialize Capturer");
+///
+/// // Click something, then wait for the animation it starts to finish.
+/// let settled = Waiter::new(&capturer, 0)
+///     .wait_until_stable(Duration::from_millis(500), Duration::from_secs(10))
+///     .expect("Couldn't Capture Screen")
+///     .expect("Still Animating");
+/// ```
+pub struct Waiter<'a, C: Capturer + ?Sized> {
+    capturer: &'a C,
+    index: usize,
+    region: Option<(Rect, CoordinateSpace)>,
+    interval: Duration,
+}
+
+impl<'a, C: Capturer + ?Sized> Waiter<'a, C> {
+    /// Watches the whole display at `index`, capturing it every 50 milliseconds.
+    pub fn new(capturer: &'a C, index: usize) -> Self {
+        Self {
+            capturer,
+            index,
+            region: None,
+            interval: Duration::from_millis(50),
+        }
+    }
+
+    /// Only watches `region` of the display, see [`Capturer::capture_region`].
+    pub fn region(mut self, region: Rect, space: CoordinateSpace) -> Self {
+        self.region = Some((region, space));
+        self
+    }
+
+    /// Sets how long to wait between captures.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Waits until the region looks different from when this was called and returns how it
+    /// looks now, or `None` if it didn't change within `timeout`.
+    pub fn wait_until_changed(&self, timeout: Duration) -> Result<Option<RgbImage>, Error> {
+        self.wait(Until::Changed, timeout)
+    }
+
+    /// Waits until the region hasn't changed for `quiet_period` and returns how it looks, or
+    /// `None` if it didn't settle down within `timeout`.
+    pub fn wait_until_stable(
+        &self,
+        quiet_period: Duration,
+        timeout: Duration,
+    ) -> Result<Option<RgbImage>, Error> {
+        self.wait(Until::Stable(quiet_period), timeout)
+    }
+
+    /// Like [`Waiter::wait_until_changed`], but doesn't block while waiting between
+    /// captures.
+    ///
+    /// The future works with any executor, it starts one thread that wakes it whenever the
+    /// next capture is due.
+    ///
+    /// **The captures themselves are taken inside `poll`**, which blocks the thread polling
+    /// it for as long as a capture takes, from a few milliseconds for a small region to much
+    /// longer for a large display. On executors with few threads, run
+    /// [`Waiter::wait_until_changed`] on their pool for blocking work instead.
+    pub fn changed(&self, timeout: Duration) -> WaitFuture<'_, 'a, C> {
+        WaitFuture::new(self, Until::Changed, timeout)
+    }
+
+    /// Like [`Waiter::wait_until_stable`], but doesn't block while waiting between
+    /// captures. The captures still block the thread polling it, see [`Waiter::changed`].
+    pub fn stable(&self, quiet_period: Duration, timeout: Duration) -> WaitFuture<'_, 'a, C> {
+        WaitFuture::new(self, Until::Stable(quiet_period), timeout)
+    }
+
+    fn wait(&self, until: Until, timeout: Duration) -> Result<Option<RgbImage>, Error> {
+        let mut progress = Progress::new(until, timeout);
+
+        loop {
+            if let Some(done) = progress.observe(self.capture()?) {
+                return Ok(done);
+            }
+
+            thread::sleep(
+                progress
+                    .next_capture(self.interval)
+                    .saturating_duration_since(Instant::now()),
+            );
+        }
+    }
+
+    fn capture(&self) -> Result<RgbImage, Error> {
+        match self.region {
+            Some((region, space)) => self.capturer.capture_region(self.index, region, space),
+            None => self.capturer.capture(self.index),
+        }
+    }
+}
+
+/// Waits for a region to change or settle down without blocking between captures, see
+/// [`Waiter::changed`] and [`Waiter::stable`]. Polling it blocks while it captures.
+pub struct WaitFuture<'w, 'a, C: Capturer + ?Sized> {
+    waiter: &'w Waiter<'a, C>,
+    progress: Progress,
+    /// When the next capture is due, there's one right away on the first poll.
+    next: Instant,
+    /// Hands the thread that wakes the future when to do it and whom, started on the first
+    /// poll that has to wait. It stops once the future is dropped.
+    timer: Option<Sender<(Instant, Waker)>>,
+}
+
+impl<'w, 'a, C: Capturer + ?Sized> WaitFuture<'w, 'a, C> {
+    fn new(waiter: &'w Waiter<'a, C>, until: Until, timeout: Duration) -> Self {
+        Self {
+            waiter,
+            progress: Progress::new(until, timeout),
+            next: Instant::now(),
+            timer: None,
+        }
+    }
+}
+
+impl<'w, 'a, C: Capturer + ?Sized> Future for WaitFuture<'w, 'a, C> {
+    type Output = Result<Option<RgbImage>, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if Instant::now() >= self.next {
+            let image = match self.waiter.capture() {
+                Ok(image) => image,
+                Err(error) => return Poll::Ready(Err(error)),
+            };
+
+            if let Some(done) = self.progress.observe(image) {
+                return Poll::Ready(Ok(done));
+            }
+
+            self.next = self.progress.next_capture(self.waiter.interval);
+        }
+
+        let wake_up = (self.next, cx.waker().clone());
+
+        // A new thread is only needed if there wasn't one yet or it somehow went away.
+        let wake_up = match &self.timer {
+            Some(timer) => timer.send(wake_up).err().map(|error| error.0),
+            None => Some(wake_up),
+        };
+
+        if let Some(wake_up) = wake_up {
+            let (sender, receiver) = mpsc::channel();
+            sender.send(wake_up).ok();
+
+            thread::spawn(move || wake_when_due(receiver));
+            self.timer = Some(sender);
+        }
+
+        Poll::Pending
+    }
+}
+
+/// Wakes the last waker it was given once its time comes, until the sending side is gone.
+fn wake_when_due(receiver: Receiver<(Instant, Waker)>) {
+    let mut pending: Option<(Instant, Waker)> = None;
+
+    loop {
+        let received = match &pending {
+            Some((at, _)) => {
+                match receiver.recv_timeout(at.saturating_duration_since(Instant::now())) {
+                    Ok(wake_up) => Some(wake_up),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            None => match receiver.recv() {
+                Ok(wake_up) => Some(wake_up),
+                Err(_) => return,
+            },
+        };
+
+        match received {
+            Some(wake_up) => pending = Some(wake_up),
+            None => {
+                if let Some((_, waker)) = pending.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Until {
+    Changed,
+    Stable(Duration),
+}
+
+/// What a wait has seen so far.
+struct Progress {
+    until: Until,
+    deadline: Instant,
+    /// The first capture when waiting for a change, otherwise the last one.
+    previous: Option<RgbImage>,
+    /// When the captures last looked different.
+    changed_at: Instant,
+}
+
+impl Progress {
+    fn new(until: Until, timeout: Duration) -> Self {
+        let now = Instant::now();
+
+        Self {
+            until,
+            deadline: now + timeout,
+            previous: None,
+            changed_at: now,
+        }
+    }
+
+    /// Takes the next capture into account and returns whether the wait is over, with the
+    /// capture it was waiting for if it didn't time out.
+    fn observe(&mut self, image: RgbImage) -> Option<Option<RgbImage>> {
+        let now = Instant::now();
+
+        let changed = match &self.previous {
+            Some(previous) => *previous != image,
+            None => {
+                self.previous = Some(image);
+                self.changed_at = now;
+
+                return self.timed_out(now);
+            }
+        };
+
+        match self.until {
+            Until::Changed if changed => return Some(Some(image)),
+            Until::Changed => {}
+            Until::Stable(quiet_period) => {
+                if changed {
+                    self.changed_at = now;
+                } else if now - self.changed_at >= quiet_period {
+                    return Some(Some(image));
+                }
+
+                self.previous = Some(image);
+            }
+        }
+
+        self.timed_out(now)
+    }
+
+    fn timed_out(&self, now: Instant) -> Option<Option<RgbImage>> {
+        if now >= self.deadline {
+            Some(None)
+        } else {
+            None
+        }
+    }
+
+    /// When to capture next, no later than the deadline so the last look is taken right
+    /// before giving up.
+    fn next_capture(&self, interval: Duration) -> Instant {
+        (Instant::now() + interval).min(self.deadline)
+    }
+}
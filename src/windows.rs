@@ -1,20 +1,30 @@
 #![cfg(target_os = "windows")]
 
-use super::{Capturer, Display};
-use image::{Rgb, RgbImage};
-use std::{error::Error, fmt, marker::PhantomData, mem, ptr};
+use super::{
+    convert::{bgrx_to_rgb, bgrx_to_rgb_scaled},
+    Backend, Capturer, Display, Exclusions, Frame, Redactions, Scale, ScaleFilter,
+};
+use image::RgbImage;
+use std::{
+    error::Error,
+    fmt, mem, ptr,
+    sync::{Mutex, PoisonError},
+    time::SystemTime,
+};
 use winapi::{
     shared::{
         minwindef::{BOOL, LPARAM, TRUE},
-        windef::{HDC, HMONITOR, LPRECT, RECT},
+        windef::{HDC, HMONITOR, LPRECT, POINT, RECT},
+        winerror::S_OK,
     },
     um::{
+        shellscalingapi::{GetDpiForMonitor, MDT_EFFECTIVE_DPI},
         wingdi::{
             BitBlt, CreateCompatibleDC, CreateDIBSection, DeleteDC, DeleteObject, GetDeviceCaps,
             SelectObject, BITMAPINFO, BITMAPINFOHEADER, BITSPIXEL, BI_RGB, CAPTUREBLT,
             DIB_RGB_COLORS, RGBQUAD, SRCCOPY,
         },
-        winuser::{EnumDisplayMonitors, GetWindowDC},
+        winuser::{EnumDisplayMonitors, GetCursorPos, GetWindowDC},
     },
 };
 
@@ -30,6 +40,7 @@ pub enum WindowsError {
     SelectObjectFailed,
     BitBltFailed,
     DeleteObjectFailed,
+    ExcludingWindowsUnsupported,
 }
 
 impl fmt::Display for WindowsError {
@@ -41,15 +52,26 @@ impl fmt::Display for WindowsError {
 impl Error for WindowsError {}
 
 pub(crate) struct WindowsCapturer {
-    h_dc: HDC,
-    h_compatible_dc: HDC,
+    /// Locked for the whole capture, every capture selects its bitmap into the same
+    /// compatible DC.
+    dcs: Mutex<DeviceContexts>,
     displays: Vec<Display>,
     primary_display_index: usize,
     bits_per_pixel: u16,
-    _phantom_data: PhantomData<*const ()>,
+    redactions: Redactions,
+}
+
+/// The DC of the screen and the one compatible with it that the displays are copied into.
+struct DeviceContexts {
+    h_dc: HDC,
+    h_compatible_dc: HDC,
 }
 
-impl Drop for WindowsCapturer {
+// The DCs can be used from any thread as long as it's only one at a time, which the mutex in
+// `WindowsCapturer` makes sure of.
+unsafe impl Send for DeviceContexts {}
+
+impl Drop for DeviceContexts {
     fn drop(&mut self) {
         unsafe {
             DeleteDC(self.h_compatible_dc);
@@ -59,12 +81,34 @@ impl Drop for WindowsCapturer {
 }
 
 impl Capturer for WindowsCapturer {
+    fn exclude_windows(&mut self, exclusions: Exclusions) -> Result<(), WindowsError> {
+        if exclusions.is_empty() {
+            Ok(())
+        } else {
+            Err(WindowsError::ExcludingWindowsUnsupported)
+        }
+    }
+
+    fn primary_display(&self) -> usize {
+        self.primary_display_index
+    }
+
+    fn redactions(&self) -> &Redactions {
+        &self.redactions
+    }
+
     fn displays(&self) -> &[Display] {
         &self.displays
     }
 
     fn refresh_displays(&mut self) -> Result<(), WindowsError> {
-        let (primary_display_index, displays) = get_displays(self.h_dc)?;
+        let h_dc = self
+            .dcs
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .h_dc;
+
+        let (primary_display_index, displays) = get_displays(h_dc)?;
         self.primary_display_index = primary_display_index;
         self.displays = displays;
         Ok(())
@@ -83,17 +127,80 @@ impl Capturer for WindowsCapturer {
     }
 
     fn capture(&self, index: usize) -> Result<RgbImage, WindowsError> {
+        let mut image = self.capture_with(index, bgrx_to_rgb)?;
+        self.redact(&mut image, index);
+        Ok(image)
+    }
+
+    fn capture_frame(&self, index: usize) -> Result<Frame, WindowsError> {
+        let started_at = SystemTime::now();
+
+        let mut point = POINT { x: 0, y: 0 };
+
+        let cursor = match unsafe { GetCursorPos(&mut point) } {
+            0 => None,
+            _ => Some((point.x, point.y)),
+        };
+
+        let image = self.capture(index)?;
+
+        Ok(Frame {
+            image,
+            index,
+            display: self.displays[index],
+            started_at,
+            finished_at: SystemTime::now(),
+            backend: Backend::Gdi,
+            cursor,
+        })
+    }
+
+    fn capture_scaled(
+        &self,
+        index: usize,
+        scale: Scale,
+        filter: ScaleFilter,
+    ) -> Result<RgbImage, WindowsError> {
+        let mut image = self.capture_with(index, |data, stride, width, height| {
+            bgrx_to_rgb_scaled(data, stride, width, height, scale, filter)
+        })?;
+        self.redact(&mut image, index);
+        Ok(image)
+    }
+}
+
+impl WindowsCapturer {
+    /// Hides the masks in `image`, a capture of the display at `index`.
+    fn redact(&self, image: &mut RgbImage, index: usize) {
+        let bounds = self.displays[index].bounds();
+
+        // Only rectangles can be masked here, so there's nothing to look up.
+        self.redactions
+            .apply(image, bounds, |_| Ok::<_, ()>(None))
+            .ok();
+    }
+
+    /// Copies the display at `index` into a bitmap and hands its BGRX pixels, the length of
+    /// a row in bytes, and its width and height to `decode`.
+    fn capture_with<T>(
+        &self,
+        index: usize,
+        decode: impl FnOnce(&[u8], usize, u32, u32) -> T,
+    ) -> Result<T, WindowsError> {
         use WindowsError::*;
 
-        let h_dc = self.h_dc;
+        let dcs = self.dcs.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let h_dc = dcs.h_dc;
 
-        let h_compatible_dc = self.h_compatible_dc;
+        let h_compatible_dc = dcs.h_compatible_dc;
 
         let Display {
             width,
             height,
             top,
             left,
+            ..
         } = *self.displays.get(index).ok_or(CouldntFindDisplay)?;
 
         unsafe {
@@ -144,26 +251,17 @@ impl Capturer for WindowsCapturer {
                 return Err(BitBltFailed);
             }
 
-            let slice = std::slice::from_raw_parts(data as *mut RGBQUAD, (width * height) as usize);
-
-            let (width, height) = (width as u32, height as u32);
-
-            let mut image: RgbImage = RgbImage::new(width, height);
-
-            let mut i = 0;
+            let slice = std::slice::from_raw_parts(
+                data,
+                (width * height) as usize * mem::size_of::<RGBQUAD>(),
+            );
 
-            for y in 0..height {
-                for x in 0..width {
-                    let RGBQUAD {
-                        rgbBlue,
-                        rgbGreen,
-                        rgbRed,
-                        ..
-                    } = slice[i];
-                    image.put_pixel(x, y, Rgb([rgbRed, rgbGreen, rgbBlue]));
-                    i += 1;
-                }
-            }
+            let image = decode(
+                slice,
+                width as usize * mem::size_of::<RGBQUAD>(),
+                width as u32,
+                height as u32,
+            );
 
             if DeleteObject(compatible_bitmap as _) == 0 {
                 return Err(DeleteObjectFailed);
@@ -172,9 +270,7 @@ impl Capturer for WindowsCapturer {
             Ok(image)
         }
     }
-}
 
-impl WindowsCapturer {
     pub(crate) fn new() -> Result<Self, WindowsError> {
         use WindowsError::*;
 
@@ -200,12 +296,14 @@ impl WindowsCapturer {
             }
 
             Ok(Self {
-                h_dc,
-                h_compatible_dc,
+                dcs: Mutex::new(DeviceContexts {
+                    h_dc,
+                    h_compatible_dc,
+                }),
                 displays,
                 primary_display_index,
                 bits_per_pixel,
-                _phantom_data: PhantomData,
+                redactions: Redactions::new(),
             })
         }
     }
@@ -247,6 +345,7 @@ impl From<RECT> for Display {
             left: rect.left,
             width: (rect.right - rect.left).abs(),
             height: (rect.bottom - rect.top).abs(),
+            scale_factor: 1.0,
         }
     }
 }
@@ -254,12 +353,20 @@ impl From<RECT> for Display {
 /// This function will give us the data we need to capture each display
 /// separately through knowing each display's coordinates.
 unsafe extern "system" fn enum_display_callback(
-    _h_monitor: HMONITOR,
+    h_monitor: HMONITOR,
     _h_dc: HDC,
     lp_rect: LPRECT,
     l_param: LPARAM,
 ) -> BOOL {
     let displays = &mut *(l_param as *mut Vec<Display>);
-    displays.push((*lp_rect).into());
+    let mut display: Display = (*lp_rect).into();
+
+    let (mut dpi_x, mut dpi_y) = (0, 0);
+
+    if GetDpiForMonitor(h_monitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y) == S_OK && dpi_x > 0 {
+        display.scale_factor = dpi_x as f64 / 96.0;
+    }
+
+    displays.push(display);
     TRUE
 }
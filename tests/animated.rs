@@ -0,0 +1,77 @@
+//! Animated PNG and GIF recordings, decoded again with `image`.
+
+use captis::{Recorder, RgbImage, VideoFormat};
+use image::{
+    codecs::{gif::GifDecoder, png::PngDecoder},
+    AnimationDecoder, Frame, Rgb,
+};
+use std::{env, fs, io::Cursor, path::PathBuf, process, time::Duration};
+
+fn path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("captis-animated-{}-{}", process::id(), name))
+}
+
+/// Few enough colours that GIF keeps them exactly, and a block that moves a little between
+/// frames so only part of the image changes.
+fn frame(step: u32) -> RgbImage {
+    RgbImage::from_fn(96, 64, |x, y| {
+        if (step * 20..step * 20 + 16).contains(&x) && (8..24).contains(&y) {
+            Rgb([200, 30, 30])
+        } else if y >= 48 {
+            Rgb([30, 30, 200])
+        } else {
+            Rgb([240, 240, 240])
+        }
+    })
+}
+
+/// Frames every 100ms, where the second one stays on screen for 300ms.
+fn record(format: VideoFormat, name: &str) -> Vec<u8> {
+    let path = path(name);
+    let mut recording = Recorder::new(format).frame_rate(10).create(&path).unwrap();
+
+    for (step, millis) in [(0, 0), (1, 100), (1, 200), (1, 300), (2, 400)] {
+        assert!(recording
+            .write_frame(&frame(step), Duration::from_millis(millis))
+            .unwrap());
+    }
+
+    recording.finish().unwrap();
+
+    let data = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    data
+}
+
+fn check(frames: Vec<Frame>) {
+    let delays: Vec<_> = frames
+        .iter()
+        .map(|frame| Duration::from(frame.delay()))
+        .collect();
+
+    assert_eq!(delays, [100, 300, 100].map(Duration::from_millis));
+
+    for (step, frame) in frames.into_iter().enumerate() {
+        let rgb = image::DynamicImage::ImageRgba8(frame.into_buffer()).to_rgb8();
+        assert_eq!(rgb, self::frame(step as u32), "frame {}", step);
+    }
+}
+
+#[test]
+fn apng_round_trips() {
+    let data = record(VideoFormat::Apng, "round-trip.png");
+
+    let decoder = PngDecoder::new(Cursor::new(data)).unwrap();
+    assert!(decoder.is_apng());
+
+    check(decoder.apng().into_frames().collect_frames().unwrap());
+}
+
+#[test]
+fn gif_round_trips() {
+    let data = record(VideoFormat::Gif, "round-trip.gif");
+
+    let decoder = GifDecoder::new(Cursor::new(data)).unwrap();
+
+    check(decoder.into_frames().collect_frames().unwrap());
+}
@@ -0,0 +1,114 @@
+//! Colour statistics of generated images, and probing a mock desktop of two displays.
+
+use captis::{Capturer, ColorStats, Display, Layout, MockCapturer, Rect, Rgb, RgbImage};
+
+#[test]
+fn stats() {
+    // Three quarters red with a little noise, one quarter blue.
+    let image = RgbImage::from_fn(8, 8, |x, y| {
+        if x < 6 {
+            Rgb([200 + ((x + y) % 2) as u8 * 4, 10, 10])
+        } else {
+            Rgb([0, 0, 250])
+        }
+    });
+
+    let stats = ColorStats::of(&image);
+
+    assert_eq!(stats.pixels(), 64);
+    assert_eq!(stats.average(), Some(Rgb([152, 8, 70])));
+
+    assert_eq!(stats.histograms()[0][200], 24);
+    assert_eq!(stats.histograms()[0][204], 24);
+    assert_eq!(stats.histograms()[0][0], 16);
+    assert_eq!(stats.histograms()[2][250], 16);
+
+    assert_eq!(stats.dominant(), Some(Rgb([202, 10, 10])));
+    assert_eq!(
+        stats.dominant_colors(5),
+        vec![(Rgb([202, 10, 10]), 0.75), (Rgb([0, 0, 250]), 0.25)]
+    );
+
+    let empty = ColorStats::of(&RgbImage::new(0, 0));
+
+    assert_eq!(empty.average(), None);
+    assert_eq!(empty.dominant(), None);
+}
+
+fn desktop() -> MockCapturer {
+    let capturer = MockCapturer::new(Layout {
+        displays: vec![
+            Display::from_pixels(0, 0, 100, 50),
+            Display::from_pixels(100, 0, 200, 100).with_scale_factor(2.0),
+        ],
+        primary: 0,
+    });
+
+    capturer.set_frame(
+        0,
+        RgbImage::from_fn(100, 50, |x, y| Rgb([x as u8, y as u8, 0])),
+    );
+    capturer.set_frame(
+        1,
+        RgbImage::from_fn(200, 100, |x, y| Rgb([x as u8, y as u8, 255])),
+    );
+
+    capturer
+}
+
+#[test]
+fn pixels_in_desktop_coordinates() {
+    let capturer = desktop();
+
+    assert_eq!(capturer.pixel_at(10, 20).unwrap(), Some(Rgb([10, 20, 0])));
+    assert_eq!(capturer.pixel_at(99, 49).unwrap(), Some(Rgb([99, 49, 0])));
+
+    // Positions are physical pixels like the frames, the scale factor doesn't come into it.
+    assert_eq!(
+        capturer.pixel_at(110, 20).unwrap(),
+        Some(Rgb([10, 20, 255]))
+    );
+    assert_eq!(
+        capturer.pixel_at(299, 99).unwrap(),
+        Some(Rgb([199, 99, 255]))
+    );
+
+    assert_eq!(capturer.pixel_at(10, 60).unwrap(), None);
+    assert_eq!(capturer.pixel_at(-1, 0).unwrap(), None);
+}
+
+#[test]
+fn areas_in_desktop_coordinates() {
+    let capturer = desktop();
+
+    let (index, image) = capturer
+        .capture_at(Rect::new(90, 40, 20, 20))
+        .unwrap()
+        .unwrap();
+
+    // Cut off at the edge of the display the corner is on.
+    assert_eq!(index, 0);
+    assert_eq!(image.dimensions(), (10, 10));
+
+    let stats = capturer
+        .color_stats_at(Rect::new(100, 0, 10, 10))
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(stats.pixels(), 100);
+    assert_eq!(stats.average(), Some(Rgb([5, 5, 255])));
+
+    // Past the first half of the display with two physical pixels per logical one.
+    let stats = capturer
+        .color_stats_at(Rect::new(250, 50, 40, 40))
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(stats.pixels(), 1600);
+    assert_eq!(stats.average(), Some(Rgb([170, 70, 255])));
+
+    assert!(capturer
+        .color_stats_at(Rect::new(400, 0, 10, 10))
+        .unwrap()
+        .is_none());
+}
@@ -0,0 +1,201 @@
+//! Encoding frames and BGRX pixels, and decoding them again.
+
+use captis::{
+    encode_bgrx, Backend, Capturer, Display, Frame, ImageFormat, Layout, Mask, MockCapturer, Rect,
+    RedactStyle, RgbImage,
+};
+use image::Rgb;
+use std::time::SystemTime;
+
+/// Runs, gradients and noise, so every kind of QOI chunk gets written.
+fn image(width: u32, height: u32) -> RgbImage {
+    RgbImage::from_fn(width, height, |x, y| {
+        if y < height / 3 {
+            Rgb([30, 60, 90])
+        } else if y < height * 2 / 3 {
+            Rgb([x as u8, (x + y) as u8, (y * 2) as u8])
+        } else {
+            let noise = x.wrapping_mul(2_654_435_761) ^ y.wrapping_mul(40_503);
+            Rgb([noise as u8, (noise >> 8) as u8, (noise >> 16) as u8])
+        }
+    })
+}
+
+fn frame(image: RgbImage) -> Frame {
+    let (width, height) = image.dimensions();
+
+    Frame {
+        image,
+        index: 0,
+        display: Display::from_pixels(0, 0, width as u16, height as u16),
+        started_at: SystemTime::now(),
+        finished_at: SystemTime::now(),
+        backend: Backend::Mock,
+        cursor: None,
+    }
+}
+
+/// The pixels of `image` as BGRX rows which are `padding` bytes longer than needed.
+fn bgrx(image: &RgbImage, padding: usize) -> (Vec<u8>, usize) {
+    let stride = image.width() as usize * 4 + padding;
+    let mut data = vec![0xaa; stride * image.height() as usize];
+
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let at = y as usize * stride + x as usize * 4;
+        data[at..at + 4].copy_from_slice(&[pixel[2], pixel[1], pixel[0], 0]);
+    }
+
+    (data, stride)
+}
+
+fn decode(encoded: &[u8], format: ImageFormat) -> RgbImage {
+    match format {
+        ImageFormat::WebP => {
+            let mut decoder = image_webp::WebPDecoder::new(std::io::Cursor::new(encoded)).unwrap();
+            let (width, height) = decoder.dimensions();
+            let mut pixels = vec![0; decoder.output_buffer_size().unwrap()];
+            decoder.read_image(&mut pixels).unwrap();
+
+            if decoder.has_alpha() {
+                image::RgbaImage::from_raw(width, height, pixels)
+                    .map(|rgba| image::DynamicImage::ImageRgba8(rgba).to_rgb8())
+                    .unwrap()
+            } else {
+                RgbImage::from_raw(width, height, pixels).unwrap()
+            }
+        }
+        _ => image::load_from_memory(encoded).unwrap().to_rgb8(),
+    }
+}
+
+fn max_difference(a: &RgbImage, b: &RgbImage) -> u8 {
+    assert_eq!(a.dimensions(), b.dimensions());
+
+    a.as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(&a, &b)| a.max(b) - a.min(b))
+        .max()
+        .unwrap_or(0)
+}
+
+const LOSSLESS: [ImageFormat; 3] = [ImageFormat::Png, ImageFormat::WebP, ImageFormat::Qoi];
+
+#[test]
+fn lossless_round_trips() {
+    for (width, height) in [(64, 48), (37, 23), (1, 1), (300, 2)] {
+        let image = image(width, height);
+
+        for format in LOSSLESS {
+            let encoded = frame(image.clone()).encode(format, 90).unwrap();
+            assert_eq!(decode(&encoded, format), image, "{:?}", format);
+
+            for padding in [0, 12] {
+                let (data, stride) = bgrx(&image, padding);
+                let mut encoded = vec![];
+
+                encode_bgrx(&data, stride, width, height, &mut encoded, format, 90).unwrap();
+                assert_eq!(decode(&encoded, format), image, "{:?} from BGRX", format);
+            }
+        }
+    }
+}
+
+#[test]
+fn jpeg_round_trips() {
+    // Smooth, so the quality and not the noise decides how close it comes.
+    let image = RgbImage::from_fn(64, 48, |x, y| Rgb([x as u8 * 4, y as u8 * 5, 128]));
+
+    let best = decode(
+        &frame(image.clone()).encode(ImageFormat::Jpeg, 100).unwrap(),
+        ImageFormat::Jpeg,
+    );
+    assert!(max_difference(&best, &image) <= 8);
+
+    let (data, stride) = bgrx(&image, 4);
+    let mut encoded = vec![];
+    encode_bgrx(&data, stride, 64, 48, &mut encoded, ImageFormat::Jpeg, 100).unwrap();
+    assert_eq!(decode(&encoded, ImageFormat::Jpeg), best);
+
+    let worst = frame(image).encode(ImageFormat::Jpeg, 1).unwrap();
+    assert!(worst.len() < encoded.len());
+}
+
+#[test]
+fn qoi_runs_cross_rows() {
+    // Longer than the 62 pixels a single run chunk holds, and over several rows.
+    let image = RgbImage::from_pixel(50, 7, Rgb([0, 0, 0]));
+    let (data, stride) = bgrx(&image, 0);
+
+    let mut encoded = vec![];
+    encode_bgrx(&data, stride, 50, 7, &mut encoded, ImageFormat::Qoi, 0).unwrap();
+
+    assert_eq!(decode(&encoded, ImageFormat::Qoi), image);
+    assert_eq!(encoded.len(), 14 + 6 + 8);
+}
+
+#[test]
+fn qoi_index_starts_empty() {
+    // Black hashes to the slot of the index that starts out as transparent black, so it must
+    // be written out in full instead of referring to that slot.
+    let image = RgbImage::from_fn(2, 1, |x, _| {
+        if x == 0 {
+            Rgb([100, 50, 25])
+        } else {
+            Rgb([0, 0, 0])
+        }
+    });
+    let (data, stride) = bgrx(&image, 0);
+
+    let mut encoded = vec![];
+    encode_bgrx(&data, stride, 2, 1, &mut encoded, ImageFormat::Qoi, 0).unwrap();
+
+    assert_eq!(
+        encoded[14..],
+        [0xfe, 100, 50, 25, 0xfe, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]
+    );
+}
+
+#[test]
+fn captures_are_encoded_with_their_masks() {
+    let capturer = MockCapturer::new(Layout {
+        displays: vec![Display::from_pixels(0, 0, 64, 48)],
+        primary: 0,
+    });
+    capturer.set_frame(0, image(64, 48));
+
+    capturer
+        .redactions()
+        .add(Mask::Rect(Rect::new(8, 8, 16, 16)), RedactStyle::Black);
+
+    let expected = capturer.capture(0).unwrap();
+
+    for format in LOSSLESS {
+        let encoded = capturer.capture_encoded(0, format, 90).unwrap();
+        assert_eq!(decode(&encoded, format), expected, "{:?}", format);
+    }
+}
+
+#[test]
+fn saves_by_extension() {
+    let dir = std::env::temp_dir().join(format!("captis-encode-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let frame = frame(image(20, 10));
+
+    for (name, format) in [
+        ("a.png", ImageFormat::Png),
+        ("b.QOI", ImageFormat::Qoi),
+        ("c.webp", ImageFormat::WebP),
+    ] {
+        frame.save(dir.join(name)).unwrap();
+        assert_eq!(
+            decode(&std::fs::read(dir.join(name)).unwrap(), format),
+            frame.image
+        );
+    }
+
+    assert!(frame.save(dir.join("d.bmp")).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,137 @@
+//! Template matching on generated images, and across the displays of a mock capturer.
+
+use captis::{find_template, Capturer, Display, Layout, MockCapturer, Rect, RgbImage};
+use image::{imageops, Rgb};
+
+/// A light background with a few differently shaped and coloured blocks on it, like the
+/// buttons and icons of a window.
+fn scene(width: u32, height: u32) -> RgbImage {
+    RgbImage::from_fn(width, height, |x, y| {
+        let in_box = |left: u32, top: u32, w: u32, h: u32| {
+            x >= left && x < left + w && y >= top && y < top + h
+        };
+
+        if in_box(40, 30, 48, 24) {
+            // A button with a label of dashes.
+            if y % 6 < 2 && x % 5 < 3 && in_box(46, 38, 36, 8) {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([30, 90, 200])
+            }
+        } else if in_box(200, 120, 32, 32) {
+            // An icon with a diagonal.
+            if (x - 200) == (y - 120) || (x - 200) + 1 == (y - 120) {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([220, 60, 40])
+            }
+        } else {
+            let value = 230 - ((x + y) % 7) as u8;
+            Rgb([value, value, value])
+        }
+    })
+}
+
+fn crop(image: &RgbImage, area: Rect) -> RgbImage {
+    imageops::crop_imm(image, area.x as u32, area.y as u32, area.width, area.height).to_image()
+}
+
+#[test]
+fn finds_exact_copies() {
+    let image = scene(320, 200);
+
+    for area in [Rect::new(40, 30, 48, 24), Rect::new(196, 116, 40, 40)] {
+        let matches = find_template(&image, &crop(&image, area), 0.9);
+
+        assert_eq!(matches.len(), 1, "{:?}", matches);
+        assert_eq!(matches[0].0, area);
+        assert!(matches[0].1 > 0.999);
+    }
+}
+
+#[test]
+fn finds_every_copy() {
+    let mut image = scene(320, 200);
+    let button = crop(&image, Rect::new(40, 30, 48, 24));
+
+    imageops::replace(&mut image, &button, 120, 150);
+
+    let mut found: Vec<Rect> = find_template(&image, &button, 0.95)
+        .into_iter()
+        .map(|(area, _)| area)
+        .collect();
+    found.sort_by_key(|area| (area.x, area.y));
+
+    assert_eq!(
+        found,
+        vec![Rect::new(40, 30, 48, 24), Rect::new(120, 150, 48, 24)]
+    );
+}
+
+#[test]
+fn tolerates_brightness_changes() {
+    let image = scene(320, 200);
+    let area = Rect::new(40, 30, 48, 24);
+
+    let mut template = crop(&image, area);
+    template
+        .pixels_mut()
+        .for_each(|pixel| pixel.0 = pixel.0.map(|value| value.saturating_sub(20)));
+
+    let matches = find_template(&image, &template, 0.9);
+
+    assert_eq!(matches.first().map(|(area, _)| *area), Some(area));
+}
+
+#[test]
+fn missing_templates() {
+    let image = scene(320, 200);
+
+    let other = RgbImage::from_fn(40, 40, |x, y| {
+        if (x / 5 + y / 5) % 2 == 0 {
+            Rgb([0, 200, 0])
+        } else {
+            Rgb([200, 0, 200])
+        }
+    });
+
+    assert!(find_template(&image, &other, 0.9).is_empty());
+    assert!(find_template(&image, &RgbImage::new(400, 10), 0.9).is_empty());
+    assert!(find_template(&image, &RgbImage::new(0, 0), 0.9).is_empty());
+}
+
+#[test]
+fn matches_are_in_desktop_coordinates() {
+    let capturer = MockCapturer::new(Layout {
+        displays: vec![
+            Display::from_pixels(0, 0, 320, 200),
+            Display::from_pixels(320, 0, 640, 400).with_scale_factor(2.0),
+        ],
+        primary: 0,
+    });
+
+    let icon = Rect::new(196, 116, 40, 40);
+    let template = crop(&scene(320, 200), icon);
+
+    let mut hidpi = RgbImage::from_pixel(640, 400, Rgb([230, 230, 230]));
+    imageops::replace(&mut hidpi, &template, 100, 60);
+
+    capturer.set_frame(0, scene(320, 200));
+    capturer.set_frame(1, hidpi);
+
+    let matches = capturer.find_on_screen(&template, 0.95).unwrap();
+
+    assert_eq!(matches.len(), 2, "{:?}", matches);
+
+    let mut found: Vec<_> = matches
+        .iter()
+        .map(|found| (found.display, found.bounds))
+        .collect();
+    found.sort_by_key(|&(display, _)| display);
+
+    // Positions are physical pixels like the frames, the scale factor doesn't come into it.
+    assert_eq!(
+        found,
+        vec![(0, icon), (1, Rect::new(320 + 100, 60, 40, 40))]
+    );
+}
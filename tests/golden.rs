@@ -0,0 +1,179 @@
+//! Golden image checks against the mock capturer, in a fresh directory for every test.
+
+use captis::{
+    Capturer, CoordinateSpace, Display, Golden, GoldenError, Layout, Mask, MockCapturer, Rect,
+    RedactStyle, RgbImage,
+};
+use image::Rgb;
+use std::{env, fs, path::PathBuf, process};
+
+fn golden_dir(test: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("captis-golden-{}-{}", process::id(), test));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+fn capturer() -> MockCapturer {
+    MockCapturer::new(Layout {
+        displays: vec![Display::from_pixels(0, 0, 160, 120)],
+        primary: 0,
+    })
+}
+
+#[test]
+fn missing_then_updated_then_matching() {
+    let dir = golden_dir("update");
+    let capturer = capturer();
+    let golden = Golden::new(&dir).update(false);
+
+    match golden.check_display(&capturer, 0, "screens/main") {
+        Err(GoldenError::Missing { path, actual }) => {
+            assert_eq!(path, dir.join("screens/main.png"));
+            assert!(actual.exists());
+        }
+        result => panic!("Expected a missing golden image, got {:?}", result),
+    }
+
+    golden
+        .clone()
+        .update(true)
+        .check_display(&capturer, 0, "screens/main")
+        .unwrap();
+
+    assert!(dir.join("screens/main.png").exists());
+    assert!(!dir.join("failures/screens/main.actual.png").exists());
+
+    golden.check_display(&capturer, 0, "screens/main").unwrap();
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn mismatch_writes_diff() {
+    let dir = golden_dir("mismatch");
+    let capturer = capturer();
+    let golden = Golden::new(&dir).update(false);
+
+    golden
+        .clone()
+        .update(true)
+        .check_display(&capturer, 0, "main")
+        .unwrap();
+
+    capturer
+        .redactions()
+        .add(Mask::Rect(Rect::new(10, 10, 20, 10)), RedactStyle::Black);
+
+    match golden.check_display(&capturer, 0, "main") {
+        Err(GoldenError::Mismatch {
+            mismatched,
+            total,
+            diff,
+            actual,
+            ..
+        }) => {
+            assert_eq!(total, 160 * 120);
+            assert!(mismatched > 0 && mismatched <= 200);
+
+            let diff = image::open(diff).unwrap().to_rgb8();
+            assert_eq!(*diff.get_pixel(15, 15), Rgb([255, 0, 0]));
+            assert_ne!(*diff.get_pixel(100, 100), Rgb([255, 0, 0]));
+
+            assert_eq!(
+                *image::open(actual).unwrap().to_rgb8().get_pixel(15, 15),
+                Rgb([0, 0, 0])
+            );
+        }
+        result => panic!("Expected a mismatch, got {:?}", result),
+    }
+
+    // The blacked out area is 1% of the display.
+    golden
+        .clone()
+        .max_mismatched(0.011)
+        .check_display(&capturer, 0, "main")
+        .unwrap();
+
+    assert!(!dir.join("failures/main.diff.png").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn tolerances() {
+    let dir = golden_dir("tolerance");
+    let golden = Golden::new(&dir).update(false);
+
+    let image = RgbImage::from_fn(64, 64, |x, y| Rgb([(x * 4) as u8, (y * 4) as u8, 128]));
+    let noisy = RgbImage::from_fn(64, 64, |x, y| {
+        let offset = ((x + y) % 3) as u8;
+        Rgb([(x * 4) as u8 | offset, (y * 4) as u8, 128 + offset])
+    });
+
+    golden
+        .clone()
+        .update(true)
+        .check("gradient", &image)
+        .unwrap();
+
+    assert!(golden.check("gradient", &noisy).is_err());
+    golden
+        .clone()
+        .channel_tolerance(2)
+        .check("gradient", &noisy)
+        .unwrap();
+    golden
+        .clone()
+        .max_mismatched(1.0)
+        .min_ssim(0.95)
+        .check("gradient", &noisy)
+        .unwrap();
+
+    let inverted = RgbImage::from_fn(64, 64, |x, y| {
+        Rgb(image.get_pixel(x, y).0.map(|value| 255 - value))
+    });
+
+    match golden
+        .clone()
+        .max_mismatched(1.0)
+        .min_ssim(0.95)
+        .check("gradient", &inverted)
+    {
+        Err(GoldenError::Mismatch {
+            ssim: Some(ssim), ..
+        }) => assert!(ssim < 0.95),
+        result => panic!("Expected a mismatch, got {:?}", result),
+    }
+
+    match golden.check("gradient", &RgbImage::new(32, 32)) {
+        Err(GoldenError::SizeMismatch {
+            expected, found, ..
+        }) => assert_eq!((expected, found), ((64, 64), (32, 32))),
+        result => panic!("Expected a size mismatch, got {:?}", result),
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn regions() {
+    let dir = golden_dir("region");
+    let capturer = capturer();
+    let golden = Golden::new(&dir).update(false);
+    let region = Rect::new(40, 30, 32, 24);
+
+    golden
+        .clone()
+        .update(true)
+        .check_region(&capturer, 0, region, CoordinateSpace::Physical, "button")
+        .unwrap();
+
+    let expected = image::open(golden.path("button")).unwrap().to_rgb8();
+    assert_eq!(expected.dimensions(), (32, 24));
+
+    golden
+        .check_region(&capturer, 0, region, CoordinateSpace::Physical, "button")
+        .unwrap();
+
+    fs::remove_dir_all(&dir).unwrap();
+}
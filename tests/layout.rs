@@ -0,0 +1,27 @@
+use captis::{Capturer, Display, Layout, MockCapturer};
+
+#[test]
+fn layouts_survive_a_round_trip_into_a_mock() {
+    let layout = Layout {
+        displays: vec![
+            Display::from_pixels(-1920, 0, 1920, 1080),
+            Display::from_pixels(0, 0, 2560, 1440).with_scale_factor(1.5),
+        ],
+        primary: 1,
+    };
+
+    let json = serde_json::to_string(&layout).unwrap();
+    let loaded: Layout = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(loaded, layout);
+
+    let mock = MockCapturer::new(loaded);
+
+    assert_eq!(mock.layout(), layout);
+    assert_eq!(mock.primary_display(), 1);
+    assert_eq!(mock.displays()[1].scale_factor(), 1.5);
+    assert_eq!(mock.capture(0).unwrap().dimensions(), (1920, 1080));
+
+    // What comes back out of the mock can be saved again.
+    assert_eq!(serde_json::to_string(&mock.layout()).unwrap(), json);
+}
@@ -0,0 +1,294 @@
+//! An HTTP client against a preview server sharing a mock capturer.
+
+use captis::{Display, Layout, MockCapturer, PreviewServer, RgbImage};
+use image::Rgb;
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+/// Flat colours, which survive JPEG well enough to tell frames apart.
+fn frame(colour: [u8; 3]) -> RgbImage {
+    RgbImage::from_fn(80, 60, |x, _| {
+        if x < 40 {
+            Rgb(colour)
+        } else {
+            Rgb([255, 255, 255])
+        }
+    })
+}
+
+fn serve(frame_rate: u32) -> (Arc<MockCapturer>, SocketAddr) {
+    let capturer = Arc::new(MockCapturer::new(Layout {
+        displays: vec![
+            Display::from_pixels(0, 0, 32, 32),
+            Display::from_pixels(32, 0, 80, 60),
+        ],
+        primary: 0,
+    }));
+    capturer.set_frame(1, frame([200, 0, 0]));
+
+    let server = PreviewServer::new()
+        .frame_rate(frame_rate)
+        .quality(95)
+        .bind(1, "127.0.0.1:0")
+        .unwrap();
+    let addr = server.local_addrs().unwrap()[0];
+
+    let running = capturer.clone();
+    thread::spawn(move || server.run(&*running));
+
+    (capturer, addr)
+}
+
+fn send(addr: SocketAddr, request: &str) -> BufReader<TcpStream> {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    write!(stream, "{}\r\nHost: localhost\r\n\r\n", request).unwrap();
+
+    BufReader::new(stream)
+}
+
+/// Reads lines up to the empty one ending a head, and returns them.
+fn read_head(reader: &mut BufReader<TcpStream>) -> Vec<String> {
+    let mut lines = vec![];
+
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+
+        match line.trim_end() {
+            "" => return lines,
+            line => lines.push(line.to_owned()),
+        }
+    }
+}
+
+fn header<'a>(head: &'a [String], name: &str) -> Option<&'a str> {
+    head.iter().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+/// Reads a body of the length in `head`.
+fn read_body(reader: &mut BufReader<TcpStream>, head: &[String]) -> Vec<u8> {
+    let length = header(head, "Content-Length").unwrap().parse().unwrap();
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+    body
+}
+
+fn get(addr: SocketAddr, path: &str) -> (String, Vec<String>, Vec<u8>) {
+    let mut reader = send(addr, &format!("GET {} HTTP/1.1", path));
+    let mut head = read_head(&mut reader);
+    let status = head.remove(0);
+    let body = read_body(&mut reader, &head);
+
+    (status, head, body)
+}
+
+/// Checks `image` is `frame(colour)`, give or take what JPEG loses.
+fn assert_close(image: &RgbImage, colour: [u8; 3]) {
+    let expected = frame(colour);
+    assert_eq!(image.dimensions(), expected.dimensions());
+
+    for (x, y, pixel) in image.enumerate_pixels() {
+        for c in 0..3 {
+            let (a, b) = (pixel[c] as i32, expected.get_pixel(x, y)[c] as i32);
+            assert!((a - b).abs() <= 24, "({}, {}) {:?}", x, y, pixel);
+        }
+    }
+}
+
+#[test]
+fn pages_and_errors() {
+    let (_capturer, addr) = serve(20);
+
+    let (status, head, body) = get(addr, "/");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(header(&head, "Content-Type"), Some("text/html"));
+    assert!(String::from_utf8(body).unwrap().contains("/stream.mjpg"));
+
+    let (status, _, _) = get(addr, "/missing");
+    assert_eq!(status, "HTTP/1.1 404 Not Found");
+
+    let mut reader = send(addr, "POST /snapshot.png HTTP/1.1");
+    assert_eq!(read_head(&mut reader)[0], "HTTP/1.1 405 Method Not Allowed");
+}
+
+#[test]
+fn snapshots() {
+    let (_capturer, addr) = serve(20);
+
+    let (status, head, body) = get(addr, "/snapshot.png");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(header(&head, "Content-Type"), Some("image/png"));
+    assert_eq!(
+        image::load_from_memory(&body).unwrap().to_rgb8(),
+        frame([200, 0, 0])
+    );
+
+    let (status, head, body) = get(addr, "/snapshot.jpg?cache=no");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(header(&head, "Content-Type"), Some("image/jpeg"));
+    assert_close(
+        &image::load_from_memory(&body).unwrap().to_rgb8(),
+        [200, 0, 0],
+    );
+}
+
+#[test]
+fn snapshots_are_fresh() {
+    // Slow enough that the frame the stream shows is old by the time the snapshot is asked for.
+    let (capturer, addr) = serve(2);
+
+    let mut stream = send(addr, "GET /stream.mjpg HTTP/1.1");
+    read_head(&mut stream);
+    read_head(&mut stream);
+
+    capturer.set_frame(1, frame([0, 0, 200]));
+
+    let (_, _, body) = get(addr, "/snapshot.png");
+    assert_eq!(
+        image::load_from_memory(&body).unwrap().to_rgb8(),
+        frame([0, 0, 200])
+    );
+}
+
+#[test]
+fn streams() {
+    let (capturer, addr) = serve(20);
+
+    let mut clients: Vec<_> = (0..3)
+        .map(|_| send(addr, "GET /stream.mjpg HTTP/1.1"))
+        .collect();
+
+    for client in &mut clients {
+        let head = read_head(client);
+        assert_eq!(head[0], "HTTP/1.1 200 OK");
+
+        let content_type = header(&head, "Content-Type").unwrap();
+        assert!(content_type.starts_with("multipart/x-mixed-replace; boundary="));
+    }
+
+    // Every part is a JPEG of the latest frame, until the frame changes.
+    let next_part = |client: &mut BufReader<TcpStream>| {
+        let head = read_head(client);
+        assert!(head[0].starts_with("--"));
+        assert_eq!(header(&head, "Content-Type"), Some("image/jpeg"));
+
+        let jpeg = read_body(client, &head);
+        read_head(client);
+
+        image::load_from_memory(&jpeg).unwrap().to_rgb8()
+    };
+
+    for client in &mut clients {
+        assert_close(&next_part(client), [200, 0, 0]);
+    }
+
+    capturer.set_frame(1, frame([0, 160, 0]));
+
+    for client in &mut clients {
+        let latest = loop {
+            let part = next_part(client);
+
+            if part.get_pixel(0, 0)[1] > 100 {
+                break part;
+            }
+        };
+
+        assert_close(&latest, [0, 160, 0]);
+    }
+}
+
+#[test]
+fn streams_end_when_capturing_fails() {
+    let capturer = Arc::new(MockCapturer::new(Layout {
+        displays: vec![Display::from_pixels(0, 0, 80, 60)],
+        primary: 0,
+    }));
+    capturer.set_frame(0, frame([200, 0, 0]));
+
+    let server = PreviewServer::new()
+        .frame_rate(20)
+        .bind(0, "127.0.0.1:0")
+        .unwrap();
+    let addr = server.local_addrs().unwrap()[0];
+
+    let running = capturer.clone();
+    let server = thread::spawn(move || server.run(&*running));
+
+    let mut stream = send(addr, "GET /stream.mjpg HTTP/1.1");
+    read_head(&mut stream);
+    read_head(&mut stream);
+
+    capturer.set_failing(true);
+    assert!(server.join().unwrap().is_err());
+
+    // Whatever was on its way still arrives, then the stream is closed instead of hanging.
+    stream.read_to_end(&mut vec![]).unwrap();
+
+    // The listener goes away soon after.
+    thread::sleep(Duration::from_millis(500));
+    assert!(TcpStream::connect(addr).is_err());
+}
+
+#[test]
+fn clients_beyond_the_limit_are_turned_away() {
+    let capturer = Arc::new(MockCapturer::new(Layout {
+        displays: vec![Display::from_pixels(0, 0, 80, 60)],
+        primary: 0,
+    }));
+    capturer.set_frame(0, frame([200, 0, 0]));
+
+    let server = PreviewServer::new()
+        .frame_rate(20)
+        .max_clients(2)
+        .bind(0, "127.0.0.1:0")
+        .unwrap();
+    let addr = server.local_addrs().unwrap()[0];
+
+    let running = capturer.clone();
+    thread::spawn(move || server.run(&*running));
+
+    let mut clients: Vec<_> = (0..2)
+        .map(|_| send(addr, "GET /stream.mjpg HTTP/1.1"))
+        .collect();
+
+    for client in &mut clients {
+        assert_eq!(read_head(client)[0], "HTTP/1.1 200 OK");
+    }
+
+    let mut turned_away = TcpStream::connect(addr).unwrap();
+    turned_away
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+
+    let mut response = vec![];
+    turned_away.read_to_end(&mut response).unwrap();
+    assert!(response.is_empty());
+
+    // The slot is given back once the server notices the client left.
+    clients.pop();
+
+    let served = (0..50).any(|_| {
+        thread::sleep(Duration::from_millis(100));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        write!(stream, "GET / HTTP/1.1\r\n\r\n").unwrap();
+
+        let mut response = vec![];
+        stream.read_to_end(&mut response).is_ok() && response.starts_with(b"HTTP/1.1 200 OK")
+    });
+    assert!(served);
+}
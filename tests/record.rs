@@ -0,0 +1,170 @@
+//! Recordings into Y4M and AVI files, parsed again to check every frame made it in.
+
+use captis::{Recorder, RgbImage, VideoFormat};
+use image::Rgb;
+use std::{env, fs, path::PathBuf, process, time::Duration};
+
+const WIDTH: u32 = 30;
+const HEIGHT: u32 = 20;
+
+fn path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("captis-record-{}-{}", process::id(), name))
+}
+
+fn frame(step: u8) -> RgbImage {
+    RgbImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        Rgb([x as u8 * 8, y as u8 * 12, step.wrapping_mul(90)])
+    })
+}
+
+/// Three frames at 10 per second, the last one a frame late so the second one is repeated.
+fn record(format: VideoFormat, name: &str) -> Vec<u8> {
+    let path = path(name);
+    let mut recording = Recorder::new(format).frame_rate(10).create(&path).unwrap();
+
+    for (step, millis) in [(0, 0), (1, 100), (2, 300)] {
+        assert!(recording
+            .write_frame(&frame(step), Duration::from_millis(millis))
+            .unwrap());
+    }
+
+    recording.finish().unwrap();
+
+    let data = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    data
+}
+
+fn expected() -> Vec<RgbImage> {
+    vec![frame(0), frame(1), frame(1), frame(2)]
+}
+
+fn u32_le(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// Splits a Y4M stream into its header and its frames, converted back to RGB.
+fn parse_y4m(data: &[u8]) -> (String, Vec<RgbImage>) {
+    let header_end = data.iter().position(|&byte| byte == b'\n').unwrap();
+    let header = String::from_utf8(data[..header_end].to_vec()).unwrap();
+
+    let plane = (WIDTH * HEIGHT) as usize;
+    let frames = data[header_end + 1..]
+        .chunks(6 + plane * 3)
+        .map(|frame| {
+            assert_eq!(&frame[..6], b"FRAME\n");
+            let (y, u, v) = (
+                &frame[6..6 + plane],
+                &frame[6 + plane..6 + plane * 2],
+                &frame[6 + plane * 2..],
+            );
+
+            RgbImage::from_fn(WIDTH, HEIGHT, |x, row| {
+                let i = (row * WIDTH + x) as usize;
+                let (y, u, v) = (
+                    1.164 * (y[i] as f64 - 16.0),
+                    u[i] as f64 - 128.0,
+                    v[i] as f64 - 128.0,
+                );
+
+                Rgb([y + 1.596 * v, y - 0.392 * u - 0.813 * v, y + 2.017 * u]
+                    .map(|value| value.round().clamp(0.0, 255.0) as u8))
+            })
+        })
+        .collect();
+
+    (header, frames)
+}
+
+#[test]
+fn y4m_round_trips() {
+    let (header, frames) = parse_y4m(&record(VideoFormat::Y4m, "round-trip.y4m"));
+
+    assert_eq!(header, "YUV4MPEG2 W30 H20 F10:1 Ip A1:1 C444");
+    assert_eq!(frames.len(), 4);
+
+    for (frame, expected) in frames.iter().zip(expected()) {
+        let difference = frame
+            .as_raw()
+            .iter()
+            .zip(expected.as_raw())
+            .map(|(&a, &b)| (a as i32 - b as i32).abs())
+            .max()
+            .unwrap();
+
+        // Limited range YUV loses a little.
+        assert!(difference <= 3, "{}", difference);
+    }
+}
+
+#[test]
+fn avi_round_trips() {
+    let data = record(VideoFormat::Avi, "round-trip.avi");
+
+    assert_eq!(&data[..4], b"RIFF");
+    assert_eq!(u32_le(&data, 4) as usize, data.len() - 8);
+    assert_eq!(&data[8..12], b"AVI ");
+    // The frame counts of the main and stream headers.
+    assert_eq!(u32_le(&data, 48), 4);
+    assert_eq!(u32_le(&data, 140), 4);
+    assert_eq!(&data[212..216], b"LIST");
+    assert_eq!(&data[220..224], b"movi");
+
+    let index_at = 216 + 4 + u32_le(&data, 216) as usize;
+    assert_eq!(&data[index_at..index_at + 4], b"idx1");
+    assert_eq!(u32_le(&data, index_at + 4), 4 * 16);
+
+    // Rows are bottom-up BGR, padded to four bytes.
+    let stride = (WIDTH as usize * 3 + 3) & !3;
+
+    for (entry, expected) in expected().iter().enumerate() {
+        let entry = index_at + 8 + entry * 16;
+        assert_eq!(&data[entry..entry + 4], b"00db");
+
+        let chunk = 220 + u32_le(&data, entry + 8) as usize;
+        let size = u32_le(&data, entry + 12) as usize;
+        assert_eq!(&data[chunk..chunk + 4], b"00db");
+        assert_eq!(u32_le(&data, chunk + 4) as usize, size);
+        assert_eq!(size, stride * HEIGHT as usize);
+
+        let pixels = &data[chunk + 8..chunk + 8 + size];
+        let frame = RgbImage::from_fn(WIDTH, HEIGHT, |x, y| {
+            let at = (HEIGHT - 1 - y) as usize * stride + x as usize * 3;
+            Rgb([pixels[at + 2], pixels[at + 1], pixels[at]])
+        });
+
+        assert_eq!(&frame, expected);
+    }
+}
+
+#[test]
+fn stops_at_the_size_limit() {
+    let path = path("limit.y4m");
+    let frame_size = 6 + WIDTH as u64 * HEIGHT as u64 * 3;
+
+    let mut recording = Recorder::new(VideoFormat::Y4m)
+        .frame_rate(10)
+        .max_size(frame_size * 2)
+        .create(&path)
+        .unwrap();
+
+    let mut written = 0;
+
+    while recording
+        .write_frame(&frame(written), Duration::from_millis(written as u64 * 100))
+        .unwrap()
+    {
+        written += 1;
+    }
+
+    let controls = recording.controls();
+    recording.finish().unwrap();
+
+    assert!(controls.is_stopped());
+    assert_eq!(written, 2);
+
+    let (_, frames) = parse_y4m(&fs::read(&path).unwrap());
+    assert_eq!(frames.len(), 2);
+
+    fs::remove_file(&path).unwrap();
+}
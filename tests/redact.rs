@@ -0,0 +1,105 @@
+//! Masks hidden in the captures of a mock capturer whose second display isn't at the origin.
+
+use captis::{
+    Capturer, CoordinateSpace, Display, Layout, Mask, MockCapturer, Rect, RedactStyle, RgbImage,
+    Scale, ScaleFilter,
+};
+use image::Rgb;
+
+fn capturer() -> MockCapturer {
+    let capturer = MockCapturer::new(Layout {
+        displays: vec![
+            Display::from_pixels(0, 0, 320, 200),
+            Display::from_pixels(320, 100, 160, 120),
+        ],
+        primary: 0,
+    });
+
+    capturer.set_frame(0, RgbImage::from_pixel(320, 200, Rgb([128, 128, 128])));
+    capturer.set_frame(1, RgbImage::from_pixel(160, 120, Rgb([128, 128, 128])));
+
+    capturer
+}
+
+/// The area of `image` that was painted black, or `None` if nothing was.
+fn black_area(image: &RgbImage) -> Option<Rect> {
+    let black: Vec<_> = image
+        .enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel.0 == [0, 0, 0])
+        .map(|(x, y, _)| (x, y))
+        .collect();
+
+    let left = black.iter().map(|&(x, _)| x).min()?;
+    let top = black.iter().map(|&(_, y)| y).min()?;
+    let right = black.iter().map(|&(x, _)| x).max()? + 1;
+    let bottom = black.iter().map(|&(_, y)| y).max()? + 1;
+
+    assert_eq!(black.len() as u32, (right - left) * (bottom - top));
+
+    Some(Rect::new(
+        left as i32,
+        top as i32,
+        right - left,
+        bottom - top,
+    ))
+}
+
+#[test]
+fn masks_across_displays() {
+    let capturer = capturer();
+
+    // Straddles the edge between both displays.
+    capturer
+        .redactions()
+        .add(Mask::Rect(Rect::new(300, 110, 40, 20)), RedactStyle::Black);
+
+    let images = capturer.capture_all().unwrap();
+
+    assert_eq!(black_area(&images[0]), Some(Rect::new(300, 110, 20, 20)));
+    assert_eq!(black_area(&images[1]), Some(Rect::new(0, 10, 20, 20)));
+}
+
+#[test]
+fn masks_in_regions() {
+    let capturer = capturer();
+
+    capturer
+        .redactions()
+        .add(Mask::Rect(Rect::new(340, 120, 40, 40)), RedactStyle::Black);
+
+    // Cuts through the mask, relative to the display at (320, 100).
+    let region = capturer
+        .capture_region(1, Rect::new(30, 10, 60, 20), CoordinateSpace::Physical)
+        .unwrap();
+    assert_eq!(black_area(&region), Some(Rect::new(0, 10, 30, 10)));
+
+    let (index, area) = capturer
+        .capture_at(Rect::new(370, 150, 30, 30))
+        .unwrap()
+        .unwrap();
+    assert_eq!(index, 1);
+    assert_eq!(black_area(&area), Some(Rect::new(0, 0, 10, 10)));
+
+    let (index, area) = capturer
+        .capture_at(Rect::new(0, 0, 100, 100))
+        .unwrap()
+        .unwrap();
+    assert_eq!(index, 0);
+    assert_eq!(black_area(&area), None);
+}
+
+#[test]
+fn masks_in_scaled_captures() {
+    let capturer = capturer();
+
+    capturer
+        .redactions()
+        .add(Mask::Rect(Rect::new(340, 120, 40, 40)), RedactStyle::Black);
+
+    let scaled = capturer
+        .capture_scaled(1, Scale::Factor(0.5), ScaleFilter::Box)
+        .unwrap();
+
+    assert_eq!(scaled.dimensions(), (80, 60));
+    assert_eq!(black_area(&scaled), Some(Rect::new(10, 10, 20, 20)));
+}
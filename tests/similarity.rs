@@ -0,0 +1,128 @@
+//! The hashes and SSIM on generated images, so the expected values never depend on a screen.
+
+use captis::{
+    average_hash, difference_hash, perceptual_hash, ssim, Capturer, Display, ImageHash, Layout,
+    MockCapturer, RgbImage,
+};
+use image::Rgb;
+
+/// Something like a window: a light background with a dark button in the upper left corner
+/// and rows of dashes standing in for text, on a faint gradient.
+fn scene(width: u32, height: u32) -> RgbImage {
+    RgbImage::from_fn(width, height, |x, y| {
+        if x < width / 4 && y < height / 4 {
+            Rgb([40, 60, 120])
+        } else if y % 16 < 6 && x % 12 < 8 {
+            Rgb([30, 30, 30])
+        } else {
+            let value = 200 + (x * 40 / width) as u8;
+            Rgb([value, value, value - 10])
+        }
+    })
+}
+
+/// Nudges every pixel by a small amount that changes from pixel to pixel, like antialiasing
+/// or dithering would.
+fn with_noise(image: &RgbImage) -> RgbImage {
+    let mut state = 0x2545_f491_u32;
+
+    RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+
+        let offset = (state % 9) as i32 - 4;
+        let pixel = image.get_pixel(x, y);
+
+        Rgb(pixel
+            .0
+            .map(|value| (value as i32 + offset).clamp(0, 255) as u8))
+    })
+}
+
+fn inverted(image: &RgbImage) -> RgbImage {
+    RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        Rgb(image.get_pixel(x, y).0.map(|value| 255 - value))
+    })
+}
+
+#[test]
+fn identical_images_match() {
+    let image = scene(320, 200);
+
+    assert_eq!(average_hash(&image), average_hash(&image.clone()));
+    assert_eq!(difference_hash(&image), difference_hash(&image.clone()));
+    assert_eq!(perceptual_hash(&image), perceptual_hash(&image.clone()));
+    assert!((ssim(&image, &image.clone()) - 1.0).abs() < 1e-9);
+}
+
+#[test]
+fn known_hashes() {
+    let halves = RgbImage::from_fn(64, 64, |x, _| {
+        if x < 32 {
+            Rgb([0, 0, 0])
+        } else {
+            Rgb([255, 255, 255])
+        }
+    });
+
+    assert_eq!(average_hash(&halves), ImageHash(0x0f0f_0f0f_0f0f_0f0f));
+    assert_eq!(average_hash(&halves).to_string(), "0f0f0f0f0f0f0f0f");
+
+    let gradient = RgbImage::from_fn(90, 8, |x, _| Rgb([x as u8 * 2; 3]));
+
+    assert_eq!(difference_hash(&gradient), ImageHash(u64::MAX));
+    assert_eq!(difference_hash(&inverted(&gradient)), ImageHash(0));
+
+    assert_eq!(average_hash(&RgbImage::new(0, 0)), ImageHash(0));
+}
+
+#[test]
+fn noise_is_tolerated() {
+    let image = scene(320, 200);
+    let noisy = with_noise(&image);
+
+    assert_ne!(image, noisy);
+
+    assert!(average_hash(&image).distance(&average_hash(&noisy)) <= 2);
+    assert!(difference_hash(&image).distance(&difference_hash(&noisy)) <= 4);
+    assert!(perceptual_hash(&image).distance(&perceptual_hash(&noisy)) <= 4);
+    assert!(ssim(&image, &noisy) > 0.95);
+}
+
+#[test]
+fn different_images_are_apart() {
+    let image = scene(320, 200);
+    let other = inverted(&image);
+
+    assert!(average_hash(&image).distance(&average_hash(&other)) > 32);
+    assert!(perceptual_hash(&image).distance(&perceptual_hash(&other)) > 16);
+    assert!(ssim(&image, &other) < 0.0);
+}
+
+#[test]
+fn ssim_edge_cases() {
+    assert_eq!(ssim(&scene(64, 64), &scene(64, 32)), 0.0);
+    assert_eq!(ssim(&RgbImage::new(0, 0), &RgbImage::new(0, 0)), 1.0);
+
+    // Larger than `SSIM_SIZE`, and smaller than a window.
+    let large = scene(1920, 1080);
+    assert!(ssim(&large, &with_noise(&large)) > 0.95);
+
+    let tiny = scene(5, 3);
+    assert!((ssim(&tiny, &tiny.clone()) - 1.0).abs() < 1e-9);
+}
+
+#[test]
+fn captures_are_hashable() {
+    let capturer = MockCapturer::new(Layout {
+        displays: vec![Display::from_pixels(0, 0, 640, 480)],
+        primary: 0,
+    });
+
+    let first = capturer.capture(0).unwrap();
+    let second = capturer.capture(0).unwrap();
+
+    assert_eq!(perceptual_hash(&first), perceptual_hash(&second));
+    assert!((ssim(&first, &second) - 1.0).abs() < 1e-9);
+}
@@ -0,0 +1,478 @@
+//! A scripted RFB client against a VNC server sharing a mock capturer.
+
+use captis::{Display, Layout, MockCapturer, Rect, RgbImage, VncServer};
+use flate2::{Decompress, FlushDecompress};
+use image::Rgb;
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+const RAW: i32 = 0;
+const ZRLE: i32 = 16;
+
+/// Noise on the left, a few colours in the middle, and a solid block on the right, so ZRLE
+/// uses raw, palette and solid tiles. 150 pixels wide to end on a partial tile.
+fn scene() -> RgbImage {
+    RgbImage::from_fn(150, 100, |x, y| {
+        if x < 64 {
+            let noise = x.wrapping_mul(2_654_435_761) ^ y.wrapping_mul(40_503);
+            Rgb([noise as u8, (noise >> 8) as u8, (noise >> 16) as u8])
+        } else if x < 128 {
+            [
+                Rgb([255, 0, 0]),
+                Rgb([0, 255, 0]),
+                Rgb([0, 0, 255]),
+                Rgb([255, 255, 255]),
+                Rgb([30, 30, 30]),
+            ][((x / 3 + y / 5) % 5) as usize]
+        } else {
+            Rgb([10, 120, 230])
+        }
+    })
+}
+
+/// Starts a server for the second display, and for the first one on another address.
+fn serve() -> (Arc<MockCapturer>, SocketAddr, SocketAddr) {
+    let capturer = Arc::new(MockCapturer::new(Layout {
+        displays: vec![
+            Display::from_pixels(0, 0, 64, 48),
+            Display::from_pixels(64, 0, 150, 100),
+        ],
+        primary: 0,
+    }));
+    capturer.set_frame(1, scene());
+
+    let server = VncServer::new()
+        .name("kiosk")
+        .frame_rate(50)
+        .bind(1, "127.0.0.1:0")
+        .unwrap()
+        .bind(0, "127.0.0.1:0")
+        .unwrap();
+    let addrs = server.local_addrs().unwrap();
+
+    let running = capturer.clone();
+    thread::spawn(move || server.run(&*running));
+
+    (capturer, addrs[0], addrs[1])
+}
+
+#[derive(Copy, Clone)]
+struct PixelFormat {
+    bits_per_pixel: u8,
+    depth: u8,
+    big_endian: bool,
+    max: [u16; 3],
+    shift: [u8; 3],
+}
+
+impl PixelFormat {
+    const RGB565: PixelFormat = PixelFormat {
+        bits_per_pixel: 16,
+        depth: 16,
+        big_endian: false,
+        max: [31, 63, 31],
+        shift: [11, 5, 0],
+    };
+
+    /// 32-bit big-endian pixels with red in the lowest byte, so ZRLE drops the top one.
+    const XBGR: PixelFormat = PixelFormat {
+        bits_per_pixel: 32,
+        depth: 24,
+        big_endian: true,
+        max: [255, 255, 255],
+        shift: [0, 8, 16],
+    };
+
+    fn pixel(&self, rgb: Rgb<u8>) -> u32 {
+        (0..3)
+            .map(|c| ((rgb[c] as u32 * self.max[c] as u32 + 127) / 255) << self.shift[c])
+            .fold(0, |pixel, channel| pixel | channel)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> u32 {
+        if self.big_endian {
+            bytes
+                .iter()
+                .fold(0, |pixel, &byte| pixel << 8 | byte as u32)
+        } else {
+            bytes
+                .iter()
+                .rev()
+                .fold(0, |pixel, &byte| pixel << 8 | byte as u32)
+        }
+    }
+
+    /// The size of a ZRLE compressed pixel.
+    fn cpixel_size(&self) -> usize {
+        if self.bits_per_pixel == 32 && self.depth <= 24 {
+            3
+        } else {
+            self.bits_per_pixel as usize / 8
+        }
+    }
+}
+
+struct Client {
+    stream: TcpStream,
+    format: PixelFormat,
+    zlib: Decompress,
+    width: u32,
+    height: u32,
+    name: String,
+    /// Every pixel as the server sent it, in `format`.
+    framebuffer: Vec<u32>,
+}
+
+impl Client {
+    fn connect(addr: SocketAddr) -> Client {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let mut client = Client {
+            stream,
+            format: PixelFormat::RGB565,
+            zlib: Decompress::new(true),
+            width: 0,
+            height: 0,
+            name: String::new(),
+            framebuffer: vec![],
+        };
+
+        assert_eq!(&client.read(12), b"RFB 003.008\n");
+        client.write(b"RFB 003.008\n");
+
+        // Only the "None" security type is offered.
+        assert_eq!(client.read(2), [1, 1]);
+        client.write(&[1]);
+        assert_eq!(client.read_u32(), 0);
+
+        // ClientInit, shared.
+        client.write(&[1]);
+
+        let init = client.read(20);
+        client.width = u16::from_be_bytes([init[0], init[1]]) as u32;
+        client.height = u16::from_be_bytes([init[2], init[3]]) as u32;
+        // The default format, 32-bit little-endian true colour.
+        assert_eq!(&init[4..8], [32, 24, 0, 1]);
+
+        let name_length = client.read_u32() as usize;
+        client.name = String::from_utf8(client.read(name_length)).unwrap();
+        client.framebuffer = vec![0; (client.width * client.height) as usize];
+
+        client
+    }
+
+    fn read(&mut self, length: usize) -> Vec<u8> {
+        let mut buffer = vec![0; length];
+        self.stream.read_exact(&mut buffer).unwrap();
+        buffer
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let bytes = self.read(2);
+        u16::from_be_bytes([bytes[0], bytes[1]])
+    }
+
+    fn read_u32(&mut self) -> u32 {
+        let bytes = self.read(4);
+        u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.stream.write_all(bytes).unwrap();
+    }
+
+    fn set_pixel_format(&mut self, format: PixelFormat) {
+        let mut message = vec![0, 0, 0, 0];
+        message.extend_from_slice(&[
+            format.bits_per_pixel,
+            format.depth,
+            format.big_endian as u8,
+            1,
+        ]);
+
+        for max in format.max {
+            message.extend_from_slice(&max.to_be_bytes());
+        }
+
+        message.extend_from_slice(&format.shift);
+        message.extend_from_slice(&[0, 0, 0]);
+
+        self.write(&message);
+        self.format = format;
+    }
+
+    fn set_encodings(&mut self, encodings: &[i32]) {
+        let mut message = vec![2, 0];
+        message.extend_from_slice(&(encodings.len() as u16).to_be_bytes());
+
+        for encoding in encodings {
+            message.extend_from_slice(&encoding.to_be_bytes());
+        }
+
+        self.write(&message);
+    }
+
+    fn request(&mut self, incremental: bool, area: Rect) {
+        let mut message = vec![3, incremental as u8];
+
+        for value in [area.x as u32, area.y as u32, area.width, area.height] {
+            message.extend_from_slice(&(value as u16).to_be_bytes());
+        }
+
+        self.write(&message);
+    }
+
+    /// Reads a FramebufferUpdate into the framebuffer, returning its rectangles and how they
+    /// were encoded.
+    fn read_update(&mut self) -> Vec<(Rect, i32)> {
+        assert_eq!(self.read(2), [0, 0]);
+
+        let count = self.read_u16();
+
+        (0..count)
+            .map(|_| {
+                let (x, y) = (self.read_u16(), self.read_u16());
+                let (width, height) = (self.read_u16(), self.read_u16());
+                let rect = Rect::new(x as i32, y as i32, width as u32, height as u32);
+                let encoding = self.read_u32() as i32;
+
+                match encoding {
+                    RAW => self.read_raw(&rect),
+                    ZRLE => self.read_zrle(&rect),
+                    _ => panic!("Unexpected encoding {}", encoding),
+                }
+
+                (rect, encoding)
+            })
+            .collect()
+    }
+
+    fn put(&mut self, x: u32, y: u32, pixel: u32) {
+        self.framebuffer[(y * self.width + x) as usize] = pixel;
+    }
+
+    fn read_raw(&mut self, rect: &Rect) {
+        let size = self.format.bits_per_pixel as usize / 8;
+        let data = self.read(rect.width as usize * rect.height as usize * size);
+
+        for (i, bytes) in data.chunks_exact(size).enumerate() {
+            let (x, y) = (i as u32 % rect.width, i as u32 / rect.width);
+            let pixel = self.format.decode(bytes);
+            self.put(rect.x as u32 + x, rect.y as u32 + y, pixel);
+        }
+    }
+
+    fn read_zrle(&mut self, rect: &Rect) {
+        let length = self.read_u32() as usize;
+        let compressed = self.read(length);
+
+        // The zlib stream spans the whole connection, every rectangle ends on a sync flush.
+        let mut data = Vec::with_capacity(length * 4 + 1024);
+        let total_in = self.zlib.total_in();
+
+        loop {
+            let consumed = (self.zlib.total_in() - total_in) as usize;
+            data.reserve(data.capacity().max(1024));
+            self.zlib
+                .decompress_vec(&compressed[consumed..], &mut data, FlushDecompress::Sync)
+                .unwrap();
+
+            // Done once everything was read and there was room left for more output.
+            if (self.zlib.total_in() - total_in) as usize == length && data.len() < data.capacity()
+            {
+                break;
+            }
+        }
+
+        let mut data = &data[..];
+        let size = self.format.cpixel_size();
+        let (right, bottom) = (rect.x as u32 + rect.width, rect.y as u32 + rect.height);
+
+        for tile_y in (rect.y as u32..bottom).step_by(64) {
+            for tile_x in (rect.x as u32..right).step_by(64) {
+                let (width, height) = (64.min(right - tile_x), 64.min(bottom - tile_y));
+                let mut take = |count: usize| {
+                    let (taken, rest) = data.split_at(count);
+                    data = rest;
+                    taken
+                };
+
+                let subencoding = take(1)[0];
+                let mut pixels = Vec::with_capacity((width * height) as usize);
+
+                match subencoding {
+                    0 => {
+                        for bytes in take(size * (width * height) as usize).chunks_exact(size) {
+                            pixels.push(self.format.decode(bytes));
+                        }
+                    }
+                    1 => {
+                        let pixel = self.format.decode(take(size));
+                        pixels.resize((width * height) as usize, pixel);
+                    }
+                    2..=16 => {
+                        let palette: Vec<u32> = take(size * subencoding as usize)
+                            .chunks_exact(size)
+                            .map(|bytes| self.format.decode(bytes))
+                            .collect();
+
+                        let bits = match subencoding {
+                            2 => 1,
+                            3..=4 => 2,
+                            _ => 4,
+                        };
+                        let row_bytes = (width as usize * bits + 7) / 8;
+
+                        for _ in 0..height {
+                            let row = take(row_bytes);
+
+                            for x in 0..width as usize {
+                                let bit = x * bits;
+                                let index =
+                                    row[bit / 8] >> (8 - bits - bit % 8) & ((1 << bits) - 1);
+                                pixels.push(palette[index as usize]);
+                            }
+                        }
+                    }
+                    _ => panic!("Unexpected subencoding {}", subencoding),
+                }
+
+                for (i, pixel) in pixels.into_iter().enumerate() {
+                    let (x, y) = (i as u32 % width, i as u32 / width);
+                    self.put(tile_x + x, tile_y + y, pixel);
+                }
+            }
+        }
+
+        assert!(data.is_empty());
+    }
+
+    /// Checks the framebuffer shows `image` within `area`.
+    fn assert_shows(&self, image: &RgbImage, area: Rect) {
+        for y in area.y as u32..area.y as u32 + area.height {
+            for x in area.x as u32..area.x as u32 + area.width {
+                assert_eq!(
+                    self.framebuffer[(y * self.width + x) as usize],
+                    self.format.pixel(*image.get_pixel(x, y)),
+                    "({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn handshake() {
+    let (_capturer, second, first) = serve();
+
+    let client = Client::connect(second);
+    assert_eq!((client.width, client.height), (150, 100));
+    assert_eq!(client.name, "kiosk");
+
+    let client = Client::connect(first);
+    assert_eq!((client.width, client.height), (64, 48));
+}
+
+#[test]
+fn raw_updates() {
+    let (_capturer, addr, _) = serve();
+    let whole = Rect::new(0, 0, 150, 100);
+
+    let mut client = Client::connect(addr);
+    client.set_pixel_format(PixelFormat::RGB565);
+    client.set_encodings(&[RAW]);
+    client.request(false, whole);
+
+    assert_eq!(client.read_update(), [(whole, RAW)]);
+    client.assert_shows(&scene(), whole);
+
+    // Only the requested area is sent.
+    let area = Rect::new(10, 20, 30, 40);
+    client.request(false, area);
+    assert_eq!(client.read_update(), [(area, RAW)]);
+}
+
+#[test]
+fn zrle_updates() {
+    let (capturer, addr, _) = serve();
+    let whole = Rect::new(0, 0, 150, 100);
+
+    let mut client = Client::connect(addr);
+    client.set_pixel_format(PixelFormat::XBGR);
+    client.set_encodings(&[ZRLE, RAW]);
+    client.request(false, whole);
+
+    assert_eq!(client.read_update(), [(whole, ZRLE)]);
+    client.assert_shows(&scene(), whole);
+
+    // Nothing changed, so an incremental request waits for the next change.
+    let mut changed = scene();
+    for (x, y) in (100..108).flat_map(|x| (60..68).map(move |y| (x, y))) {
+        changed.put_pixel(x, y, Rgb([255, 255, 0]));
+    }
+
+    client.request(true, whole);
+    thread::sleep(Duration::from_millis(100));
+    capturer.set_frame(1, changed.clone());
+
+    let rects = client.read_update();
+    assert!(!rects.is_empty());
+
+    // Only the tiles around the change are sent.
+    for (rect, encoding) in &rects {
+        assert_eq!(*encoding, ZRLE);
+        assert!(
+            rect.intersection(&Rect::new(100, 60, 8, 8)).is_some(),
+            "{:?}",
+            rect
+        );
+    }
+
+    let sent: u32 = rects.iter().map(|(rect, _)| rect.width * rect.height).sum();
+    assert!(sent < 150 * 100 / 2);
+
+    client.assert_shows(&changed, whole);
+}
+
+#[test]
+fn viewers_share_the_display() {
+    let (_capturer, addr, _) = serve();
+    let whole = Rect::new(0, 0, 150, 100);
+
+    let mut clients: Vec<_> = (0..3).map(|_| Client::connect(addr)).collect();
+
+    for client in &mut clients {
+        client.set_pixel_format(PixelFormat::XBGR);
+        client.set_encodings(&[RAW]);
+        client.request(false, whole);
+    }
+
+    for client in &mut clients {
+        assert_eq!(client.read_update(), [(whole, RAW)]);
+        client.assert_shows(&scene(), whole);
+    }
+}
+
+#[test]
+fn viewers_are_disconnected_when_capturing_fails() {
+    let (capturer, addr, _) = serve();
+
+    let mut client = Client::connect(addr);
+    client.request(false, Rect::new(0, 0, 150, 100));
+    client.read_update();
+
+    capturer.set_failing(true);
+
+    // An idle viewer is hung up on instead of waiting for frames that never come.
+    client.stream.read_to_end(&mut vec![]).unwrap();
+}
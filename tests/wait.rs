@@ -0,0 +1,211 @@
+//! Waiting for a mock display whose frames are changed from another thread.
+
+use captis::{CoordinateSpace, Display, Layout, MockCapturer, Rect, RgbImage, Waiter};
+use image::Rgb;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+fn capturer() -> Arc<MockCapturer> {
+    let capturer = MockCapturer::new(Layout {
+        displays: vec![Display::from_pixels(0, 0, 64, 48)],
+        primary: 0,
+    });
+
+    capturer.set_frame(0, frame(0, 0));
+
+    Arc::new(capturer)
+}
+
+/// A grey frame with a white pixel at `(x, y)`.
+fn frame(x: u32, y: u32) -> RgbImage {
+    let mut frame = RgbImage::from_pixel(64, 48, Rgb([128, 128, 128]));
+    frame.put_pixel(x, y, Rgb([255, 255, 255]));
+    frame
+}
+
+/// Moves the white pixel one step to the right every `interval`, `steps` times.
+fn animate(capturer: &Arc<MockCapturer>, delay: Duration, interval: Duration, steps: u32) {
+    let capturer = capturer.clone();
+
+    thread::spawn(move || {
+        thread::sleep(delay);
+
+        for step in 1..=steps {
+            capturer.set_frame(0, frame(step, 0));
+            thread::sleep(interval);
+        }
+    });
+}
+
+struct ThreadWaker(thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        match Pin::as_mut(&mut future).poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[test]
+fn waits_for_a_change() {
+    let capturer = capturer();
+    animate(&capturer, Duration::from_millis(100), Duration::ZERO, 1);
+
+    let started = Instant::now();
+    let changed = Waiter::new(&*capturer, 0)
+        .interval(Duration::from_millis(10))
+        .wait_until_changed(Duration::from_secs(5))
+        .unwrap();
+
+    assert_eq!(changed, Some(frame(1, 0)));
+    assert!(started.elapsed() >= Duration::from_millis(100));
+}
+
+#[test]
+fn change_times_out() {
+    let capturer = capturer();
+
+    let started = Instant::now();
+    let changed = Waiter::new(&*capturer, 0)
+        .wait_until_changed(Duration::from_millis(150))
+        .unwrap();
+
+    assert_eq!(changed, None);
+    assert!(started.elapsed() >= Duration::from_millis(150));
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn waits_for_an_animation_to_finish() {
+    let capturer = capturer();
+    animate(&capturer, Duration::ZERO, Duration::from_millis(20), 10);
+
+    let started = Instant::now();
+    let settled = Waiter::new(&*capturer, 0)
+        .interval(Duration::from_millis(10))
+        .wait_until_stable(Duration::from_millis(150), Duration::from_secs(5))
+        .unwrap();
+
+    assert_eq!(settled, Some(frame(10, 0)));
+    assert!(started.elapsed() >= Duration::from_millis(300));
+}
+
+#[test]
+fn stable_times_out() {
+    let capturer = capturer();
+    animate(&capturer, Duration::ZERO, Duration::from_millis(10), 100);
+
+    let settled = Waiter::new(&*capturer, 0)
+        .interval(Duration::from_millis(5))
+        .wait_until_stable(Duration::from_millis(200), Duration::from_millis(300))
+        .unwrap();
+
+    assert_eq!(settled, None);
+}
+
+#[test]
+fn only_the_region_is_watched() {
+    let capturer = capturer();
+    animate(&capturer, Duration::from_millis(20), Duration::ZERO, 1);
+
+    let waiter = Waiter::new(&*capturer, 0)
+        .region(Rect::new(0, 10, 64, 38), CoordinateSpace::Physical)
+        .interval(Duration::from_millis(10));
+
+    assert_eq!(
+        waiter
+            .wait_until_changed(Duration::from_millis(150))
+            .unwrap(),
+        None
+    );
+
+    let moved = capturer.clone();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        moved.set_frame(0, frame(5, 20));
+    });
+
+    let changed = waiter
+        .wait_until_changed(Duration::from_secs(5))
+        .unwrap()
+        .unwrap();
+    assert_eq!(changed.dimensions(), (64, 38));
+    assert_eq!(*changed.get_pixel(5, 10), Rgb([255, 255, 255]));
+}
+
+#[test]
+fn futures() {
+    let capturer = capturer();
+    animate(
+        &capturer,
+        Duration::from_millis(50),
+        Duration::from_millis(20),
+        5,
+    );
+
+    let waiter = Waiter::new(&*capturer, 0).interval(Duration::from_millis(10));
+
+    assert_eq!(
+        block_on(waiter.changed(Duration::from_secs(5))).unwrap(),
+        Some(frame(1, 0))
+    );
+    assert_eq!(
+        block_on(waiter.stable(Duration::from_millis(150), Duration::from_secs(5))).unwrap(),
+        Some(frame(5, 0))
+    );
+}
+
+#[derive(Default)]
+struct CountingWaker(AtomicUsize);
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn futures_only_wake_the_latest_waker() {
+    let capturer = capturer();
+    let waiter = Waiter::new(&*capturer, 0).interval(Duration::from_millis(100));
+    let mut future = Box::pin(waiter.changed(Duration::from_secs(5)));
+
+    let (first, second) = (
+        Arc::new(CountingWaker::default()),
+        Arc::new(CountingWaker::default()),
+    );
+
+    for waker in [&first, &second] {
+        let waker = Waker::from(waker.clone());
+        assert!(future
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending());
+    }
+
+    thread::sleep(Duration::from_millis(300));
+
+    assert_eq!(first.0.load(Ordering::SeqCst), 0);
+    assert_eq!(second.0.load(Ordering::SeqCst), 1);
+}